@@ -0,0 +1,345 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use fs2::FileExt;
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use sysinfo::Users;
+
+use crate::{jcs, system};
+
+/// Log location, relative to the working directory, unless `audit.log` is
+/// configured.
+pub const DEFAULT_LOG: &str = ".osul-audit.log";
+
+/// `prev` of the first record.
+const GENESIS: &str =
+    "0000000000000000000000000000000000000000000000000000000000000000";
+
+pub fn log_path() -> PathBuf {
+    crate::config::get().audit_log()
+}
+
+/// The last record's sequence number and hash are mirrored here so that
+/// `verify` can tell a truncated log from a complete one.
+pub fn head_path(log: &Path) -> PathBuf {
+    let mut name = log.as_os_str().to_os_string();
+    name.push(".head");
+    PathBuf::from(name)
+}
+
+/// Resolves `path` the way `sanitize_path` does for files that may not
+/// exist yet, so it can be compared with a sanitized target.
+pub fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .map(|parent| parent.join(name))
+            .unwrap_or(absolute),
+        _ => absolute,
+    }
+}
+
+pub struct FileState {
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl FileState {
+    /// Size and SHA-256 of the file at `path`, or `None` if there is none.
+    /// A symlink is described by the path it holds, as git does, so that its
+    /// target, possibly outside the sandbox, is never read.
+    pub fn of(path: &Path) -> Result<Option<FileState>> {
+        if let Ok(target) = fs::read_link(path) {
+            let target = target.as_os_str().as_encoded_bytes();
+            return Ok(Some(FileState {
+                size: target.len() as u64,
+                sha256: hex::encode(Sha256::digest(target)),
+            }));
+        }
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Hashing {}", path.display()));
+            }
+        };
+        let mut hasher = Sha256::new();
+        let mut buf = [0u8; 64 * 1024];
+        let mut size = 0;
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            size += n as u64;
+        }
+        Ok(Some(FileState {
+            size,
+            sha256: hex::encode(hasher.finalize()),
+        }))
+    }
+
+    fn to_json(&self) -> JsonValue {
+        json!({ "size": self.size, "sha256": self.sha256 })
+    }
+}
+
+/// A mutation in progress. `begin` captures the target before it is
+/// touched; `commit` captures it afterwards and appends the record. A change
+/// that is dropped without committing (because the operation failed) is not
+/// logged.
+pub struct Change {
+    operation: &'static str,
+    path: PathBuf,
+    detail: Option<String>,
+    before: Option<FileState>,
+}
+
+impl Change {
+    /// Refuses to touch the audit log or its head file through osul.
+    pub fn begin(operation: &'static str, path: &Path) -> Result<Change> {
+        let log = log_path();
+        let target = resolve(path);
+        if target == resolve(&log) || target == resolve(&head_path(&log)) {
+            return Err(crate::Error::Sandbox(format!(
+                "Access denied: '{}' is the audit log",
+                path.display()
+            ))
+            .into());
+        }
+        Ok(Change {
+            operation,
+            path: path.to_path_buf(),
+            detail: None,
+            before: FileState::of(path)?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Size of the target when the change began, `None` if it did not exist.
+    pub fn before_size(&self) -> Option<u64> {
+        self.before.as_ref().map(|state| state.size)
+    }
+
+    /// Extra context for the record, such as the archive entry involved.
+    pub fn detail(mut self, detail: impl Into<String>) -> Change {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Reports the change instead of making it, for `--dry-run`. `after` is
+    /// the size the file would have, or `None` if it would be deleted.
+    /// Nothing is recorded in the log.
+    pub fn plan(self, after: Option<u64>) -> Result<()> {
+        let path = self.path.display();
+        let action = match (&self.before, after) {
+            (None, Some(size)) => format!("create {path} ({size} bytes)"),
+            (Some(before), Some(size)) => {
+                format!("overwrite {path} ({} -> {size} bytes)", before.size)
+            }
+            (Some(before), None) => {
+                format!("delete {path} ({} bytes)", before.size)
+            }
+            (None, None) => format!("delete {path} (missing)"),
+        };
+        match &self.detail {
+            Some(detail) => println!("Dry run: would {action} [{detail}]"),
+            None => println!("Dry run: would {action}"),
+        }
+        Ok(())
+    }
+
+    pub fn commit(self) -> Result<()> {
+        let after = FileState::of(&self.path)?;
+        append(json!({
+            "operation": self.operation,
+            "path": self.path.to_string_lossy(),
+            "detail": self.detail,
+            "before": self.before.as_ref().map(FileState::to_json),
+            "after": after.as_ref().map(FileState::to_json),
+        }))
+    }
+}
+
+fn current_user() -> (u32, Option<String>) {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::geteuid() };
+    let name = Users::new_with_refreshed_list()
+        .iter()
+        .find(|user| **user.id() == uid)
+        .map(|user| user.name().to_string());
+    (uid, name)
+}
+
+/// SHA-256 over the RFC 8785 form of the record without its `hash`.
+fn record_hash(record: &JsonValue) -> Result<String> {
+    let mut unhashed = record.clone();
+    if let Some(object) = unhashed.as_object_mut() {
+        object.remove("hash");
+    }
+    jcs::sha256_hex(&unhashed)
+}
+
+/// (sequence number, hash) of the last record, from the head file or, if
+/// that is missing, from the last line of the log.
+fn last_record(log: &Path) -> Result<Option<(u64, String)>> {
+    let head = match fs::read_to_string(head_path(log)) {
+        Ok(head) => head,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let content = match fs::read_to_string(log) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(e.into()),
+            };
+            match content.lines().rev().find(|line| !line.trim().is_empty()) {
+                Some(line) => line.to_string(),
+                None => return Ok(None),
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let head: JsonValue = serde_json::from_str(&head).with_context(|| {
+        format!("Audit head of {} is corrupt", log.display())
+    })?;
+    match (head["seq"].as_u64(), head["hash"].as_str()) {
+        (Some(seq), Some(hash)) => Ok(Some((seq, hash.to_string()))),
+        _ => Err(anyhow!("Audit head of {} is corrupt", log.display())),
+    }
+}
+
+/// Appends `fields` as the next record. The log is locked for the whole
+/// read-modify-append so that concurrent osul processes keep one chain.
+fn append(fields: JsonValue) -> Result<()> {
+    let log = log_path();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log)
+        .with_context(|| format!("Opening audit log {}", log.display()))?;
+    file.lock_exclusive()
+        .with_context(|| format!("Locking audit log {}", log.display()))?;
+    let (seq, prev) = last_record(&log)?
+        .map_or((1, GENESIS.to_string()), |(seq, hash)| (seq + 1, hash));
+    let (uid, user) = current_user();
+
+    let mut record = fields;
+    record["seq"] = json!(seq);
+    record["timestamp"] = json!(system::utc_timestamp());
+    record["uid"] = json!(uid);
+    record["user"] = json!(user);
+    record["prev"] = json!(prev);
+    let hash = record_hash(&record)?;
+    record["hash"] = json!(hash);
+
+    writeln!(file, "{record}")?;
+    file.sync_data()?;
+    let head = head_path(&log);
+    let staged = head.with_extension("head.tmp");
+    fs::write(
+        &staged,
+        format!("{}\n", json!({ "seq": seq, "hash": hash })),
+    )?;
+    fs::rename(&staged, &head)
+        .with_context(|| format!("Updating {}", head.display()))?;
+    Ok(())
+}
+
+pub struct Verification {
+    pub records: u64,
+    pub problems: Vec<String>,
+    /// Set when the head file is missing, so truncation at the end of the
+    /// log cannot be detected.
+    pub unanchored: bool,
+}
+
+/// Re-checks every record's hash, sequence number and link to its
+/// predecessor, then compares the last record with the head file.
+pub fn verify(log: &Path) -> Result<Verification> {
+    let file = File::open(log)
+        .with_context(|| format!("Opening audit log {}", log.display()))?;
+    let mut problems = Vec::new();
+    let mut expected_prev = GENESIS.to_string();
+    let mut records = 0;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        let record: JsonValue = match serde_json::from_str(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                problems
+                    .push(format!("Line {number}: not a valid record ({e})"));
+                // Resynchronise on the next record's own `prev`.
+                expected_prev.clear();
+                continue;
+            }
+        };
+        records += 1;
+        let seq = record["seq"].as_u64();
+        if seq != Some(records) {
+            problems.push(format!(
+                "Line {number}: sequence number {} where {records} was \
+                 expected (records removed or reordered)",
+                record["seq"]
+            ));
+        }
+        let prev = record["prev"].as_str().unwrap_or_default();
+        if !expected_prev.is_empty() && prev != expected_prev {
+            problems.push(format!(
+                "Line {number}: does not link to the previous record"
+            ));
+        }
+        let hash = record["hash"].as_str().unwrap_or_default();
+        if record_hash(&record)? != hash {
+            problems.push(format!("Line {number}: record has been modified"));
+        }
+        expected_prev = hash.to_string();
+        // Keep counting from the record's own number after a gap, so a
+        // single removal is reported once.
+        if let Some(seq) = seq {
+            records = seq;
+        }
+    }
+
+    let head = match fs::read_to_string(head_path(log)) {
+        Ok(head) => Some(head),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let unanchored = head.is_none();
+    if let Some(head) = head {
+        match serde_json::from_str::<JsonValue>(&head) {
+            Ok(head) => {
+                let seq = head["seq"].as_u64().unwrap_or_default();
+                if seq != records {
+                    problems.push(format!(
+                        "Head records {seq} entries but the log ends at \
+                         {records} (log truncated or head replaced)"
+                    ));
+                } else if head["hash"].as_str() != Some(expected_prev.as_str())
+                {
+                    problems.push(
+                        "Last record does not match the head".to_string(),
+                    );
+                }
+            }
+            Err(_) => problems.push("Head file is corrupt".to_string()),
+        }
+    }
+    Ok(Verification {
+        records,
+        problems,
+        unanchored,
+    })
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+use xmltree::{Element, XMLNode};
+
+use crate::xml_safe::Document;
+
+/// Serializes `document` as Exclusive XML Canonicalization 1.0 without
+/// comments (<https://www.w3.org/TR/xml-exc-c14n/>): no declaration or
+/// document type, namespace declarations only where a prefix is visibly used
+/// and not already in effect, sorted namespaces and attributes, `<a></a>` for
+/// empty elements, CDATA sections as escaped text and fixed escaping rules.
+///
+/// All text inside the document element is significant, whitespace included,
+/// so `document` must come from
+/// [`parse_document_with_whitespace`](crate::xml_safe::parse_document_with_whitespace).
+/// Processing instructions outside the document element are kept, separated
+/// from it by a line feed.
+pub fn canonicalize(document: &Document) -> String {
+    let mut out = String::new();
+    let (before, after) = document.misc.split_at(document.prolog);
+    for node in before {
+        if let XMLNode::ProcessingInstruction(target, data) = node {
+            write_pi(target, data.as_deref(), &mut out);
+            out.push('\n');
+        }
+    }
+    write_element(&document.root, &BTreeMap::new(), &mut out);
+    for node in after {
+        if let XMLNode::ProcessingInstruction(target, data) = node {
+            out.push('\n');
+            write_pi(target, data.as_deref(), &mut out);
+        }
+    }
+    out
+}
+
+pub fn sha256_hex(document: &Document) -> String {
+    hex::encode(Sha256::digest(canonicalize(document).as_bytes()))
+}
+
+fn qualified(prefix: Option<&str>, local: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{prefix}:{local}"),
+        None => local.to_string(),
+    }
+}
+
+fn escape_text(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn write_pi(target: &str, data: Option<&str>, out: &mut String) {
+    out.push_str("<?");
+    out.push_str(target);
+    if let Some(data) = data.filter(|data| !data.is_empty()) {
+        out.push(' ');
+        out.push_str(data);
+    }
+    out.push_str("?>");
+}
+
+fn lookup<'a>(element: &'a Element, prefix: &str) -> &'a str {
+    element
+        .namespaces
+        .as_ref()
+        .and_then(|namespaces| namespaces.get(prefix))
+        .unwrap_or("")
+}
+
+/// `rendered` maps each prefix (`""` for the default namespace) to the URI
+/// an output ancestor declared for it.
+fn write_element(
+    element: &Element,
+    rendered: &BTreeMap<String, String>,
+    out: &mut String,
+) {
+    // Prefixes visibly utilized by the element and its attributes; the
+    // `xml` prefix is bound by definition and never declared.
+    let mut used: Vec<&str> = vec![element.prefix.as_deref().unwrap_or("")];
+    used.extend(
+        element
+            .attributes
+            .keys()
+            .filter_map(|name| name.split_once(':').map(|(prefix, _)| prefix)),
+    );
+    used.retain(|prefix| *prefix != "xml");
+    used.sort_unstable();
+    used.dedup();
+
+    let mut scope = rendered.clone();
+    let mut declarations = Vec::new();
+    for prefix in used {
+        let uri = lookup(element, prefix);
+        let current = rendered.get(prefix).map_or("", String::as_str);
+        if uri != current {
+            declarations.push((prefix, uri));
+            scope.insert(prefix.to_string(), uri.to_string());
+        }
+    }
+
+    let name = qualified(element.prefix.as_deref(), &element.name);
+    out.push('<');
+    out.push_str(&name);
+    // `used` was sorted, so the default namespace comes first.
+    for (prefix, uri) in declarations {
+        out.push_str(if prefix.is_empty() {
+            " xmlns"
+        } else {
+            " xmlns:"
+        });
+        out.push_str(prefix);
+        out.push_str("=\"");
+        escape_attribute(uri, out);
+        out.push('"');
+    }
+
+    // Attributes sort by namespace URI, then local name; unqualified
+    // attributes have no namespace and come first.
+    let mut attributes: Vec<(&str, &str, &String, &String)> = element
+        .attributes
+        .iter()
+        .map(|(name, value)| match name.split_once(':') {
+            Some(("xml", local)) => {
+                ("http://www.w3.org/XML/1998/namespace", local, name, value)
+            }
+            Some((prefix, local)) => {
+                (lookup(element, prefix), local, name, value)
+            }
+            None => ("", name.as_str(), name, value),
+        })
+        .collect();
+    attributes.sort_unstable_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+    for (_, _, name, value) in attributes {
+        out.push(' ');
+        out.push_str(name);
+        out.push_str("=\"");
+        escape_attribute(value, out);
+        out.push('"');
+    }
+    out.push('>');
+
+    for child in &element.children {
+        match child {
+            XMLNode::Element(child) => write_element(child, &scope, out),
+            XMLNode::Text(text) | XMLNode::CData(text) => {
+                escape_text(text, out)
+            }
+            XMLNode::ProcessingInstruction(target, data) => {
+                write_pi(target, data.as_deref(), out)
+            }
+            XMLNode::Comment(_) => {}
+        }
+    }
+    out.push_str("</");
+    out.push_str(&name);
+    out.push('>');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xml_safe::{self, XmlLimits};
+
+    fn c14n(input: &str) -> String {
+        let document = xml_safe::parse_document_with_whitespace(
+            input.as_bytes(),
+            &XmlLimits::default(),
+        )
+        .expect("well-formed input");
+        canonicalize(&document)
+    }
+
+    // The vectors below are the examples of Canonical XML 1.0, section 3
+    // (<https://www.w3.org/TR/xml-c14n/#Examples>), minus the parts that
+    // need a DTD, with the namespace output of the exclusive variant.
+
+    #[test]
+    fn nodes_outside_the_document_element() {
+        let input = "<?xml version=\"1.0\"?>\n\n\
+            <?xml-stylesheet   href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n\n\
+            <doc>Hello, world!<!-- Comment 1 --></doc>\n\n\
+            <?pi-without-data     ?>\n\n\
+            <!-- Comment 2 -->\n\n\
+            <!-- Comment 3 -->\n";
+        assert_eq!(
+            c14n(input),
+            "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n\
+             <doc>Hello, world!</doc>\n\
+             <?pi-without-data?>"
+        );
+    }
+
+    #[test]
+    fn whitespace_in_content_is_kept() {
+        let input = "<doc>\n   <clean>   </clean>\n   <dirty>   A   B   </dirty>\n   <mixed>\n      A\n      <clean>   </clean>\n      B\n      <dirty>   A   B   </dirty>\n      C\n   </mixed>\n</doc>";
+        assert_eq!(c14n(input), input);
+        assert_eq!(
+            c14n("<r><a>x</a> <b/></r>"),
+            "<r><a>x</a> <b></b></r>",
+            "whitespace between siblings is content"
+        );
+    }
+
+    #[test]
+    fn start_and_end_tags() {
+        let input = r#"<doc>
+   <e1   />
+   <e2   ></e2>
+   <e3   name = "elem3"   id="elem3"   />
+   <e4   name="elem4"   id="elem4"   ></e4>
+   <e5 a:attr="out" b:attr="sorted" attr2="all" attr="I'm"
+      xmlns:b="http://www.ietf.org"
+      xmlns:a="http://www.w3.org"
+      xmlns="http://example.org"/>
+   <e6 xmlns="" xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="" xmlns:a="http://www.w3.org">
+            <e9 xmlns="" xmlns:a="http://www.ietf.org"/>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#;
+        let expected = r#"<doc>
+   <e1></e1>
+   <e2></e2>
+   <e3 id="elem3" name="elem3"></e3>
+   <e4 id="elem4" name="elem4"></e4>
+   <e5 xmlns="http://example.org" xmlns:a="http://www.w3.org" xmlns:b="http://www.ietf.org" attr="I'm" attr2="all" b:attr="sorted" a:attr="out"></e5>
+   <e6>
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="">
+            <e9></e9>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#;
+        assert_eq!(c14n(input), expected);
+    }
+
+    #[test]
+    fn character_modifications_and_references() {
+        let input = r#"<doc>
+   <text>First line&#x0d;&#10;Second line</text>
+   <value>&#x32;</value>
+   <compute><![CDATA[value>"0" && value<"10" ?"valid":"error"]]></compute>
+   <compute expr='value>"0" &amp;&amp; value&lt;"10" ?"valid":"error"'>valid</compute>
+   <norm attr=' &apos;   &#x20;&#13;&#xa;&#9;   &apos; '/>
+</doc>"#;
+        let expected = r#"<doc>
+   <text>First line&#xD;
+Second line</text>
+   <value>2</value>
+   <compute>value&gt;"0" &amp;&amp; value&lt;"10" ?"valid":"error"</compute>
+   <compute expr="value>&quot;0&quot; &amp;&amp; value&lt;&quot;10&quot; ?&quot;valid&quot;:&quot;error&quot;">valid</compute>
+   <norm attr=" '    &#xD;&#xA;&#x9;   ' "></norm>
+</doc>"#;
+        assert_eq!(c14n(input), expected);
+    }
+
+    #[test]
+    fn utf8_output() {
+        assert_eq!(c14n("<doc>&#169;</doc>"), "<doc>\u{a9}</doc>");
+    }
+
+    #[test]
+    fn only_visibly_used_namespaces_are_declared() {
+        // Exclusive XML Canonicalization 1.0, section 2.2.
+        let input = r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org"><n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"/></n1:elem2></n0:local>"#;
+        assert_eq!(
+            c14n(input),
+            r#"<n0:local xmlns:n0="foo:bar"><n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"></n3:stuff></n1:elem2></n0:local>"#
+        );
+    }
+
+    #[test]
+    fn digest_covers_whitespace() {
+        let digest = |input: &str| {
+            sha256_hex(
+                &xml_safe::parse_document_with_whitespace(
+                    input.as_bytes(),
+                    &XmlLimits::default(),
+                )
+                .unwrap(),
+            )
+        };
+        assert_eq!(digest("<r><a/></r>"), digest("<r><a></a></r>"));
+        assert_ne!(digest("<r><a/></r>"), digest("<r> <a/></r>"));
+    }
+}
@@ -0,0 +1,746 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+
+use crate::convert::Format;
+use crate::manifest::{self, Algorithm};
+use crate::network::StateFilter;
+use crate::process::SortKey;
+use crate::xpath::Position;
+
+const EXIT_STATUS: &str = "\
+Exit status:
+  0  success
+  1  any other error
+  2  invalid usage
+  3  sandbox violation: a path outside the sandbox root
+  4  limit exceeded: quota, archive expansion ratio, parser limits
+  5  not found
+  6  already exists
+  7  parse error: malformed JSON, XML, YAML, TOML, CSV or archive
+  8  I/O error";
+
+/// Without a subcommand osul starts the interactive menu.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, after_long_help = EXIT_STATUS)]
+pub struct Cli {
+    /// Validate and report what create, write, delete, add and extract
+    /// operations would change without touching the filesystem
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Override a setting for this run, e.g. `--set quota.files=100`; see
+    /// `osul config show` for the keys
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    pub settings: Vec<String>,
+    /// Report errors on stderr as a JSON object with their kind and exit
+    /// status (also when `output.format` is `json`)
+    #[arg(long, global = true)]
+    pub json_errors: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Convert a document between JSON, XML, YAML, TOML and CSV
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// Input format, detected from the extension by default
+        #[arg(long, value_enum)]
+        from: Option<Format>,
+        /// Output format, detected from the extension by default
+        #[arg(long, value_enum)]
+        to: Option<Format>,
+        /// Fail instead of warning when the conversion loses data
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Show disks with usage, flags, mount options and inodes
+    Disks {
+        /// Only disks mounted at or below this path
+        #[arg(long)]
+        mount: Option<PathBuf>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Refresh disk usage periodically and alert on low free space
+    ///
+    /// Filesystems at or above the thresholds are marked WARN or CRIT. With
+    /// --min-free, crossing the limit in either direction prints an alert to
+    /// stderr and, with --alert-log, appends it as a JSON line.
+    Watch {
+        /// Seconds between refreshes
+        #[arg(short, long, default_value_t = 5.0)]
+        interval: f64,
+        /// Used percentage marked as WARN
+        #[arg(long, default_value_t = 80.0)]
+        warn: f64,
+        /// Used percentage marked as CRIT
+        #[arg(long, default_value_t = 90.0)]
+        critical: f64,
+        /// Alert when free space drops below this size (e.g. 500M, 2G)
+        #[arg(long, value_parser = crate::system::parse_size)]
+        min_free: Option<u64>,
+        /// Append alerts as JSON lines to this file
+        #[arg(long)]
+        alert_log: Option<PathBuf>,
+        /// Only disks mounted at or below this path
+        #[arg(long)]
+        mount: Option<PathBuf>,
+        /// Stop after this many refreshes
+        #[arg(short = 'n', long)]
+        count: Option<u64>,
+    },
+    /// Summarize disk usage below a directory, like `du`
+    ///
+    /// Symbolic links are never followed and hard-linked files are counted
+    /// once. Sizes are shown both on disk (allocated blocks) and apparent.
+    Du {
+        #[arg(default_value = ".")]
+        path: PathBuf,
+        /// Deepest directory level to list (totals still cover everything)
+        #[arg(short, long)]
+        depth: Option<usize>,
+        /// Number of largest files and directories to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+        /// Skip entries whose relative path or name matches (repeatable)
+        #[arg(short, long = "exclude", value_name = "GLOB")]
+        exclude: Vec<String>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show memory, swap, CPU, load average, uptime and OS details
+    System {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// List, inspect and signal processes
+    #[command(subcommand, alias = "ps")]
+    Process(ProcessCommand),
+    /// Network interfaces and sockets
+    #[command(subcommand)]
+    Net(NetCommand),
+    /// Audit log of mutating operations
+    ///
+    /// Every create, write, delete, add and extract appends a hash-chained
+    /// record to .osul-audit.log in the working directory, or to the file
+    /// configured as audit.log (OSUL_AUDIT_LOG).
+    #[command(subcommand)]
+    Audit(AuditCommand),
+    /// Limits on the space and files used under the sandbox root
+    ///
+    /// The sandbox root is the working directory unless sandbox.root (or
+    /// OSUL_SANDBOX_ROOT) names another. quota.bytes (a size such as 500M)
+    /// and quota.files, or OSUL_QUOTA_BYTES and OSUL_QUOTA_FILES, set the
+    /// limits; every write is checked against them before it starts and
+    /// stopped if it runs past them. The audit log is not counted.
+    #[command(subcommand)]
+    Quota(QuotaCommand),
+    /// Layered configuration
+    ///
+    /// Settings are read from /etc/osul/config.toml, then
+    /// ~/.config/osul/config.toml, then the nearest .osul.toml in the working
+    /// directory or above, then OSUL_* environment variables, then --set.
+    /// Each layer overrides the ones before it.
+    ///
+    /// A .osul.toml is only read from directories the user owns. It may not
+    /// set editor.*, audit.log, interactive.history_file or
+    /// parse.xml_allow_internal_dtd, may only lower limits, and may only
+    /// put sandbox.root inside its own directory.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Print digests of files, and of every file below directories
+    ///
+    /// Lines read `<digest>  <path>`, as sha256sum prints them. Symlinks
+    /// inside directories are not followed.
+    Hash {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t = Algorithm::Sha256)]
+        algorithm: Algorithm,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Digest manifests for checking that a directory tree arrived intact
+    #[command(subcommand)]
+    Manifest(ManifestCommand),
+    /// Browse the sandbox in a full-screen file manager
+    ///
+    /// A directory tree on the left, a preview of the selection on the right
+    /// (text, pretty JSON, indented XML, archive listings). n creates, w
+    /// writes, d deletes and x extracts from an archive, each after a
+    /// confirmation.
+    Tui,
+    /// Run a file of osul commands, one per line
+    ///
+    /// Lines are osul command lines without the leading `osul`, split like a
+    /// shell would. Blank lines and lines starting with # are skipped.
+    /// `NAME=value` defines a variable, used as $NAME or ${NAME} (falling
+    /// back to the environment; $$ is a literal $, and nothing is expanded
+    /// inside single quotes or after a backslash). `set -e` stops at the
+    /// first failing command and `set -x` echoes commands to stderr; +e and
+    /// +x turn them off again. Interactive commands (the menu, `tui` and
+    /// `xml build`) are rejected before anything runs. A summary is printed
+    /// at the end and the exit status is non-zero if any line failed.
+    Script {
+        file: PathBuf,
+        /// Start as if the script began with `set -e`
+        #[arg(short = 'e', long)]
+        stop_on_error: bool,
+        /// Start as if the script began with `set -x`
+        #[arg(short = 'x', long)]
+        trace: bool,
+        /// Define a variable before the script runs
+        #[arg(long = "var", value_name = "NAME=VALUE")]
+        variables: Vec<String>,
+    },
+    /// File command utilities
+    #[command(subcommand)]
+    File(FileCommand),
+    /// ZIP archive command utilities
+    #[command(subcommand)]
+    Zip(ZipCommand),
+    /// JSON manipulation command utilities
+    #[command(subcommand)]
+    Json(JsonCommand),
+    /// Streaming JSON Lines (NDJSON) command utilities
+    #[command(subcommand)]
+    Jsonl(JsonlCommand),
+    /// XML manipulation command utilities
+    #[command(subcommand)]
+    Xml(XmlCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProcessCommand {
+    /// List processes with PID, user, CPU, memory, start time and command
+    List {
+        /// Sort key; CPU and memory sort highest first
+        #[arg(short, long, value_enum, default_value_t = SortKey::Cpu)]
+        sort: SortKey,
+        /// Reverse the sort order
+        #[arg(short, long)]
+        reverse: bool,
+        /// Only processes whose name or command line contains this
+        #[arg(short, long)]
+        filter: Option<String>,
+        /// Only processes of this user
+        #[arg(short, long)]
+        user: Option<String>,
+        /// Show at most this many processes
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show details, open files and (for your own processes) environment
+    Show {
+        pid: u32,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Send a signal (TERM by default) after confirmation
+    Signal {
+        pid: u32,
+        /// Signal name (TERM, SIGKILL, ...) or number
+        #[arg(short, long, default_value = "TERM")]
+        signal: String,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NetCommand {
+    /// List interfaces with addresses and traffic counters
+    Interfaces {
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// List TCP/UDP sockets with their owning processes
+    ///
+    /// Shows listening and established sockets by default. Owners of
+    /// sockets held by other users are only visible when run as root.
+    Sockets {
+        /// Only TCP sockets
+        #[arg(short, long, conflicts_with = "udp")]
+        tcp: bool,
+        /// Only UDP sockets
+        #[arg(short, long)]
+        udp: bool,
+        /// Only listening sockets
+        #[arg(short, long, conflicts_with = "all")]
+        listening: bool,
+        /// Sockets in every state
+        #[arg(short, long)]
+        all: bool,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditCommand {
+    /// Check the hash chain and detect edited, removed or truncated records
+    Verify {
+        /// Log to check instead of the configured one
+        #[arg(long)]
+        log: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QuotaCommand {
+    /// Show usage against the configured limits
+    Status {
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ManifestCommand {
+    /// Hash every regular file below a directory
+    ///
+    /// Paths are relative to DIR, so a sha256sum-format manifest can also be
+    /// checked with `cd DIR && sha256sum -c MANIFEST` (sha512sum or b3sum
+    /// for the other algorithms). Symlinks are not followed.
+    Create {
+        dir: PathBuf,
+        /// Write the manifest to this file instead of printing it; a file
+        /// inside DIR leaves itself out
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t = Algorithm::Sha256)]
+        algorithm: Algorithm,
+        /// JSON when the output file ends in .json, sha256sum otherwise
+        #[arg(long, value_enum)]
+        format: Option<manifest::Format>,
+    },
+    /// Report files that were modified, are missing or are not listed
+    Verify {
+        manifest: PathBuf,
+        /// Directory the manifest describes, by default the one holding it
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Algorithm of a sha256sum-format manifest; by default SHA-512 for
+        /// 128-digit digests and SHA-256 otherwise
+        #[arg(short, long, value_enum)]
+        algorithm: Option<Algorithm>,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print every effective setting and where its value came from
+    Show {
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum FileCommand {
+    /// Create an empty file; it must not exist yet
+    Create { file: PathBuf },
+    /// Replace the content of a file, creating it if needed
+    Write { file: PathBuf, content: String },
+    /// Print a file
+    Read { file: PathBuf },
+    /// Delete a file
+    Delete { file: PathBuf },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ZipCommand {
+    /// Create an empty archive; it must not exist yet
+    Create { archive: PathBuf },
+    /// Add a file to an existing archive under its file name
+    Add { archive: PathBuf, file: PathBuf },
+    /// Extract one entry into the sandbox, under its name in the archive
+    Extract { archive: PathBuf, entry: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum JsonCommand {
+    /// Pretty-print a JSON file
+    Read {
+        file: PathBuf,
+        /// Print the RFC 8785 canonical form instead
+        #[arg(long)]
+        canonical: bool,
+    },
+    /// Print the SHA-256 digest of the canonical form
+    Digest { file: PathBuf },
+    /// Generate an Ed25519 key pair (`KEY` and `KEY.pub`)
+    Keygen { key: PathBuf },
+    /// Write a detached signature over the canonical form
+    Sign {
+        file: PathBuf,
+        #[arg(short, long)]
+        key: PathBuf,
+        /// Signature path, `FILE.sig` by default
+        #[arg(short, long)]
+        signature: Option<PathBuf>,
+    },
+    /// Check a detached signature against the canonical form
+    Verify {
+        file: PathBuf,
+        #[arg(short, long)]
+        key: PathBuf,
+        /// Signature path, `FILE.sig` by default
+        #[arg(short, long)]
+        signature: Option<PathBuf>,
+    },
+    /// Run a jq-style filter, printing the results or writing them to a file
+    Query {
+        file: PathBuf,
+        filter: String,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum JsonlCommand {
+    /// Count the records
+    Count { file: PathBuf },
+    /// Print the first records
+    Head {
+        file: PathBuf,
+        #[arg(short, default_value_t = 10)]
+        n: usize,
+    },
+    /// Print the last records
+    Tail {
+        file: PathBuf,
+        #[arg(short, default_value_t = 10)]
+        n: usize,
+    },
+    /// Keep records whose value at a JSON pointer exists or equals a value
+    Filter {
+        file: PathBuf,
+        /// JSON pointer such as `/user/name`
+        #[arg(short, long)]
+        pointer: String,
+        /// Expected value as a JSON literal
+        #[arg(short, long)]
+        value: Option<String>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check that every record is valid JSON, reporting bad line numbers
+    Validate { file: PathBuf },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum XmlCommand {
+    /// Create a file with an empty root element
+    New { file: PathBuf },
+    /// Append an element, or text wrapped in `<entry>`, to the root
+    Write { file: PathBuf, content: String },
+    /// Pretty-print an XML file
+    Read {
+        file: PathBuf,
+        /// Print the Exclusive C14N canonical form instead; whitespace is
+        /// significant, use `xml diff` to compare ignoring indentation
+        #[arg(long)]
+        canonical: bool,
+    },
+    /// Print the SHA-256 digest of the canonical form
+    Digest { file: PathBuf },
+    /// Report added, removed and changed elements and attributes by path,
+    /// ignoring whitespace-only text
+    Diff { old: PathBuf, new: PathBuf },
+    /// Build a document interactively, with a preview before writing
+    Build { file: PathBuf },
+    /// Print the elements, text or attribute values matched by an XPath
+    Query { file: PathBuf, xpath: String },
+    /// Replace the text of every matched element
+    SetText {
+        file: PathBuf,
+        xpath: String,
+        text: String,
+    },
+    /// Set an attribute on every matched element
+    SetAttr {
+        file: PathBuf,
+        xpath: String,
+        name: String,
+        value: String,
+    },
+    /// Insert an XML element relative to every matched element
+    Insert {
+        file: PathBuf,
+        xpath: String,
+        element: String,
+        #[arg(short, long, value_enum, default_value_t = Position::Last)]
+        position: Position,
+    },
+    /// Remove every matched element, attribute or text node
+    Remove { file: PathBuf, xpath: String },
+    /// Check a file against an XSD schema
+    ///
+    /// Supports elements, sequence, choice, occurrence bounds, simple types
+    /// with patterns and enumerations, and attributes. Set `OSUL_XML_SCHEMA`
+    /// to a schema to have every XML write checked against it.
+    Validate {
+        file: PathBuf,
+        #[arg(long)]
+        schema: PathBuf,
+    },
+}
+
+pub fn dispatch(command: Command) -> Result<()> {
+    match command {
+        Command::Disks { mount, json } => {
+            crate::cmd_disks(mount.as_deref(), wants_json(json))
+        }
+        Command::System { json } => crate::cmd_system(wants_json(json)),
+        Command::Watch {
+            interval,
+            warn,
+            critical,
+            min_free,
+            alert_log,
+            mount,
+            count,
+        } => crate::disk_watch(crate::watch::Options {
+            interval: Duration::try_from_secs_f64(interval)
+                .map_err(|_| anyhow!("Invalid interval {interval}"))?,
+            warn_percent: warn,
+            critical_percent: critical,
+            min_free,
+            alert_log,
+            mount,
+            count,
+        }),
+        Command::Process(ProcessCommand::List {
+            sort,
+            reverse,
+            filter,
+            user,
+            limit,
+            json,
+        }) => crate::process_list(
+            sort,
+            reverse,
+            filter.as_deref(),
+            user.as_deref(),
+            limit,
+            wants_json(json),
+        ),
+        Command::Process(ProcessCommand::Show { pid, json }) => {
+            crate::process_show(pid, wants_json(json))
+        }
+        Command::Process(ProcessCommand::Signal { pid, signal, yes }) => {
+            crate::process_signal(pid, &signal, yes)
+        }
+        Command::Net(NetCommand::Interfaces { json }) => {
+            crate::net_interfaces(wants_json(json))
+        }
+        Command::Net(NetCommand::Sockets {
+            tcp,
+            udp,
+            listening,
+            all,
+            json,
+        }) => crate::net_sockets(
+            !udp,
+            !tcp,
+            if listening {
+                StateFilter::Listening
+            } else if all {
+                StateFilter::All
+            } else {
+                StateFilter::ListeningOrEstablished
+            },
+            wants_json(json),
+        ),
+        Command::Audit(AuditCommand::Verify { log }) => {
+            crate::audit_verify(log.as_deref())
+        }
+        Command::Quota(QuotaCommand::Status { json }) => {
+            crate::quota_status(wants_json(json))
+        }
+        Command::Config(ConfigCommand::Show { json }) => {
+            crate::config_show(wants_json(json))
+        }
+        Command::Hash {
+            paths,
+            algorithm,
+            json,
+        } => crate::hash_paths(&paths, algorithm, wants_json(json)),
+        Command::Manifest(ManifestCommand::Create {
+            dir,
+            output,
+            algorithm,
+            format,
+        }) => {
+            crate::manifest_create(&dir, output.as_deref(), algorithm, format)
+        }
+        Command::Manifest(ManifestCommand::Verify {
+            manifest,
+            dir,
+            algorithm,
+            json,
+        }) => crate::manifest_verify(
+            &manifest,
+            dir.as_deref(),
+            algorithm,
+            wants_json(json),
+        ),
+        Command::Tui => crate::tui_browse(),
+        Command::Script {
+            file,
+            stop_on_error,
+            trace,
+            variables,
+        } => crate::script_run(&file, &variables, stop_on_error, trace),
+        Command::Du {
+            path,
+            depth,
+            top,
+            exclude,
+            json,
+        } => crate::disk_usage(
+            &path,
+            &crate::du::Options {
+                max_depth: depth,
+                top,
+                exclude,
+            },
+            wants_json(json),
+        ),
+        Command::Jsonl(JsonlCommand::Count { file }) => {
+            crate::jsonl_count(&file)
+        }
+        Command::Jsonl(JsonlCommand::Head { file, n }) => {
+            crate::jsonl_head(&file, n)
+        }
+        Command::Jsonl(JsonlCommand::Tail { file, n }) => {
+            crate::jsonl_tail(&file, n)
+        }
+        Command::Jsonl(JsonlCommand::Filter {
+            file,
+            pointer,
+            value,
+            output,
+        }) => crate::jsonl_filter(
+            &file,
+            &pointer,
+            value.as_deref(),
+            output.as_deref(),
+        ),
+        Command::Jsonl(JsonlCommand::Validate { file }) => {
+            crate::jsonl_validate(&file)
+        }
+        Command::Convert {
+            input,
+            output,
+            from,
+            to,
+            strict,
+        } => crate::convert_file(&input, &output, from, to, strict),
+        Command::File(FileCommand::Create { file }) => {
+            crate::file_create(&file)
+        }
+        Command::File(FileCommand::Write { file, content }) => {
+            crate::file_write(&file, &content)
+        }
+        Command::File(FileCommand::Read { file }) => crate::file_read(&file),
+        Command::File(FileCommand::Delete { file }) => {
+            crate::file_delete(&file)
+        }
+        Command::Zip(ZipCommand::Create { archive }) => {
+            crate::zip_create(&archive)
+        }
+        Command::Zip(ZipCommand::Add { archive, file }) => {
+            crate::zip_add(&archive, &file)
+        }
+        Command::Zip(ZipCommand::Extract { archive, entry }) => {
+            crate::zip_extract(&archive, &entry)
+        }
+        Command::Json(JsonCommand::Read { file, canonical }) => {
+            crate::json_read(&file, canonical)
+        }
+        Command::Json(JsonCommand::Digest { file }) => {
+            crate::json_digest(&file)
+        }
+        Command::Json(JsonCommand::Keygen { key }) => crate::json_keygen(&key),
+        Command::Json(JsonCommand::Sign {
+            file,
+            key,
+            signature,
+        }) => crate::json_sign(&file, &key, signature.as_deref()),
+        Command::Json(JsonCommand::Verify {
+            file,
+            key,
+            signature,
+        }) => crate::json_verify(&file, &key, signature.as_deref()),
+        Command::Json(JsonCommand::Query {
+            file,
+            filter,
+            output,
+        }) => crate::json_query(&file, &filter, output.as_deref()),
+        Command::Xml(XmlCommand::New { file }) => crate::xml_new(&file),
+        Command::Xml(XmlCommand::Write { file, content }) => {
+            crate::xml_write(&file, &content)
+        }
+        Command::Xml(XmlCommand::Read { file, canonical }) => {
+            crate::xml_read(&file, canonical)
+        }
+        Command::Xml(XmlCommand::Digest { file }) => crate::xml_digest(&file),
+        Command::Xml(XmlCommand::Diff { old, new }) => {
+            crate::xml_compare(&old, &new)
+        }
+        Command::Xml(XmlCommand::Build { file }) => {
+            crate::xml_interactive(&file)
+        }
+        Command::Xml(XmlCommand::Query { file, xpath }) => {
+            crate::xml_query(&file, &xpath)
+        }
+        Command::Xml(XmlCommand::SetText { file, xpath, text }) => {
+            crate::xml_set_text(&file, &xpath, &text)
+        }
+        Command::Xml(XmlCommand::SetAttr {
+            file,
+            xpath,
+            name,
+            value,
+        }) => crate::xml_set_attr(&file, &xpath, &name, &value),
+        Command::Xml(XmlCommand::Insert {
+            file,
+            xpath,
+            element,
+            position,
+        }) => crate::xml_insert(&file, &xpath, &element, position),
+        Command::Xml(XmlCommand::Remove { file, xpath }) => {
+            crate::xml_remove(&file, &xpath)
+        }
+        Command::Xml(XmlCommand::Validate { file, schema }) => {
+            crate::xml_validate(&file, &schema)
+        }
+    }
+}
+
+/// `--json`, or `output.format = "json"` in the configuration.
+fn wants_json(flag: bool) -> bool {
+    flag || crate::config::get().json_output()
+}
@@ -0,0 +1,633 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value as JsonValue;
+use toml::Value;
+
+use crate::xml_safe::XmlLimits;
+use crate::{audit, editor, json_query, ndjson, system};
+
+/// Read before the user and project files, which override it.
+pub const SYSTEM_FILE: &str = "/etc/osul/config.toml";
+
+/// Looked up in the working directory and then its ancestors, so that a
+/// project can pin its own sandbox and limits. The search stops at the first
+/// directory not owned by the user running osul.
+pub const PROJECT_FILE: &str = ".osul.toml";
+
+/// Settings a project file may not set: a checked-out tree is not trusted
+/// to pick programs to run, files to append to or to relax XML hardening.
+const PROJECT_DENIED: &[&str] = &[
+    "editor.command",
+    "editor.allowlist",
+    "audit.log",
+    "interactive.history_file",
+    "parse.xml_allow_internal_dtd",
+];
+
+/// Limits a project file may lower but not raise.
+const PROJECT_LIMITS: &[&str] = &[
+    "archive.max_expansion_percent",
+    "parse.xml_max_bytes",
+    "parse.xml_max_depth",
+    "parse.xml_max_attributes",
+    "parse.xml_max_entity_expansion",
+    "parse.xml_max_entity_depth",
+    "parse.jsonl_max_line_bytes",
+    "query.max_array_padding",
+    "quota.bytes",
+    "quota.files",
+];
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Loads the configuration once, with `overrides` (`KEY=VALUE` from
+/// `--set`) as the last layer.
+pub fn init(overrides: &[String]) -> Result<()> {
+    let config = Config::load(overrides)?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+/// The effective configuration; the built-in defaults if `init` was never
+/// called.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::defaults)
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Path,
+    Text,
+    Count,
+    Size,
+    Bool,
+    List,
+    Format,
+}
+
+impl Kind {
+    fn describe(self) -> &'static str {
+        match self {
+            Kind::Path => "a path",
+            Kind::Text => "a string",
+            Kind::Count => "a non-negative integer",
+            Kind::Size => "a size such as 500M",
+            Kind::Bool => "true or false",
+            Kind::List => "a list of strings",
+            Kind::Format => "\"text\" or \"json\"",
+        }
+    }
+
+    /// Values from the environment and `--set`. Lists are comma separated.
+    fn parse(self, raw: &str) -> Result<Value> {
+        let raw = raw.trim();
+        Ok(match self {
+            Kind::Path | Kind::Text => Value::String(raw.to_string()),
+            Kind::Count => integer(raw.parse::<u64>().map_err(|_| {
+                anyhow!("expected {}, got '{raw}'", self.describe())
+            })?)?,
+            Kind::Size => integer(system::parse_size(raw)?)?,
+            Kind::Bool => match raw.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Value::Boolean(true),
+                "false" | "no" | "off" | "0" => Value::Boolean(false),
+                _ => bail!("expected {}, got '{raw}'", self.describe()),
+            },
+            Kind::List => Value::Array(
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect(),
+            ),
+            Kind::Format => match raw {
+                "text" | "json" => Value::String(raw.to_string()),
+                _ => bail!("expected {}, got '{raw}'", self.describe()),
+            },
+        })
+    }
+
+    /// Values from a config file. Relative paths are taken relative to the
+    /// directory holding the file.
+    fn parse_toml(self, value: Value, base: &Path) -> Result<Value> {
+        match (self, value) {
+            (Kind::Path, Value::String(path)) => {
+                // Collecting the components drops `.` segments.
+                let path: PathBuf = base.join(path).components().collect();
+                Ok(Value::String(path.to_string_lossy().into()))
+            }
+            (_, Value::String(raw)) => self.parse(&raw),
+            (Kind::Count | Kind::Size, Value::Integer(n)) if n >= 0 => {
+                Ok(Value::Integer(n))
+            }
+            (Kind::Bool, value @ Value::Boolean(_)) => Ok(value),
+            (Kind::List, Value::Array(items)) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::String(_) => Ok(item),
+                    _ => Err(anyhow!("expected {}", self.describe())),
+                })
+                .collect::<Result<_>>()
+                .map(Value::Array),
+            (_, value) => Err(anyhow!(
+                "expected {}, got {}",
+                self.describe(),
+                value.type_str()
+            )),
+        }
+    }
+}
+
+fn integer(n: u64) -> Result<Value> {
+    i64::try_from(n)
+        .map(Value::Integer)
+        .map_err(|_| anyhow!("{n} is too large"))
+}
+
+/// Where an effective value came from.
+#[derive(Clone)]
+pub enum Origin {
+    Default,
+    /// `layer` is `system`, `user` or `project`.
+    File {
+        layer: &'static str,
+        path: PathBuf,
+    },
+    Env(&'static str),
+    Flag,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File { layer, path } => {
+                write!(f, "{layer} file {}", path.display())
+            }
+            Origin::Env(var) => write!(f, "environment {var}"),
+            Origin::Flag => write!(f, "--set"),
+        }
+    }
+}
+
+pub struct Entry {
+    pub key: &'static str,
+    kind: Kind,
+    /// Checked in order; the first one set and non-empty wins.
+    env: &'static [&'static str],
+    pub value: Option<Value>,
+    pub origin: Origin,
+}
+
+impl Entry {
+    fn new(
+        key: &'static str,
+        kind: Kind,
+        env: &'static [&'static str],
+        default: Option<Value>,
+    ) -> Entry {
+        Entry {
+            key,
+            kind,
+            env,
+            value: default,
+            origin: Origin::Default,
+        }
+    }
+}
+
+/// Every setting with its layered value, in the order `config show` lists
+/// them.
+pub struct Config {
+    entries: Vec<Entry>,
+}
+
+impl Config {
+    fn defaults() -> Config {
+        let xml = XmlLimits::default();
+        let count = |n: usize| Some(Value::Integer(n as i64));
+        let text = |s: &str| Some(Value::String(s.to_string()));
+        let entries = vec![
+            Entry::new(
+                "sandbox.root",
+                Kind::Path,
+                &["OSUL_SANDBOX_ROOT"],
+                None,
+            ),
+            Entry::new(
+                "archive.max_expansion_percent",
+                Kind::Count,
+                &["OSUL_ARCHIVE_MAX_EXPANSION_PERCENT"],
+                count(10_000),
+            ),
+            Entry::new(
+                "parse.xml_max_bytes",
+                Kind::Size,
+                &["OSUL_PARSE_XML_MAX_BYTES"],
+                count(xml.max_bytes),
+            ),
+            Entry::new(
+                "parse.xml_max_depth",
+                Kind::Count,
+                &["OSUL_PARSE_XML_MAX_DEPTH"],
+                count(xml.max_depth),
+            ),
+            Entry::new(
+                "parse.xml_max_attributes",
+                Kind::Count,
+                &["OSUL_PARSE_XML_MAX_ATTRIBUTES"],
+                count(xml.max_attributes),
+            ),
+            Entry::new(
+                "parse.xml_max_entity_expansion",
+                Kind::Count,
+                &["OSUL_PARSE_XML_MAX_ENTITY_EXPANSION"],
+                count(xml.max_entity_expansion),
+            ),
+            Entry::new(
+                "parse.xml_max_entity_depth",
+                Kind::Count,
+                &["OSUL_PARSE_XML_MAX_ENTITY_DEPTH"],
+                count(usize::from(xml.max_entity_depth)),
+            ),
+            Entry::new(
+                "parse.xml_allow_internal_dtd",
+                Kind::Bool,
+                &["OSUL_PARSE_XML_ALLOW_INTERNAL_DTD"],
+                Some(Value::Boolean(xml.allow_internal_dtd)),
+            ),
+            Entry::new(
+                "parse.jsonl_max_line_bytes",
+                Kind::Size,
+                &["OSUL_PARSE_JSONL_MAX_LINE_BYTES"],
+                Some(Value::Integer(ndjson::MAX_LINE_BYTES as i64)),
+            ),
+            Entry::new(
+                "query.max_array_padding",
+                Kind::Count,
+                &["OSUL_QUERY_MAX_ARRAY_PADDING"],
+                count(json_query::MAX_ARRAY_PADDING),
+            ),
+            Entry::new("xml.schema", Kind::Path, &["OSUL_XML_SCHEMA"], None),
+            Entry::new(
+                "editor.command",
+                Kind::Text,
+                &["VISUAL", "EDITOR"],
+                text(editor::DEFAULT_EDITOR),
+            ),
+            Entry::new(
+                "editor.allowlist",
+                Kind::List,
+                &["OSUL_EDITOR_ALLOWLIST"],
+                Some(Value::Array(
+                    editor::DEFAULT_ALLOWLIST
+                        .iter()
+                        .map(|name| Value::String(name.to_string()))
+                        .collect(),
+                )),
+            ),
+            Entry::new(
+                "output.format",
+                Kind::Format,
+                &["OSUL_OUTPUT_FORMAT"],
+                text("text"),
+            ),
+            Entry::new(
+                "audit.log",
+                Kind::Path,
+                &["OSUL_AUDIT_LOG"],
+                text(audit::DEFAULT_LOG),
+            ),
+            Entry::new(
+                "interactive.history_file",
+                Kind::Path,
+                &["OSUL_HISTORY_FILE"],
+                None,
+            ),
+            Entry::new(
+                "interactive.history_size",
+                Kind::Count,
+                &["OSUL_HISTORY_SIZE"],
+                count(1000),
+            ),
+            Entry::new("quota.bytes", Kind::Size, &["OSUL_QUOTA_BYTES"], None),
+            Entry::new("quota.files", Kind::Count, &["OSUL_QUOTA_FILES"], None),
+        ];
+        Config { entries }
+    }
+
+    fn load(overrides: &[String]) -> Result<Config> {
+        let mut config = Config::defaults();
+        for (layer, path) in files() {
+            if path.is_file() {
+                config.apply_file(layer, &path)?;
+            }
+        }
+        for entry in &mut config.entries {
+            let set = entry.env.iter().find_map(|var| {
+                std::env::var(var)
+                    .ok()
+                    .filter(|value| !value.trim().is_empty())
+                    .map(|value| (*var, value))
+            });
+            if let Some((var, raw)) = set {
+                entry.value = Some(
+                    entry
+                        .kind
+                        .parse(&raw)
+                        .with_context(|| format!("Invalid {var}"))?,
+                );
+                entry.origin = Origin::Env(var);
+            }
+        }
+        for assignment in overrides {
+            let (key, raw) = assignment.split_once('=').ok_or_else(|| {
+                anyhow!("Expected KEY=VALUE for --set, got '{assignment}'")
+            })?;
+            let entry = config.entry_mut(key.trim())?;
+            entry.value = Some(
+                entry
+                    .kind
+                    .parse(raw)
+                    .with_context(|| format!("Invalid --set {assignment}"))?,
+            );
+            entry.origin = Origin::Flag;
+        }
+        Ok(config)
+    }
+
+    /// Files use one table per section, e.g. `[archive]` holding
+    /// `max_expansion_percent`. Unknown keys are errors so that typos do
+    /// not silently leave a limit at its default.
+    fn apply_file(&mut self, layer: &'static str, path: &Path) -> Result<()> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Reading {}", path.display()))?;
+        let table: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("Parsing {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        for (section, values) in table {
+            let Value::Table(values) = values else {
+                bail!(
+                    "{}: '{section}' must be a [section] table",
+                    path.display()
+                );
+            };
+            for (name, value) in values {
+                let key = format!("{section}.{name}");
+                let entry = self
+                    .entry_mut(&key)
+                    .with_context(|| format!("In {}", path.display()))?;
+                let value =
+                    entry.kind.parse_toml(value, base).with_context(|| {
+                        format!("{}: invalid {key}", path.display())
+                    })?;
+                if layer == "project" {
+                    check_project(&key, entry.value.as_ref(), &value, base)
+                        .with_context(|| format!("In {}", path.display()))?;
+                }
+                entry.value = Some(value);
+                entry.origin = Origin::File {
+                    layer,
+                    path: path.to_path_buf(),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_mut(&mut self, key: &str) -> Result<&mut Entry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.key == key)
+            .ok_or_else(|| {
+                anyhow!("Unknown setting '{key}' (see `osul config show`)")
+            })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    fn value(&self, key: &str) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .and_then(|entry| entry.value.as_ref())
+    }
+
+    fn count(&self, key: &str) -> Option<u64> {
+        self.value(key)
+            .and_then(Value::as_integer)
+            .and_then(|n| u64::try_from(n).ok())
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.value(key)
+            .and_then(Value::as_str)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    }
+
+    /// `None` confines osul to the working directory.
+    pub fn sandbox_root(&self) -> Option<PathBuf> {
+        self.path("sandbox.root")
+    }
+
+    pub fn max_expansion_percent(&self) -> u64 {
+        self.count("archive.max_expansion_percent").unwrap_or(0)
+    }
+
+    pub fn xml_limits(&self) -> XmlLimits {
+        let defaults = XmlLimits::default();
+        let size = |key, default: usize| {
+            self.count(key)
+                .map_or(default, |n| usize::try_from(n).unwrap_or(usize::MAX))
+        };
+        XmlLimits {
+            max_bytes: size("parse.xml_max_bytes", defaults.max_bytes),
+            max_depth: size("parse.xml_max_depth", defaults.max_depth),
+            max_attributes: size(
+                "parse.xml_max_attributes",
+                defaults.max_attributes,
+            ),
+            max_entity_expansion: size(
+                "parse.xml_max_entity_expansion",
+                defaults.max_entity_expansion,
+            ),
+            max_entity_depth: self
+                .count("parse.xml_max_entity_depth")
+                .map_or(defaults.max_entity_depth, |n| {
+                    u8::try_from(n).unwrap_or(u8::MAX)
+                }),
+            allow_internal_dtd: self
+                .value("parse.xml_allow_internal_dtd")
+                .and_then(Value::as_bool)
+                .unwrap_or(defaults.allow_internal_dtd),
+        }
+    }
+
+    pub fn jsonl_max_line_bytes(&self) -> u64 {
+        self.count("parse.jsonl_max_line_bytes")
+            .unwrap_or(ndjson::MAX_LINE_BYTES)
+    }
+
+    pub fn max_array_padding(&self) -> usize {
+        self.count("query.max_array_padding")
+            .map_or(json_query::MAX_ARRAY_PADDING, |n| {
+                usize::try_from(n).unwrap_or(usize::MAX)
+            })
+    }
+
+    /// Schema every XML write is validated against, if any.
+    pub fn xml_schema(&self) -> Option<PathBuf> {
+        self.path("xml.schema")
+    }
+
+    pub fn editor_command(&self) -> String {
+        self.value("editor.command")
+            .and_then(Value::as_str)
+            .unwrap_or(editor::DEFAULT_EDITOR)
+            .to_string()
+    }
+
+    pub fn editor_allowlist(&self) -> Vec<String> {
+        self.value("editor.allowlist")
+            .and_then(Value::as_array)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether commands with a `--json` switch print JSON without it.
+    pub fn json_output(&self) -> bool {
+        self.value("output.format").and_then(Value::as_str) == Some("json")
+    }
+
+    pub fn audit_log(&self) -> PathBuf {
+        self.path("audit.log")
+            .unwrap_or_else(|| PathBuf::from(audit::DEFAULT_LOG))
+    }
+
+    /// Where the interactive prompts keep their history; see
+    /// `prompt::default_history_file` for the default.
+    pub fn history_file(&self) -> Option<PathBuf> {
+        self.path("interactive.history_file")
+            .or_else(crate::prompt::default_history_file)
+    }
+
+    /// Entries kept in the history; 0 turns it off.
+    pub fn history_size(&self) -> usize {
+        self.count("interactive.history_size")
+            .map_or(0, |n| usize::try_from(n).unwrap_or(usize::MAX))
+    }
+
+    pub fn quota_bytes(&self) -> Option<u64> {
+        self.count("quota.bytes")
+    }
+
+    pub fn quota_files(&self) -> Option<u64> {
+        self.count("quota.files")
+    }
+}
+
+/// Keeps a project file to the settings it may change; see
+/// [`PROJECT_DENIED`] and [`PROJECT_LIMITS`]. `current` is the value from
+/// the system and user layers.
+fn check_project(
+    key: &str,
+    current: Option<&Value>,
+    value: &Value,
+    base: &Path,
+) -> Result<()> {
+    if PROJECT_DENIED.contains(&key) {
+        bail!("{key} can only be set in the system or user config");
+    }
+    if PROJECT_LIMITS.contains(&key)
+        && let (Some(current), Some(new)) =
+            (current.and_then(Value::as_integer), value.as_integer())
+        && new > current
+    {
+        bail!("{key} = {new} would raise the limit of {current}");
+    }
+    if key == "sandbox.root" {
+        let dir = base
+            .canonicalize()
+            .with_context(|| format!("Resolving {}", base.display()))?;
+        let root = Path::new(value.as_str().unwrap_or_default());
+        let resolved = root
+            .canonicalize()
+            .with_context(|| format!("Resolving {}", root.display()))?;
+        if !resolved.starts_with(&dir) {
+            return Err(crate::Error::Sandbox(format!(
+                "sandbox.root {} is outside {}",
+                root.display(),
+                dir.display()
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+fn owned_by_user(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let euid = unsafe { libc::geteuid() };
+    fs::metadata(path).is_ok_and(|metadata| metadata.uid() == euid)
+}
+
+/// The nearest project file in `cwd` or above that the user owns, along
+/// with every directory on the way to it.
+fn project_file(cwd: &Path) -> Option<PathBuf> {
+    for dir in cwd.ancestors() {
+        if !owned_by_user(dir) {
+            return None;
+        }
+        let path = dir.join(PROJECT_FILE);
+        if path.is_file() {
+            return owned_by_user(&path).then_some(path);
+        }
+    }
+    None
+}
+
+/// The config files in the order they are applied.
+pub fn files() -> Vec<(&'static str, PathBuf)> {
+    let mut files = vec![("system", PathBuf::from(SYSTEM_FILE))];
+    let user_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".config"))
+        });
+    if let Some(dir) = user_dir {
+        files.push(("user", dir.join("osul").join("config.toml")));
+    }
+    if let Ok(cwd) = std::env::current_dir() {
+        // Where one would go when none is found, so `config show` can say
+        // so; an untrusted file there is left out altogether.
+        let fallback = cwd.join(PROJECT_FILE);
+        match project_file(&cwd) {
+            Some(project) => files.push(("project", project)),
+            None if !fallback.exists() => files.push(("project", fallback)),
+            None => {}
+        }
+    }
+    files
+}
+
+pub fn value_json(value: Option<&Value>) -> JsonValue {
+    value
+        .and_then(|value| serde_json::to_value(value).ok())
+        .unwrap_or(JsonValue::Null)
+}
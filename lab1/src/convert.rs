@@ -0,0 +1,685 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{Map, Number, Value as JsonValue};
+use xmltree::{Element, XMLNode};
+
+/// Attribute keys are prefixed with `@` and character data is stored under
+/// `#text` when an element also has attributes or child elements.
+const ATTRIBUTE_PREFIX: char = '@';
+const TEXT_KEY: &str = "#text";
+const DEFAULT_XML_ROOT: &str = "root";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Json,
+    Xml,
+    Yaml,
+    Toml,
+    Csv,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => Some(Format::Json),
+            "xml" => Some(Format::Xml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Json => "JSON",
+            Format::Xml => "XML",
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+            Format::Csv => "CSV",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Collects every place where information could not be carried over,
+/// grouped by the kind of loss.
+#[derive(Debug, Default)]
+pub struct LossReport {
+    entries: BTreeMap<String, Vec<String>>,
+}
+
+impl LossReport {
+    fn note(&mut self, path: &str, what: impl Into<String>) {
+        self.entries
+            .entry(what.into())
+            .or_default()
+            .push(path.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        const SHOWN_PATHS: usize = 3;
+        self.entries
+            .iter()
+            .map(|(what, paths)| {
+                let mut shown = paths
+                    .iter()
+                    .take(SHOWN_PATHS)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if paths.len() > SHOWN_PATHS {
+                    shown.push_str(&format!(
+                        " and {} more",
+                        paths.len() - SHOWN_PATHS
+                    ));
+                }
+                format!("{what} at {shown}")
+            })
+            .collect()
+    }
+}
+
+pub struct Conversion {
+    pub output: String,
+    pub losses: LossReport,
+}
+
+pub fn convert(input: &str, from: Format, to: Format) -> Result<Conversion> {
+    let mut losses = LossReport::default();
+    let value = decode(input, from, &mut losses)
+        .with_context(|| format!("Reading input as {from}"))?;
+    let output = encode(&value, to, &mut losses)
+        .with_context(|| format!("Writing output as {to}"))?;
+    Ok(Conversion { output, losses })
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    format!("{path}.{key}")
+}
+
+fn item_path(path: &str, index: usize) -> String {
+    format!("{path}[{index}]")
+}
+
+fn decode(
+    input: &str,
+    from: Format,
+    losses: &mut LossReport,
+) -> Result<JsonValue> {
+    match from {
+        Format::Json => Ok(serde_json::from_str(input)?),
+        Format::Xml => {
+            let document = crate::xml_safe::parse_document(
+                input.as_bytes(),
+                &crate::config::get().xml_limits(),
+            )?;
+            for node in &document.misc {
+                match node {
+                    XMLNode::ProcessingInstruction(..) => losses.note(
+                        "$",
+                        "processing instruction outside the root dropped",
+                    ),
+                    _ => losses.note("$", "comment outside the root dropped"),
+                }
+            }
+            let root = document.root;
+            let mut map = Map::new();
+            map.insert(root.name.clone(), xml_to_json(&root, "$", losses));
+            Ok(JsonValue::Object(map))
+        }
+        Format::Yaml => {
+            let value: serde_yaml::Value = serde_yaml::from_str(input)?;
+            Ok(yaml_to_json(value, "$", losses))
+        }
+        Format::Toml => {
+            let value: toml::Table = toml::from_str(input)?;
+            Ok(toml_to_json(toml::Value::Table(value), "$", losses))
+        }
+        Format::Csv => csv_to_json(input),
+    }
+}
+
+fn encode(
+    value: &JsonValue,
+    to: Format,
+    losses: &mut LossReport,
+) -> Result<String> {
+    match to {
+        Format::Json => Ok(serde_json::to_string_pretty(value)?),
+        Format::Xml => json_to_xml(value, losses),
+        Format::Yaml => Ok(serde_yaml::to_string(value)?),
+        Format::Toml => {
+            let JsonValue::Object(map) = value else {
+                bail!("TOML documents must be a table at the top level");
+            };
+            let table = json_to_toml_table(map, "$", losses)?;
+            Ok(toml::to_string_pretty(&table)?)
+        }
+        Format::Csv => json_to_csv(value, losses),
+    }
+}
+
+fn qualified_name(element: &Element) -> String {
+    match &element.prefix {
+        Some(prefix) => format!("{prefix}:{}", element.name),
+        None => element.name.clone(),
+    }
+}
+
+fn xml_to_json(
+    element: &Element,
+    path: &str,
+    losses: &mut LossReport,
+) -> JsonValue {
+    let mut map = Map::new();
+    if element.namespace.is_some() {
+        losses.note(path, "namespace URI dropped");
+    }
+    let mut attributes: Vec<_> = element.attributes.iter().collect();
+    attributes.sort();
+    let mut prefixes: Vec<&str> = Vec::new();
+    for (name, _) in &attributes {
+        if let Some((prefix, _)) = name.split_once(':')
+            && prefix != "xml"
+            && !prefixes.contains(&prefix)
+        {
+            prefixes.push(prefix);
+            losses.note(
+                path,
+                format!(
+                    "namespace binding of attribute prefix '{prefix}' dropped"
+                ),
+            );
+        }
+    }
+    for (name, value) in attributes {
+        map.insert(
+            format!("{ATTRIBUTE_PREFIX}{name}"),
+            JsonValue::String(value.clone()),
+        );
+    }
+
+    let mut text = String::new();
+    let mut groups: Vec<(String, Vec<JsonValue>)> = Vec::new();
+    let mut previous: Option<String> = None;
+    let mut interleaved: Vec<String> = Vec::new();
+    for node in &element.children {
+        match node {
+            XMLNode::Element(child) => {
+                let name = qualified_name(child);
+                let child_path = child_path(path, &name);
+                let child_value = xml_to_json(child, &child_path, losses);
+                match groups.iter_mut().find(|(n, _)| *n == name) {
+                    Some((_, values)) => {
+                        // Grouping by name moves this one next to its
+                        // namesakes, past the differently named siblings.
+                        if previous.as_ref() != Some(&name)
+                            && !interleaved.contains(&name)
+                        {
+                            losses.note(
+                                &child_path,
+                                "order of interleaved sibling elements lost",
+                            );
+                            interleaved.push(name.clone());
+                        }
+                        values.push(child_value)
+                    }
+                    None => groups.push((name.clone(), vec![child_value])),
+                }
+                previous = Some(name);
+            }
+            XMLNode::Text(t) | XMLNode::CData(t) => text.push_str(t),
+            XMLNode::Comment(_) => losses.note(path, "comment dropped"),
+            XMLNode::ProcessingInstruction(..) => {
+                losses.note(path, "processing instruction dropped")
+            }
+        }
+    }
+
+    if map.is_empty() && groups.is_empty() {
+        return JsonValue::String(text);
+    }
+    let trimmed = text.trim();
+    if !trimmed.is_empty() {
+        if !groups.is_empty() {
+            losses.note(path, "mixed content flattened into #text");
+        }
+        map.insert(TEXT_KEY.to_string(), JsonValue::String(trimmed.into()));
+    }
+    for (name, mut values) in groups {
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            JsonValue::Array(values)
+        };
+        map.insert(name, value);
+    }
+    JsonValue::Object(map)
+}
+
+fn check_xml_name(name: &str, path: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid_start =
+        chars.next().is_some_and(|c| c.is_alphabetic() || c == '_');
+    let valid_rest = chars
+        .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'));
+    if valid_start && valid_rest {
+        Ok(())
+    } else {
+        Err(anyhow!("'{name}' at {path} is not a valid XML name"))
+    }
+}
+
+fn scalar_text(
+    value: &JsonValue,
+    path: &str,
+    losses: &mut LossReport,
+) -> Option<String> {
+    match value {
+        JsonValue::String(s) => Some(s.clone()),
+        JsonValue::Number(n) => {
+            losses.note(path, "number type lost (written as text)");
+            Some(n.to_string())
+        }
+        JsonValue::Bool(b) => {
+            losses.note(path, "boolean type lost (written as text)");
+            Some(b.to_string())
+        }
+        JsonValue::Null => {
+            losses.note(path, "null written as empty text");
+            Some(String::new())
+        }
+        JsonValue::Array(_) | JsonValue::Object(_) => None,
+    }
+}
+
+fn json_to_xml(value: &JsonValue, losses: &mut LossReport) -> Result<String> {
+    let root = match value {
+        JsonValue::Object(map)
+            if map.len() == 1
+                && !map.values().next().is_some_and(JsonValue::is_array) =>
+        {
+            let (name, inner) = map.iter().next().unwrap();
+            let mut elements =
+                json_to_elements(name, inner, &child_path("$", name), losses)?;
+            elements.remove(0)
+        }
+        _ => {
+            losses.note("$", "document wrapped in <root> element");
+            let mut root = Element::new(DEFAULT_XML_ROOT);
+            fill_element(&mut root, value, "$", losses)?;
+            root
+        }
+    };
+    let mut buf = Vec::new();
+    root.write_with_config(
+        &mut buf,
+        xmltree::EmitterConfig::new().perform_indent(true),
+    )?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn json_to_elements(
+    name: &str,
+    value: &JsonValue,
+    path: &str,
+    losses: &mut LossReport,
+) -> Result<Vec<Element>> {
+    check_xml_name(name, path)?;
+    if let JsonValue::Array(items) = value {
+        let mut elements = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            let item_path = item_path(path, i);
+            if item.is_array() {
+                losses.note(&item_path, "nested array wrapped in <item>");
+                let mut element = Element::new(name);
+                for (j, inner) in item.as_array().unwrap().iter().enumerate() {
+                    for child in json_to_elements(
+                        "item",
+                        inner,
+                        &self::item_path(&item_path, j),
+                        losses,
+                    )? {
+                        element.children.push(XMLNode::Element(child));
+                    }
+                }
+                elements.push(element);
+            } else {
+                let mut element = Element::new(name);
+                fill_element(&mut element, item, &item_path, losses)?;
+                elements.push(element);
+            }
+        }
+        if elements.is_empty() {
+            losses.note(path, "empty array dropped");
+        }
+        return Ok(elements);
+    }
+    let mut element = Element::new(name);
+    fill_element(&mut element, value, path, losses)?;
+    Ok(vec![element])
+}
+
+fn fill_element(
+    element: &mut Element,
+    value: &JsonValue,
+    path: &str,
+    losses: &mut LossReport,
+) -> Result<()> {
+    let JsonValue::Object(map) = value else {
+        if let JsonValue::Array(_) = value {
+            for child in json_to_elements("item", value, path, losses)? {
+                element.children.push(XMLNode::Element(child));
+            }
+            return Ok(());
+        }
+        let text = scalar_text(value, path, losses).unwrap_or_default();
+        if !text.is_empty() {
+            element.children.push(XMLNode::Text(text));
+        }
+        return Ok(());
+    };
+    for (key, item) in map {
+        let item_path = child_path(path, key);
+        if let Some(attribute) = key.strip_prefix(ATTRIBUTE_PREFIX) {
+            check_xml_name(attribute, &item_path)?;
+            let text =
+                scalar_text(item, &item_path, losses).ok_or_else(|| {
+                    anyhow!("Attribute {item_path} must be a scalar value")
+                })?;
+            element.attributes.insert(attribute.to_string(), text);
+        } else if key == TEXT_KEY {
+            let text = scalar_text(item, &item_path, losses)
+                .ok_or_else(|| anyhow!("{item_path} must be a scalar value"))?;
+            element.children.push(XMLNode::Text(text));
+        } else {
+            for child in json_to_elements(key, item, &item_path, losses)? {
+                element.children.push(XMLNode::Element(child));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn float_to_json(f: f64, path: &str, losses: &mut LossReport) -> JsonValue {
+    match Number::from_f64(f) {
+        Some(n) => JsonValue::Number(n),
+        None => {
+            losses.note(path, "non-finite number replaced by null");
+            JsonValue::Null
+        }
+    }
+}
+
+fn yaml_to_json(
+    value: serde_yaml::Value,
+    path: &str,
+    losses: &mut LossReport,
+) -> JsonValue {
+    use serde_yaml::Value as Yaml;
+    match value {
+        Yaml::Null => JsonValue::Null,
+        Yaml::Bool(b) => JsonValue::Bool(b),
+        Yaml::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                JsonValue::from(i)
+            } else if let Some(u) = n.as_u64() {
+                JsonValue::from(u)
+            } else {
+                float_to_json(n.as_f64().unwrap_or(f64::NAN), path, losses)
+            }
+        }
+        Yaml::String(s) => JsonValue::String(s),
+        Yaml::Sequence(items) => JsonValue::Array(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    yaml_to_json(item, &item_path(path, i), losses)
+                })
+                .collect(),
+        ),
+        Yaml::Mapping(mapping) => {
+            let mut map = Map::new();
+            for (key, item) in mapping {
+                let key = match key {
+                    Yaml::String(s) => s,
+                    other => {
+                        losses.note(path, "non-string mapping key stringified");
+                        serde_yaml::to_string(&other)
+                            .unwrap_or_default()
+                            .trim_end()
+                            .to_string()
+                    }
+                };
+                let item = yaml_to_json(item, &child_path(path, &key), losses);
+                map.insert(key, item);
+            }
+            JsonValue::Object(map)
+        }
+        Yaml::Tagged(tagged) => {
+            losses.note(path, format!("YAML tag {} dropped", tagged.tag));
+            yaml_to_json(tagged.value, path, losses)
+        }
+    }
+}
+
+fn toml_to_json(
+    value: toml::Value,
+    path: &str,
+    losses: &mut LossReport,
+) -> JsonValue {
+    match value {
+        toml::Value::String(s) => JsonValue::String(s),
+        toml::Value::Integer(i) => JsonValue::from(i),
+        toml::Value::Float(f) => float_to_json(f, path, losses),
+        toml::Value::Boolean(b) => JsonValue::Bool(b),
+        toml::Value::Datetime(dt) => {
+            losses.note(path, "datetime type lost (written as string)");
+            JsonValue::String(dt.to_string())
+        }
+        toml::Value::Array(items) => JsonValue::Array(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    toml_to_json(item, &item_path(path, i), losses)
+                })
+                .collect(),
+        ),
+        toml::Value::Table(table) => JsonValue::Object(
+            table
+                .into_iter()
+                .map(|(key, item)| {
+                    let item =
+                        toml_to_json(item, &child_path(path, &key), losses);
+                    (key, item)
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn json_to_toml(
+    value: &JsonValue,
+    path: &str,
+    losses: &mut LossReport,
+) -> Result<Option<toml::Value>> {
+    Ok(Some(match value {
+        JsonValue::Null => {
+            losses.note(path, "null dropped (TOML has no null)");
+            return Ok(None);
+        }
+        JsonValue::Bool(b) => toml::Value::Boolean(*b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => {
+                if n.as_u64().is_some() {
+                    losses
+                        .note(path, "integer above i64 range written as float");
+                }
+                toml::Value::Float(n.as_f64().unwrap_or_default())
+            }
+        },
+        JsonValue::String(s) => toml::Value::String(s.clone()),
+        JsonValue::Array(items) => {
+            let mut array = Vec::new();
+            for (i, item) in items.iter().enumerate() {
+                if let Some(item) =
+                    json_to_toml(item, &item_path(path, i), losses)?
+                {
+                    array.push(item);
+                }
+            }
+            toml::Value::Array(array)
+        }
+        JsonValue::Object(map) => {
+            toml::Value::Table(json_to_toml_table(map, path, losses)?)
+        }
+    }))
+}
+
+fn json_to_toml_table(
+    map: &Map<String, JsonValue>,
+    path: &str,
+    losses: &mut LossReport,
+) -> Result<toml::Table> {
+    let mut table = toml::Table::new();
+    for (key, item) in map {
+        if let Some(item) = json_to_toml(item, &child_path(path, key), losses)?
+        {
+            table.insert(key.clone(), item);
+        }
+    }
+    Ok(table)
+}
+
+fn csv_to_json(input: &str) -> Result<JsonValue> {
+    let mut reader = csv::Reader::from_reader(input.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let row: Map<String, JsonValue> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(h, v)| (h.to_string(), JsonValue::String(v.to_string())))
+            .collect();
+        rows.push(JsonValue::Object(row));
+    }
+    Ok(JsonValue::Array(rows))
+}
+
+fn json_to_csv(value: &JsonValue, losses: &mut LossReport) -> Result<String> {
+    let JsonValue::Array(rows) = value else {
+        bail!("CSV output requires an array of flat objects");
+    };
+    let mut headers: Vec<&String> = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let JsonValue::Object(map) = row else {
+            bail!("{} is not an object", item_path("$", i));
+        };
+        for key in map.keys() {
+            if !headers.contains(&key) {
+                headers.push(key);
+            }
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&headers)?;
+    for (i, row) in rows.iter().enumerate() {
+        let row_path = item_path("$", i);
+        let map = row.as_object().unwrap();
+        let mut record = Vec::with_capacity(headers.len());
+        for header in &headers {
+            let cell_path = child_path(&row_path, header);
+            match map.get(*header) {
+                None => {
+                    losses
+                        .note(&row_path, "missing field written as empty cell");
+                    record.push(String::new());
+                }
+                Some(cell) => record.push(
+                    scalar_text(cell, &cell_path, losses).ok_or_else(|| {
+                        anyhow!(
+                            "{cell_path} is nested and cannot be a CSV cell"
+                        )
+                    })?,
+                ),
+            }
+        }
+        writer.write_record(&record)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn losses(conversion: &Conversion) -> Vec<String> {
+        conversion.losses.lines()
+    }
+
+    #[test]
+    fn xml_sibling_order_survives_a_round_trip() {
+        let xml = "<config><zeta>1</zeta><alpha>2</alpha><mid>3</mid></config>";
+        let json = convert(xml, Format::Xml, Format::Json).unwrap();
+        assert!(losses(&json).is_empty(), "{:?}", losses(&json));
+        let value: JsonValue = serde_json::from_str(&json.output).unwrap();
+        let keys: Vec<_> =
+            value["config"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["zeta", "alpha", "mid"]);
+
+        let back = convert(&json.output, Format::Json, Format::Xml).unwrap();
+        let zeta = back.output.find("<zeta>").unwrap();
+        let alpha = back.output.find("<alpha>").unwrap();
+        let mid = back.output.find("<mid>").unwrap();
+        assert!(zeta < alpha && alpha < mid, "{}", back.output);
+    }
+
+    #[test]
+    fn json_key_order_is_kept() {
+        let json = r#"{"b": 1, "a": {"z": true, "y": null}}"#;
+        let yaml = convert(json, Format::Json, Format::Yaml).unwrap();
+        assert_eq!(yaml.output, "b: 1\na:\n  z: true\n  y: null\n");
+    }
+
+    #[test]
+    fn interleaved_siblings_are_reported() {
+        let xml = "<r><a>1</a><b>2</b><a>3</a></r>";
+        let json = convert(xml, Format::Xml, Format::Json).unwrap();
+        assert_eq!(
+            losses(&json),
+            ["order of interleaved sibling elements lost at $.a"]
+        );
+    }
+
+    #[test]
+    fn prolog_nodes_and_namespaces_are_reported() {
+        let xml = "<!-- head --><?pi x?>\
+            <r xmlns:p='urn:p' p:id='1'><!-- c --></r>";
+        let json = convert(xml, Format::Xml, Format::Json).unwrap();
+        assert_eq!(
+            losses(&json),
+            [
+                "comment dropped at $",
+                "comment outside the root dropped at $",
+                "namespace binding of attribute prefix 'p' dropped at $",
+                "processing instruction outside the root dropped at $",
+            ]
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::ops::AddAssign;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde_json::{json, Value as JsonValue};
+
+pub struct Options {
+    /// Deepest directory level listed (the root is level 0); totals always
+    /// cover the whole tree.
+    pub max_depth: Option<usize>,
+    /// How many of the largest files and directories to keep.
+    pub top: usize,
+    /// Globs matched against both the path relative to the root and the
+    /// entry's file name; matching entries are skipped entirely.
+    pub exclude: Vec<String>,
+}
+
+/// Apparent size is the byte length; on-disk size is the allocated blocks,
+/// which is smaller for sparse files and larger for small ones.
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    pub apparent: u64,
+    pub on_disk: u64,
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.apparent += other.apparent;
+        self.on_disk += other.on_disk;
+    }
+}
+
+impl Usage {
+    fn of(metadata: &fs::Metadata) -> Usage {
+        Usage {
+            apparent: metadata.len(),
+            // st_blocks is always in 512-byte units.
+            on_disk: metadata.blocks() * 512,
+        }
+    }
+
+    fn to_json(self) -> JsonValue {
+        json!({ "apparent_bytes": self.apparent, "on_disk_bytes": self.on_disk })
+    }
+}
+
+#[derive(Default)]
+pub struct ExtensionStats {
+    pub files: u64,
+    pub usage: Usage,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub root: PathBuf,
+    pub total: Usage,
+    /// Regular files only, without directories and symlinks.
+    pub file_usage: Usage,
+    pub files: u64,
+    pub directories: u64,
+    pub symlinks: u64,
+    pub excluded: u64,
+    /// Directories up to the depth limit, parents after their children.
+    pub listed: Vec<(PathBuf, Usage)>,
+    pub largest_files: Vec<(PathBuf, Usage)>,
+    pub largest_directories: Vec<(PathBuf, Usage)>,
+    pub extensions: BTreeMap<String, ExtensionStats>,
+    /// Entries that could not be read, with the reason.
+    pub errors: Vec<String>,
+}
+
+struct Walker<'a> {
+    options: &'a Options,
+    exclude: GlobSet,
+    seen_inodes: HashSet<(u64, u64)>,
+    report: Report,
+}
+
+/// Walks `root` without following symbolic links: links are counted as
+/// links, so nothing outside the sandbox is reached through them. Files
+/// with several hard links are counted once, as `du` does.
+pub fn analyze(root: &Path, options: &Options) -> Result<Report> {
+    let mut exclude = GlobSetBuilder::new();
+    for pattern in &options.exclude {
+        exclude.add(
+            Glob::new(pattern)
+                .with_context(|| format!("Invalid glob '{pattern}'"))?,
+        );
+    }
+    let metadata = fs::symlink_metadata(root)
+        .with_context(|| format!("Reading {}", root.display()))?;
+    let mut walker = Walker {
+        options,
+        exclude: exclude.build()?,
+        seen_inodes: HashSet::new(),
+        report: Report {
+            root: root.to_path_buf(),
+            ..Report::default()
+        },
+    };
+    let total = if metadata.is_dir() {
+        walker.directory(root, Path::new(""), &metadata, 0)
+    } else {
+        walker.file(root, Path::new(""), &metadata)
+    };
+    let mut report = walker.report;
+    report.total = total;
+    // The root is the total; it is not one of the largest directories.
+    report
+        .largest_directories
+        .retain(|(path, _)| !path.as_os_str().is_empty());
+    trim_top(&mut report.largest_files, options.top);
+    trim_top(&mut report.largest_directories, options.top);
+    Ok(report)
+}
+
+/// Keeps the `top` largest entries by on-disk size, trimming lazily.
+fn push_top(
+    list: &mut Vec<(PathBuf, Usage)>,
+    top: usize,
+    entry: (PathBuf, Usage),
+) {
+    list.push(entry);
+    if list.len() >= top.max(1) * 2 {
+        trim_top(list, top);
+    }
+}
+
+fn trim_top(list: &mut Vec<(PathBuf, Usage)>, top: usize) {
+    list.sort_by(|a, b| b.1.on_disk.cmp(&a.1.on_disk).then(a.0.cmp(&b.0)));
+    list.truncate(top);
+}
+
+impl Walker<'_> {
+    fn excluded(&self, relative: &Path) -> bool {
+        self.exclude.is_match(relative)
+            || relative
+                .file_name()
+                .is_some_and(|name| self.exclude.is_match(name))
+    }
+
+    fn file(
+        &mut self,
+        path: &Path,
+        relative: &Path,
+        metadata: &fs::Metadata,
+    ) -> Usage {
+        if metadata.nlink() > 1
+            && !self.seen_inodes.insert((metadata.dev(), metadata.ino()))
+        {
+            return Usage::default();
+        }
+        let usage = Usage::of(metadata);
+        if metadata.file_type().is_symlink() {
+            self.report.symlinks += 1;
+            return usage;
+        }
+        self.report.files += 1;
+        self.report.file_usage += usage;
+        let extension = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+            .unwrap_or_else(|| "(none)".to_string());
+        let stats = self.report.extensions.entry(extension).or_default();
+        stats.files += 1;
+        stats.usage += usage;
+        push_top(
+            &mut self.report.largest_files,
+            self.options.top,
+            (relative.to_path_buf(), usage),
+        );
+        usage
+    }
+
+    fn directory(
+        &mut self,
+        path: &Path,
+        relative: &Path,
+        metadata: &fs::Metadata,
+        depth: usize,
+    ) -> Usage {
+        self.report.directories += 1;
+        let mut total = Usage::of(metadata);
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.report.errors.push(format!("{}: {e}", path.display()));
+                return total;
+            }
+        };
+        let mut entries: Vec<_> = entries.collect();
+        entries.sort_by_key(|entry| {
+            entry.as_ref().map(|e| e.file_name()).unwrap_or_default()
+        });
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.report.errors.push(format!("{}: {e}", path.display()));
+                    continue;
+                }
+            };
+            let child_relative = relative.join(entry.file_name());
+            if self.excluded(&child_relative) {
+                self.report.excluded += 1;
+                continue;
+            }
+            let child = entry.path();
+            // DirEntry::metadata does not traverse symlinks.
+            let child_metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    self.report
+                        .errors
+                        .push(format!("{}: {e}", child.display()));
+                    continue;
+                }
+            };
+            total += if child_metadata.is_dir() {
+                self.directory(
+                    &child,
+                    &child_relative,
+                    &child_metadata,
+                    depth + 1,
+                )
+            } else {
+                self.file(&child, &child_relative, &child_metadata)
+            };
+        }
+        if self.options.max_depth.is_none_or(|max| depth <= max) {
+            self.report.listed.push((relative.to_path_buf(), total));
+        }
+        push_top(
+            &mut self.report.largest_directories,
+            // One extra slot, as the root is dropped afterwards.
+            self.options.top + 1,
+            (relative.to_path_buf(), total),
+        );
+        total
+    }
+}
+
+impl Report {
+    pub fn to_json(&self) -> JsonValue {
+        let entries = |list: &[(PathBuf, Usage)]| -> Vec<JsonValue> {
+            list.iter()
+                .map(|(path, usage)| {
+                    let mut entry = usage.to_json();
+                    entry["path"] = json!(display_relative(path));
+                    entry
+                })
+                .collect()
+        };
+        let extensions: serde_json::Map<String, JsonValue> = self
+            .extensions
+            .iter()
+            .map(|(ext, stats)| {
+                let mut entry = stats.usage.to_json();
+                entry["files"] = json!(stats.files);
+                (ext.clone(), entry)
+            })
+            .collect();
+        json!({
+            "root": self.root.to_string_lossy(),
+            "total": self.total.to_json(),
+            "files": self.files,
+            "directories": self.directories,
+            "symlinks": self.symlinks,
+            "excluded": self.excluded,
+            "listed": entries(&self.listed),
+            "largest_files": entries(&self.largest_files),
+            "largest_directories": entries(&self.largest_directories),
+            "extensions": extensions,
+            "errors": self.errors,
+        })
+    }
+}
+
+/// Paths are reported relative to the analyzed root, which is `.`.
+pub fn display_relative(path: &Path) -> String {
+    if path.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        format!("./{}", path.display())
+    }
+}
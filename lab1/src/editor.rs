@@ -0,0 +1,116 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+
+pub const DEFAULT_EDITOR: &str = "vi";
+pub const DEFAULT_ALLOWLIST: &[&str] = &[
+    "vi", "vim", "nvim", "nano", "emacs", "micro", "hx", "kak", "code", "subl",
+    "gedit", "kate",
+];
+
+/// Editor command split into a program and its leading arguments.
+#[derive(Debug)]
+pub struct EditorCommand {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+/// Resolves the editor from `VISUAL`, then `EDITOR`, then `editor.command`
+/// (`vi` unless configured), and checks it against `editor.allowlist`.
+pub fn resolve_editor() -> Result<EditorCommand> {
+    let config = crate::config::get();
+    let command = parse_editor(&config.editor_command())?;
+    check_allowed(&command, &config.editor_allowlist())?;
+    Ok(command)
+}
+
+pub fn parse_editor(raw: &str) -> Result<EditorCommand> {
+    let mut words = shlex::split(raw)
+        .ok_or_else(|| anyhow!("Cannot parse editor command '{raw}'"))?
+        .into_iter();
+    let program = words
+        .next()
+        .ok_or_else(|| anyhow!("Editor command is empty"))?;
+    Ok(EditorCommand {
+        program,
+        args: words.collect(),
+    })
+}
+
+/// Arguments an allowlisted editor may be given without the allowlist naming
+/// them: they only control windowing and blocking, so they cannot make the
+/// editor run commands (as `vim -c '!sh'` or `nvim -u evil.vim` would).
+pub const SAFE_EDITOR_ARGS: &[&str] =
+    &["--wait", "-w", "-n", "--new-window", "-f", "--nofork"];
+
+fn same_program(entry: &str, program: &str) -> bool {
+    if Path::new(program).components().count() > 1 {
+        Path::new(entry) == Path::new(program)
+    } else {
+        entry == program
+    }
+}
+
+/// Bare names in the allowlist match editors looked up through `PATH`,
+/// absolute paths must match exactly. Arguments must either be listed in
+/// [`SAFE_EDITOR_ARGS`] or the whole command line must match an allowlist
+/// entry such as `"code --wait --reuse-window"`.
+fn check_allowed(command: &EditorCommand, allowlist: &[String]) -> Result<()> {
+    let matches_entry = |entry: &String| {
+        parse_editor(entry).is_ok_and(|allowed| {
+            same_program(&allowed.program, &command.program)
+                && (allowed.args == command.args
+                    || command
+                        .args
+                        .iter()
+                        .all(|arg| SAFE_EDITOR_ARGS.contains(&arg.as_str())))
+        })
+    };
+    if allowlist.iter().any(matches_entry) {
+        return Ok(());
+    }
+    if allowlist.iter().any(|entry| {
+        parse_editor(entry).is_ok_and(|allowed| {
+            same_program(&allowed.program, &command.program)
+        })
+    }) {
+        return Err(anyhow!(
+            "Editor arguments '{}' are not allowed; add the full command to \
+             the allowlist or use only {}",
+            command.args.join(" "),
+            SAFE_EDITOR_ARGS.join(", ")
+        ));
+    }
+    Err(anyhow!(
+        "Editor '{}' is not in the allowlist ({})",
+        command.program,
+        allowlist.join(", ")
+    ))
+}
+
+/// Lets the user edit `initial` in a private temporary copy and returns the
+/// edited content. The editor never receives the sandboxed path itself, so
+/// it cannot be tricked into writing anywhere else.
+pub fn edit_copy(initial: &[u8], suffix: &str) -> Result<Vec<u8>> {
+    let command = resolve_editor()?;
+    let mut scratch = tempfile::Builder::new()
+        .prefix("osul-edit-")
+        .suffix(suffix)
+        .tempfile()
+        .context("creating temporary file for editor")?;
+    scratch.write_all(initial)?;
+    scratch.flush()?;
+
+    let status = Command::new(&command.program)
+        .args(&command.args)
+        .arg(scratch.path())
+        .status()
+        .with_context(|| format!("launching editor '{}'", command.program))?;
+    if !status.success() {
+        return Err(anyhow!("Editor exited with non-zero"));
+    }
+    fs::read(scratch.path()).context("reading edited temporary file")
+}
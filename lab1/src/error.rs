@@ -0,0 +1,179 @@
+use std::fmt;
+use std::io;
+
+use serde_json::{json, Value as JsonValue};
+
+/// Failures that callers, and scripts via the exit status, can tell apart.
+/// They travel inside `anyhow::Error` like every other error, so context
+/// added on the way up is kept; [`ErrorKind::of`] finds them again in the chain.
+#[derive(Debug)]
+pub enum Error {
+    /// A path resolves outside the sandbox root.
+    Sandbox(String),
+    /// A configured limit was reached: quota, archive expansion ratio,
+    /// parser limits.
+    Limit(String),
+    NotFound(String),
+    AlreadyExists(String),
+    /// Malformed input, e.g. invalid JSON or XML or a broken archive.
+    Parse(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sandbox(message)
+            | Error::Limit(message)
+            | Error::NotFound(message)
+            | Error::AlreadyExists(message)
+            | Error::Parse(message) => f.write_str(message),
+            Error::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Sandbox(_) => ErrorKind::Sandbox,
+            Error::Limit(_) => ErrorKind::Limit,
+            Error::NotFound(_) => ErrorKind::NotFound,
+            Error::AlreadyExists(_) => ErrorKind::AlreadyExists,
+            Error::Parse(_) => ErrorKind::Parse,
+            Error::Io(err) => ErrorKind::of_io(err),
+        }
+    }
+}
+
+/// The category of a failure, which decides the exit status:
+///
+/// | status | kind             |
+/// |--------|------------------|
+/// | 0      | success          |
+/// | 1      | any other error  |
+/// | 2      | invalid usage    |
+/// | 3      | `sandbox`        |
+/// | 4      | `limit`          |
+/// | 5      | `not_found`      |
+/// | 6      | `already_exists` |
+/// | 7      | `parse`          |
+/// | 8      | `io`             |
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Other,
+    Sandbox,
+    Limit,
+    NotFound,
+    AlreadyExists,
+    Parse,
+    Io,
+}
+
+impl ErrorKind {
+    /// The outermost cause in `err`'s chain that is an [`Error`] or a
+    /// library error with an obvious category decides; anything else is
+    /// [`ErrorKind::Other`].
+    pub fn of(err: &anyhow::Error) -> ErrorKind {
+        err.chain()
+            .find_map(ErrorKind::of_cause)
+            .unwrap_or(ErrorKind::Other)
+    }
+
+    fn of_cause(
+        cause: &(dyn std::error::Error + 'static),
+    ) -> Option<ErrorKind> {
+        if let Some(err) = cause.downcast_ref::<Error>() {
+            Some(err.kind())
+        } else if let Some(err) = cause.downcast_ref::<io::Error>() {
+            Some(ErrorKind::of_io(err))
+        } else if let Some(err) = cause.downcast_ref::<serde_json::Error>() {
+            // Read failures surface as serde_json errors too; the wrapped
+            // io::Error is their source.
+            (!err.is_io()).then_some(ErrorKind::Parse)
+        } else if let Some(err) = cause.downcast_ref::<zip::result::ZipError>()
+        {
+            match err {
+                zip::result::ZipError::Io(_) => None,
+                zip::result::ZipError::FileNotFound => {
+                    Some(ErrorKind::NotFound)
+                }
+                _ => Some(ErrorKind::Parse),
+            }
+        } else if cause.is::<serde_yaml::Error>()
+            || cause.is::<toml::de::Error>()
+            || cause.is::<xmltree::ParseError>()
+            || cause.is::<csv::Error>()
+        {
+            Some(ErrorKind::Parse)
+        } else {
+            None
+        }
+    }
+
+    /// An [`Error`] wrapped in an `io::Error`, as writers raise them, keeps
+    /// its kind; the wrapper hides it from the source chain.
+    fn of_io(err: &io::Error) -> ErrorKind {
+        if let Some(inner) =
+            err.get_ref().and_then(|e| e.downcast_ref::<Error>())
+        {
+            return inner.kind();
+        }
+        match err.kind() {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+            _ => ErrorKind::Io,
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Sandbox => 3,
+            ErrorKind::Limit => 4,
+            ErrorKind::NotFound => 5,
+            ErrorKind::AlreadyExists => 6,
+            ErrorKind::Parse => 7,
+            ErrorKind::Io => 8,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Other => "other",
+            ErrorKind::Sandbox => "sandbox",
+            ErrorKind::Limit => "limit",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::AlreadyExists => "already_exists",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Io => "io",
+        }
+    }
+}
+
+/// `{"error": {"kind", "exit_code", "message", "causes"}}`, where
+/// `message` is the outermost context and `causes` the rest of the chain.
+pub fn to_json(err: &anyhow::Error) -> JsonValue {
+    let kind = ErrorKind::of(err);
+    json!({
+        "error": {
+            "kind": kind.name(),
+            "exit_code": kind.exit_code(),
+            "message": err.to_string(),
+            "causes": err
+                .chain()
+                .skip(1)
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+        }
+    })
+}
@@ -0,0 +1,265 @@
+use std::fmt::Write as _;
+
+use anyhow::{anyhow, Result};
+use serde_json::{Number, Value as JsonValue};
+use sha2::{Digest, Sha256};
+
+/// Largest integer every IEEE 754 double can hold exactly (2^53).
+const MAX_SAFE_INTEGER: u64 = 1 << 53;
+
+/// Serializes `value` following the JSON Canonicalization Scheme
+/// (RFC 8785): no insignificant whitespace, object members sorted by their
+/// UTF-16 code units, minimal string escaping and ECMAScript number
+/// formatting.
+pub fn canonicalize(value: &JsonValue) -> Result<String> {
+    let mut out = String::new();
+    write_value(value, &mut out)?;
+    Ok(out)
+}
+
+pub fn sha256_hex(value: &JsonValue) -> Result<String> {
+    let canonical = canonicalize(value)?;
+    Ok(hex::encode(Sha256::digest(canonical.as_bytes())))
+}
+
+fn write_value(value: &JsonValue, out: &mut String) -> Result<()> {
+    match value {
+        JsonValue::Null => out.push_str("null"),
+        JsonValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        JsonValue::Number(n) => out.push_str(&format_number(n)?),
+        JsonValue::String(s) => write_string(s, out),
+        JsonValue::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out)?;
+            }
+            out.push(']');
+        }
+        JsonValue::Object(map) => {
+            let mut members: Vec<_> = map.iter().collect();
+            members.sort_by(|(a, _), (b, _)| {
+                a.encode_utf16().cmp(b.encode_utf16())
+            });
+            out.push('{');
+            for (i, (key, item)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(item, out)?;
+            }
+            out.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn format_number(n: &Number) -> Result<String> {
+    let integer = n
+        .as_i64()
+        .map(i128::from)
+        .or_else(|| n.as_u64().map(i128::from));
+    if let Some(i) = integer {
+        if i.unsigned_abs() <= u128::from(MAX_SAFE_INTEGER) {
+            return Ok(i.to_string());
+        }
+        // Larger integers are fine as long as the double is the same number,
+        // e.g. 2^60; anything else would change value when parsed back.
+        let f = i as f64;
+        if f as i128 != i {
+            return Err(anyhow!(
+                "Integer {i} cannot be represented exactly as an IEEE 754 double"
+            ));
+        }
+        return Ok(format_double(f));
+    }
+    let f = n
+        .as_f64()
+        .ok_or_else(|| anyhow!("Unsupported number {n}"))?;
+    Ok(format_double(f))
+}
+
+/// Formats a finite double like ECMAScript's `Number.prototype.toString`.
+fn format_double(f: f64) -> String {
+    if f == 0.0 {
+        return "0".to_string();
+    }
+    let sign = if f < 0.0 { "-" } else { "" };
+    // `{:e}` yields the shortest round-tripping digits, e.g. `1.2345e-7`.
+    let shortest = format!("{:e}", f.abs());
+    // Of the candidates with that many digits ECMAScript takes the one
+    // closest to `f`, and the even one on a tie (1424953923781206.25 becomes
+    // ...206.2); exact formatting rounds the same way.
+    let len = shortest.split_once('e').unwrap().0.replace('.', "").len();
+    let closest = format!("{:.*e}", len - 1, f.abs());
+    let sci = if closest.parse::<f64>() == Ok(f.abs()) {
+        closest
+    } else {
+        shortest
+    };
+    let (mantissa, exponent) = sci.split_once('e').unwrap();
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let exponent: i32 = exponent.parse().unwrap();
+    let k = digits.len() as i32;
+    let n = exponent + 1;
+
+    let body = if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        let (int, frac) = digits.split_at(n as usize);
+        format!("{int}.{frac}")
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat((-n) as usize))
+    } else {
+        let exp_sign = if n - 1 < 0 { '-' } else { '+' };
+        let (first, rest) = digits.split_at(1);
+        let rest = if rest.is_empty() {
+            String::new()
+        } else {
+            format!(".{rest}")
+        };
+        format!("{first}{rest}e{exp_sign}{}", (n - 1).abs())
+    };
+    format!("{sign}{body}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 8785 Appendix B: IEEE 754 bit patterns and their serialization.
+    const NUMBERS: &[(u64, &str)] = &[
+        (0x0000000000000000, "0"),
+        (0x8000000000000000, "0"),
+        (0x0000000000000001, "5e-324"),
+        (0x8000000000000001, "-5e-324"),
+        (0x7fefffffffffffff, "1.7976931348623157e+308"),
+        (0xffefffffffffffff, "-1.7976931348623157e+308"),
+        (0x4340000000000000, "9007199254740992"),
+        (0xc340000000000000, "-9007199254740992"),
+        (0x4430000000000000, "295147905179352830000"),
+        (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+        (0x44b52d02c7e14af6, "1e+23"),
+        (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+        (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+        (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+        (0x444b1ae4d6e2ef50, "1e+21"),
+        (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+        (0x3eb0c6f7a0b5ed8d, "0.000001"),
+        (0x41b3de4355555553, "333333333.3333332"),
+        (0x41b3de4355555554, "333333333.33333325"),
+        (0x41b3de4355555555, "333333333.3333333"),
+        (0x41b3de4355555556, "333333333.3333334"),
+        (0x41b3de4355555557, "333333333.33333343"),
+        (0xbecbf647612f3696, "-0.0000033333333333333333"),
+        (0x43143ff3c1cb0959, "1424953923781206.2"),
+    ];
+
+    #[test]
+    fn numbers_match_rfc_8785_appendix_b() {
+        for &(bits, expected) in NUMBERS {
+            assert_eq!(
+                format_double(f64::from_bits(bits)),
+                expected,
+                "{bits:#018x}"
+            );
+        }
+    }
+
+    #[test]
+    fn exponent_boundaries() {
+        assert_eq!(format_double(1e21), "1e+21");
+        assert_eq!(format_double(1e20), "100000000000000000000");
+        assert_eq!(format_double(1e-7), "1e-7");
+        assert_eq!(format_double(1e-6), "0.000001");
+        assert_eq!(format_double(-0.0), "0");
+    }
+
+    fn canonical(json: &str) -> String {
+        canonicalize(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn keys_sort_by_utf16_code_units() {
+        // RFC 8785 section 3.2.3.
+        let input = r#"{
+            "\u20ac": "Euro Sign",
+            "\r": "Carriage Return",
+            "\ufb33": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\ud83d\ude00": "Emoji: Grinning Face",
+            "\u0080": "Control",
+            "\u00f6": "Latin Small Letter O With Diaeresis"
+        }"#;
+        assert_eq!(
+            canonical(input),
+            concat!(
+                r#"{"\r":"Carriage Return","1":"One","#,
+                "\"\u{80}\":\"Control\",",
+                "\"\u{f6}\":\"Latin Small Letter O With Diaeresis\",",
+                "\"\u{20ac}\":\"Euro Sign\",",
+                "\"\u{1f600}\":\"Emoji: Grinning Face\",",
+                "\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+            )
+        );
+    }
+
+    #[test]
+    fn rfc_8785_sample_document() {
+        // RFC 8785 section 3.2.2.
+        let input = r#"{
+            "numbers": [333333333.33333329, 1E30, 4.50,
+                        2e-3, 0.000000000000000000000000001],
+            "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+            "literals": [null, true, false]
+        }"#;
+        assert_eq!(
+            canonical(input),
+            concat!(
+                r#"{"literals":[null,true,false],"#,
+                r#""numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"#,
+                r#""string":"€$\u000f\nA'B\"\\\\\"/"}"#
+            )
+        );
+    }
+
+    #[test]
+    fn integers_beyond_2_pow_53_must_round_trip() {
+        assert_eq!(canonical("9007199254740992"), "9007199254740992");
+        assert_eq!(
+            canonical("1152921504606846976"),
+            "1152921504606847000",
+            "2^60 is exactly representable and printed like ECMAScript"
+        );
+        assert_eq!(canonical("-9223372036854775808"), "-9223372036854776000");
+        assert_eq!(canonical("18446744073709549568"), "18446744073709550000");
+        assert!(canonicalize(&serde_json::json!(9007199254740993u64)).is_err());
+        assert!(canonicalize(&serde_json::json!(-9007199254740993i64)).is_err());
+        assert!(canonicalize(&serde_json::json!(i64::MAX)).is_err());
+        assert!(canonicalize(&serde_json::json!(u64::MAX)).is_err());
+    }
+}
@@ -0,0 +1,1209 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Number, Value as JsonValue};
+
+/// Parsed filter in a practical subset of the jq language: paths
+/// (`.a.b`, `.[0]`, `.[1:3]`, `.[]`, `..`), pipes and commas, comparisons
+/// with `and`/`or`, `+`/`-`, array construction, assignment (`=`, `|=`) and
+/// the builtins `map`, `select`, `keys`, `length`, `has`, `type`, `not`,
+/// `del` and `empty`.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Identity,
+    Recurse,
+    Field(Box<Filter>, String),
+    Index(Box<Filter>, Box<Filter>),
+    Slice(Box<Filter>, Option<Box<Filter>>, Option<Box<Filter>>),
+    Iterate(Box<Filter>),
+    Optional(Box<Filter>),
+    Literal(JsonValue),
+    Array(Option<Box<Filter>>),
+    Pipe(Box<Filter>, Box<Filter>),
+    Comma(Box<Filter>, Box<Filter>),
+    Binary(BinOp, Box<Filter>, Box<Filter>),
+    Neg(Box<Filter>),
+    Assign(Box<Filter>, Box<Filter>),
+    Update(Box<Filter>, Box<Filter>),
+    Call(String, Vec<Filter>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    Add,
+    Sub,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Dot,
+    DotDot,
+    Field(String),
+    Ident(String),
+    Str(String),
+    Num(f64),
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Colon,
+    Semicolon,
+    Comma,
+    Pipe,
+    Question,
+    Assign,
+    Update,
+    Cmp(BinOp),
+    Plus,
+    Minus,
+}
+
+/// Default for how far past its end an assignment may extend an array
+/// (`query.max_array_padding`), filling the gap with nulls, so that an index
+/// such as `.[1000000000000]` fails instead of exhausting memory.
+pub const MAX_ARRAY_PADDING: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Seg {
+    Key(String),
+    Index(usize),
+}
+
+pub fn parse(source: &str) -> Result<Filter> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let filter = parser.pipe()?;
+    if let Some(token) = parser.peek() {
+        bail!("Unexpected token {token:?} in filter");
+    }
+    Ok(filter)
+}
+
+pub fn run(filter: &Filter, input: &JsonValue) -> Result<Vec<JsonValue>> {
+    eval(filter, input)
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let ident = |i: &mut usize| {
+        let start = *i;
+        while *i < chars.len() && is_ident_char(chars[*i]) {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>()
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '.' if next == Some('.') => {
+                tokens.push(Token::DotDot);
+                i += 2;
+                if chars.get(i).copied().is_some_and(is_ident_start) {
+                    tokens.push(Token::Field(ident(&mut i)));
+                }
+            }
+            '.' if next.is_some_and(is_ident_start) => {
+                i += 1;
+                tokens.push(Token::Field(ident(&mut i)));
+            }
+            '.' => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            '"' => {
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => bail!("Unterminated string in filter"),
+                        Some('"') => break,
+                        Some('\\') => {
+                            i += 1;
+                            match chars.get(i) {
+                                Some('n') => s.push('\n'),
+                                Some('t') => s.push('\t'),
+                                Some(&other) => s.push(other),
+                                None => bail!("Unterminated string in filter"),
+                            }
+                        }
+                        Some(&other) => s.push(other),
+                    }
+                    i += 1;
+                }
+                i += 1;
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_ascii_digit() || chars[i] == '.')
+                {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let n = text
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Invalid number '{text}'"))?;
+                tokens.push(Token::Num(n));
+            }
+            c if is_ident_start(c) => tokens.push(Token::Ident(ident(&mut i))),
+            _ => {
+                let (token, width) = match (c, next) {
+                    ('|', Some('=')) => (Token::Update, 2),
+                    ('=', Some('=')) => (Token::Cmp(BinOp::Eq), 2),
+                    ('!', Some('=')) => (Token::Cmp(BinOp::Ne), 2),
+                    ('<', Some('=')) => (Token::Cmp(BinOp::Le), 2),
+                    ('>', Some('=')) => (Token::Cmp(BinOp::Ge), 2),
+                    ('<', _) => (Token::Cmp(BinOp::Lt), 1),
+                    ('>', _) => (Token::Cmp(BinOp::Gt), 1),
+                    ('=', _) => (Token::Assign, 1),
+                    ('|', _) => (Token::Pipe, 1),
+                    ('[', _) => (Token::LBracket, 1),
+                    (']', _) => (Token::RBracket, 1),
+                    ('(', _) => (Token::LParen, 1),
+                    (')', _) => (Token::RParen, 1),
+                    (':', _) => (Token::Colon, 1),
+                    (';', _) => (Token::Semicolon, 1),
+                    (',', _) => (Token::Comma, 1),
+                    ('?', _) => (Token::Question, 1),
+                    ('+', _) => (Token::Plus, 1),
+                    ('-', _) => (Token::Minus, 1),
+                    _ => bail!("Unexpected character '{c}' in filter"),
+                };
+                tokens.push(token);
+                i += width;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(anyhow!("Expected {token:?}, found {:?}", self.peek()))
+        }
+    }
+
+    fn eat_keyword(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(w)) if w == word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn pipe(&mut self) -> Result<Filter> {
+        let mut lhs = self.comma()?;
+        while self.eat(&Token::Pipe) {
+            let rhs = self.comma()?;
+            lhs = Filter::Pipe(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn comma(&mut self) -> Result<Filter> {
+        let mut lhs = self.assign()?;
+        while self.eat(&Token::Comma) {
+            let rhs = self.assign()?;
+            lhs = Filter::Comma(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn assign(&mut self) -> Result<Filter> {
+        let lhs = self.or()?;
+        if self.eat(&Token::Assign) {
+            let rhs = self.or()?;
+            return Ok(Filter::Assign(Box::new(lhs), Box::new(rhs)));
+        }
+        if self.eat(&Token::Update) {
+            let rhs = self.or()?;
+            return Ok(Filter::Update(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Filter> {
+        let mut lhs = self.and()?;
+        while self.eat_keyword("or") {
+            let rhs = self.and()?;
+            lhs = Filter::Binary(BinOp::Or, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut lhs = self.comparison()?;
+        while self.eat_keyword("and") {
+            let rhs = self.comparison()?;
+            lhs = Filter::Binary(BinOp::And, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Filter> {
+        let lhs = self.additive()?;
+        if let Some(Token::Cmp(op)) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.additive()?;
+            return Ok(Filter::Binary(op, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn additive(&mut self) -> Result<Filter> {
+        let mut lhs = self.postfix()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinOp::Add,
+                Some(Token::Minus) => BinOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.postfix()?;
+            lhs = Filter::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Filter> {
+        let mut term = self.primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Field(_)) => {
+                    let Some(Token::Field(name)) = self.next() else {
+                        unreachable!()
+                    };
+                    term = Filter::Field(Box::new(term), name);
+                }
+                Some(Token::Dot)
+                    if matches!(
+                        self.tokens.get(self.pos + 1),
+                        Some(Token::LBracket) | Some(Token::Str(_))
+                    ) =>
+                {
+                    self.pos += 1;
+                    if let Some(Token::Str(_)) = self.peek() {
+                        let Some(Token::Str(name)) = self.next() else {
+                            unreachable!()
+                        };
+                        term = Filter::Field(Box::new(term), name);
+                    }
+                }
+                Some(Token::LBracket) => {
+                    self.pos += 1;
+                    term = self.bracket_suffix(term)?;
+                }
+                Some(Token::Question) => {
+                    self.pos += 1;
+                    term = Filter::Optional(Box::new(term));
+                }
+                _ => return Ok(term),
+            }
+        }
+    }
+
+    fn bracket_suffix(&mut self, term: Filter) -> Result<Filter> {
+        let term = Box::new(term);
+        if self.eat(&Token::RBracket) {
+            return Ok(Filter::Iterate(term));
+        }
+        if self.eat(&Token::Colon) {
+            let end = self.pipe()?;
+            self.expect(&Token::RBracket)?;
+            return Ok(Filter::Slice(term, None, Some(Box::new(end))));
+        }
+        let start = self.pipe()?;
+        if self.eat(&Token::Colon) {
+            let end = if self.peek() == Some(&Token::RBracket) {
+                None
+            } else {
+                Some(Box::new(self.pipe()?))
+            };
+            self.expect(&Token::RBracket)?;
+            return Ok(Filter::Slice(term, Some(Box::new(start)), end));
+        }
+        self.expect(&Token::RBracket)?;
+        Ok(Filter::Index(term, Box::new(start)))
+    }
+
+    fn primary(&mut self) -> Result<Filter> {
+        match self.next() {
+            Some(Token::Dot) => match self.peek() {
+                Some(Token::Str(_)) => {
+                    let Some(Token::Str(name)) = self.next() else {
+                        unreachable!()
+                    };
+                    Ok(Filter::Field(Box::new(Filter::Identity), name))
+                }
+                Some(Token::LBracket) => {
+                    self.pos += 1;
+                    self.bracket_suffix(Filter::Identity)
+                }
+                _ => Ok(Filter::Identity),
+            },
+            Some(Token::DotDot) => match self.peek() {
+                Some(Token::Field(_)) => {
+                    let Some(Token::Field(name)) = self.next() else {
+                        unreachable!()
+                    };
+                    let field = Filter::Field(Box::new(Filter::Identity), name);
+                    Ok(Filter::Pipe(
+                        Box::new(Filter::Recurse),
+                        Box::new(Filter::Optional(Box::new(field))),
+                    ))
+                }
+                _ => Ok(Filter::Recurse),
+            },
+            Some(Token::Field(name)) => {
+                Ok(Filter::Field(Box::new(Filter::Identity), name))
+            }
+            Some(Token::Str(s)) => Ok(Filter::Literal(JsonValue::String(s))),
+            Some(Token::Num(n)) => Ok(Filter::Literal(number(n))),
+            Some(Token::Minus) => Ok(Filter::Neg(Box::new(self.postfix()?))),
+            Some(Token::LParen) => {
+                let inner = self.pipe()?;
+                self.expect(&Token::RParen)?;
+                Ok(inner)
+            }
+            Some(Token::LBracket) => {
+                if self.eat(&Token::RBracket) {
+                    return Ok(Filter::Array(None));
+                }
+                let inner = self.pipe()?;
+                self.expect(&Token::RBracket)?;
+                Ok(Filter::Array(Some(Box::new(inner))))
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Filter::Literal(JsonValue::Bool(true))),
+                "false" => Ok(Filter::Literal(JsonValue::Bool(false))),
+                "null" => Ok(Filter::Literal(JsonValue::Null)),
+                _ => {
+                    let mut args = Vec::new();
+                    if self.eat(&Token::LParen) {
+                        args.push(self.pipe()?);
+                        while self.eat(&Token::Semicolon) {
+                            args.push(self.pipe()?);
+                        }
+                        self.expect(&Token::RParen)?;
+                    }
+                    Ok(Filter::Call(name, args))
+                }
+            },
+            other => Err(anyhow!("Unexpected token {other:?} in filter")),
+        }
+    }
+}
+
+fn number(n: f64) -> JsonValue {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        JsonValue::from(n as i64)
+    } else {
+        Number::from_f64(n).map_or(JsonValue::Null, JsonValue::Number)
+    }
+}
+
+fn type_name(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "boolean",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "string",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object",
+    }
+}
+
+fn truthy(value: &JsonValue) -> bool {
+    !matches!(value, JsonValue::Null | JsonValue::Bool(false))
+}
+
+/// Total order used by jq: null < false < true < numbers < strings <
+/// arrays < objects.
+pub fn compare(a: &JsonValue, b: &JsonValue) -> Ordering {
+    fn rank(v: &JsonValue) -> u8 {
+        match v {
+            JsonValue::Null => 0,
+            JsonValue::Bool(false) => 1,
+            JsonValue::Bool(true) => 2,
+            JsonValue::Number(_) => 3,
+            JsonValue::String(_) => 4,
+            JsonValue::Array(_) => 5,
+            JsonValue::Object(_) => 6,
+        }
+    }
+    match (a, b) {
+        (JsonValue::Number(x), JsonValue::Number(y)) => {
+            let (x, y) = (x.as_f64().unwrap_or(0.0), y.as_f64().unwrap_or(0.0));
+            x.partial_cmp(&y).unwrap_or(Ordering::Equal)
+        }
+        (JsonValue::String(x), JsonValue::String(y)) => x.cmp(y),
+        (JsonValue::Array(x), JsonValue::Array(y)) => {
+            for (l, r) in x.iter().zip(y) {
+                let ord = compare(l, r);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            x.len().cmp(&y.len())
+        }
+        (JsonValue::Object(x), JsonValue::Object(y)) => {
+            let mut xk: Vec<_> = x.keys().collect();
+            let mut yk: Vec<_> = y.keys().collect();
+            xk.sort();
+            yk.sort();
+            xk.cmp(&yk).then_with(|| {
+                xk.iter()
+                    .map(|k| compare(&x[k.as_str()], &y[k.as_str()]))
+                    .find(|ord| *ord != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn resolve_index(len: usize, index: f64) -> Option<usize> {
+    let index = index.floor() as i64;
+    let index = if index < 0 { len as i64 + index } else { index };
+    (index >= 0 && (index as usize) < len).then_some(index as usize)
+}
+
+fn clamp_bound(len: usize, bound: Option<&JsonValue>, default: usize) -> usize {
+    match bound.and_then(JsonValue::as_f64) {
+        None => default,
+        Some(b) => {
+            let b = b.floor() as i64;
+            let b = if b < 0 { len as i64 + b } else { b };
+            b.clamp(0, len as i64) as usize
+        }
+    }
+}
+
+fn index_value(value: &JsonValue, key: &JsonValue) -> Result<JsonValue> {
+    match (value, key) {
+        (JsonValue::Object(map), JsonValue::String(k)) => {
+            Ok(map.get(k).cloned().unwrap_or(JsonValue::Null))
+        }
+        (JsonValue::Array(items), JsonValue::Number(n)) => {
+            Ok(resolve_index(items.len(), n.as_f64().unwrap_or(0.0))
+                .map_or(JsonValue::Null, |i| items[i].clone()))
+        }
+        (JsonValue::Null, JsonValue::String(_) | JsonValue::Number(_)) => {
+            Ok(JsonValue::Null)
+        }
+        _ => Err(anyhow!(
+            "Cannot index {} with {}",
+            type_name(value),
+            type_name(key)
+        )),
+    }
+}
+
+fn slice_value(
+    value: &JsonValue,
+    start: Option<&JsonValue>,
+    end: Option<&JsonValue>,
+) -> Result<JsonValue> {
+    match value {
+        JsonValue::Array(items) => {
+            let from = clamp_bound(items.len(), start, 0);
+            let to = clamp_bound(items.len(), end, items.len()).max(from);
+            Ok(JsonValue::Array(items[from..to].to_vec()))
+        }
+        JsonValue::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let from = clamp_bound(chars.len(), start, 0);
+            let to = clamp_bound(chars.len(), end, chars.len()).max(from);
+            Ok(JsonValue::String(chars[from..to].iter().collect()))
+        }
+        JsonValue::Null => Ok(JsonValue::Null),
+        _ => Err(anyhow!("Cannot slice {}", type_name(value))),
+    }
+}
+
+fn iterate_value(value: &JsonValue) -> Result<Vec<JsonValue>> {
+    match value {
+        JsonValue::Array(items) => Ok(items.clone()),
+        JsonValue::Object(map) => Ok(map.values().cloned().collect()),
+        _ => Err(anyhow!("Cannot iterate over {}", type_name(value))),
+    }
+}
+
+fn recurse_value(value: &JsonValue, out: &mut Vec<JsonValue>) {
+    out.push(value.clone());
+    match value {
+        JsonValue::Array(items) => {
+            items.iter().for_each(|item| recurse_value(item, out))
+        }
+        JsonValue::Object(map) => {
+            map.values().for_each(|item| recurse_value(item, out))
+        }
+        _ => {}
+    }
+}
+
+fn arithmetic(op: BinOp, a: &JsonValue, b: &JsonValue) -> Result<JsonValue> {
+    match (op, a, b) {
+        (BinOp::Add, JsonValue::Null, other)
+        | (BinOp::Add, other, JsonValue::Null) => Ok(other.clone()),
+        (_, JsonValue::Number(x), JsonValue::Number(y)) => {
+            let (x, y) = (x.as_f64().unwrap_or(0.0), y.as_f64().unwrap_or(0.0));
+            Ok(number(if matches!(op, BinOp::Add) {
+                x + y
+            } else {
+                x - y
+            }))
+        }
+        (BinOp::Add, JsonValue::String(x), JsonValue::String(y)) => {
+            Ok(JsonValue::String(format!("{x}{y}")))
+        }
+        (BinOp::Add, JsonValue::Array(x), JsonValue::Array(y)) => {
+            Ok(JsonValue::Array(x.iter().chain(y).cloned().collect()))
+        }
+        (BinOp::Sub, JsonValue::Array(x), JsonValue::Array(y)) => {
+            Ok(JsonValue::Array(
+                x.iter().filter(|item| !y.contains(item)).cloned().collect(),
+            ))
+        }
+        (BinOp::Add, JsonValue::Object(x), JsonValue::Object(y)) => {
+            let mut merged = x.clone();
+            merged.extend(y.clone());
+            Ok(JsonValue::Object(merged))
+        }
+        _ => Err(anyhow!(
+            "Cannot apply {op:?} to {} and {}",
+            type_name(a),
+            type_name(b)
+        )),
+    }
+}
+
+fn eval(filter: &Filter, input: &JsonValue) -> Result<Vec<JsonValue>> {
+    match filter {
+        Filter::Identity => Ok(vec![input.clone()]),
+        Filter::Recurse => {
+            let mut out = Vec::new();
+            recurse_value(input, &mut out);
+            Ok(out)
+        }
+        Filter::Literal(value) => Ok(vec![value.clone()]),
+        Filter::Field(term, name) => eval(term, input)?
+            .iter()
+            .map(|v| index_value(v, &JsonValue::String(name.clone())))
+            .collect(),
+        Filter::Index(term, key) => {
+            let keys = eval(key, input)?;
+            let mut out = Vec::new();
+            for value in eval(term, input)? {
+                for key in &keys {
+                    out.push(index_value(&value, key)?);
+                }
+            }
+            Ok(out)
+        }
+        Filter::Slice(term, start, end) => {
+            let start = start.as_ref().map(|f| first(f, input)).transpose()?;
+            let end = end.as_ref().map(|f| first(f, input)).transpose()?;
+            eval(term, input)?
+                .iter()
+                .map(|v| slice_value(v, start.as_ref(), end.as_ref()))
+                .collect()
+        }
+        Filter::Iterate(term) => {
+            let mut out = Vec::new();
+            for value in eval(term, input)? {
+                out.extend(iterate_value(&value)?);
+            }
+            Ok(out)
+        }
+        Filter::Optional(term) => Ok(eval(term, input).unwrap_or_default()),
+        Filter::Array(inner) => Ok(vec![JsonValue::Array(match inner {
+            Some(inner) => eval(inner, input)?,
+            None => Vec::new(),
+        })]),
+        Filter::Pipe(lhs, rhs) => {
+            let mut out = Vec::new();
+            for value in eval(lhs, input)? {
+                out.extend(eval(rhs, &value)?);
+            }
+            Ok(out)
+        }
+        Filter::Comma(lhs, rhs) => {
+            let mut out = eval(lhs, input)?;
+            out.extend(eval(rhs, input)?);
+            Ok(out)
+        }
+        Filter::Binary(BinOp::And, lhs, rhs) => {
+            let mut out = Vec::new();
+            for l in eval(lhs, input)? {
+                if !truthy(&l) {
+                    out.push(JsonValue::Bool(false));
+                    continue;
+                }
+                for r in eval(rhs, input)? {
+                    out.push(JsonValue::Bool(truthy(&r)));
+                }
+            }
+            Ok(out)
+        }
+        Filter::Binary(BinOp::Or, lhs, rhs) => {
+            let mut out = Vec::new();
+            for l in eval(lhs, input)? {
+                if truthy(&l) {
+                    out.push(JsonValue::Bool(true));
+                    continue;
+                }
+                for r in eval(rhs, input)? {
+                    out.push(JsonValue::Bool(truthy(&r)));
+                }
+            }
+            Ok(out)
+        }
+        Filter::Binary(op, lhs, rhs) => {
+            let rights = eval(rhs, input)?;
+            let mut out = Vec::new();
+            for l in eval(lhs, input)? {
+                for r in &rights {
+                    let ord = compare(&l, r);
+                    out.push(match op {
+                        BinOp::Eq => JsonValue::Bool(ord == Ordering::Equal),
+                        BinOp::Ne => JsonValue::Bool(ord != Ordering::Equal),
+                        BinOp::Lt => JsonValue::Bool(ord == Ordering::Less),
+                        BinOp::Le => JsonValue::Bool(ord != Ordering::Greater),
+                        BinOp::Gt => JsonValue::Bool(ord == Ordering::Greater),
+                        BinOp::Ge => JsonValue::Bool(ord != Ordering::Less),
+                        _ => arithmetic(*op, &l, r)?,
+                    });
+                }
+            }
+            Ok(out)
+        }
+        Filter::Neg(term) => eval(term, input)?
+            .iter()
+            .map(|v| arithmetic(BinOp::Sub, &JsonValue::from(0), v))
+            .collect(),
+        Filter::Assign(lhs, rhs) => {
+            let paths = eval_paths(lhs, input, Vec::new())?;
+            let mut out = Vec::new();
+            for value in eval(rhs, input)? {
+                let mut doc = input.clone();
+                for path in &paths {
+                    set_path(&mut doc, path, value.clone())?;
+                }
+                out.push(doc);
+            }
+            Ok(out)
+        }
+        Filter::Update(lhs, rhs) => {
+            let mut doc = input.clone();
+            let mut removed = Vec::new();
+            for path in eval_paths(lhs, input, Vec::new())? {
+                let current = get_path(&doc, &path);
+                match eval(rhs, &current)?.into_iter().next() {
+                    Some(value) => set_path(&mut doc, &path, value)?,
+                    None => removed.push(path),
+                }
+            }
+            delete_paths(&mut doc, removed);
+            Ok(vec![doc])
+        }
+        Filter::Call(name, args) => call(name, args, input),
+    }
+}
+
+fn first(filter: &Filter, input: &JsonValue) -> Result<JsonValue> {
+    eval(filter, input)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Expression produced no value"))
+}
+
+fn call(
+    name: &str,
+    args: &[Filter],
+    input: &JsonValue,
+) -> Result<Vec<JsonValue>> {
+    match (name, args) {
+        ("empty", []) => Ok(Vec::new()),
+        ("not", []) => Ok(vec![JsonValue::Bool(!truthy(input))]),
+        ("type", []) => Ok(vec![JsonValue::from(type_name(input))]),
+        ("length", []) => Ok(vec![match input {
+            JsonValue::Null => JsonValue::from(0),
+            JsonValue::Bool(_) => bail!("boolean has no length"),
+            JsonValue::Number(n) => number(n.as_f64().unwrap_or(0.0).abs()),
+            JsonValue::String(s) => JsonValue::from(s.chars().count()),
+            JsonValue::Array(items) => JsonValue::from(items.len()),
+            JsonValue::Object(map) => JsonValue::from(map.len()),
+        }]),
+        ("keys", []) => Ok(vec![match input {
+            JsonValue::Object(map) => {
+                let mut keys: Vec<_> = map.keys().cloned().collect();
+                keys.sort();
+                JsonValue::from(keys)
+            }
+            JsonValue::Array(items) => {
+                JsonValue::from((0..items.len()).collect::<Vec<_>>())
+            }
+            other => bail!("{} has no keys", type_name(other)),
+        }]),
+        ("has", [key]) => eval(key, input)?
+            .iter()
+            .map(|key| match (input, key) {
+                (JsonValue::Object(map), JsonValue::String(k)) => {
+                    Ok(JsonValue::Bool(map.contains_key(k)))
+                }
+                (JsonValue::Array(items), JsonValue::Number(n)) => {
+                    let n = n.as_f64().unwrap_or(-1.0);
+                    Ok(JsonValue::Bool(n >= 0.0 && (n as usize) < items.len()))
+                }
+                _ => Err(anyhow!(
+                    "Cannot check whether {} has a {} key",
+                    type_name(input),
+                    type_name(key)
+                )),
+            })
+            .collect(),
+        ("select", [cond]) => Ok(eval(cond, input)?
+            .iter()
+            .filter(|v| truthy(v))
+            .map(|_| input.clone())
+            .collect()),
+        ("map", [f]) => {
+            let mut out = Vec::new();
+            for item in iterate_value(input)? {
+                out.extend(eval(f, &item)?);
+            }
+            Ok(vec![JsonValue::Array(out)])
+        }
+        ("del", [f]) => {
+            let mut doc = input.clone();
+            delete_paths(&mut doc, eval_paths(f, input, Vec::new())?);
+            Ok(vec![doc])
+        }
+        _ => Err(anyhow!("Unknown function {name}/{}", args.len())),
+    }
+}
+
+/// Evaluates a path expression, returning the location of every output
+/// relative to the document root instead of the values themselves.
+fn eval_paths(
+    filter: &Filter,
+    root: &JsonValue,
+    at: Vec<Seg>,
+) -> Result<Vec<Vec<Seg>>> {
+    let current = get_path(root, &at);
+    match filter {
+        Filter::Identity => Ok(vec![at]),
+        Filter::Recurse => {
+            let mut out = Vec::new();
+            collect_paths(&current, at, &mut out);
+            Ok(out)
+        }
+        Filter::Field(term, name) => {
+            extend_paths(root, eval_paths(term, root, at)?, &|v| match v {
+                JsonValue::Object(_) | JsonValue::Null => {
+                    Ok(vec![Seg::Key(name.clone())])
+                }
+                other => bail!("Cannot index {} with string", type_name(other)),
+            })
+        }
+        Filter::Index(term, key) => {
+            let keys = eval(key, &current)?;
+            extend_paths(root, eval_paths(term, root, at)?, &|v| {
+                keys.iter()
+                    .map(|key| match (v, key) {
+                        (
+                            JsonValue::Object(_) | JsonValue::Null,
+                            JsonValue::String(k),
+                        ) => Ok(Seg::Key(k.clone())),
+                        (JsonValue::Array(items), JsonValue::Number(n)) => {
+                            let n = n.as_f64().unwrap_or(0.0);
+                            resolve_index(items.len(), n)
+                                .or((n >= 0.0).then_some(n as usize))
+                                .map(Seg::Index)
+                                .ok_or_else(|| {
+                                    anyhow!("Index {n} out of range")
+                                })
+                        }
+                        (JsonValue::Null, JsonValue::Number(n)) => {
+                            Ok(Seg::Index(
+                                n.as_f64().unwrap_or(0.0).max(0.0) as usize
+                            ))
+                        }
+                        _ => Err(anyhow!(
+                            "Cannot index {} with {}",
+                            type_name(v),
+                            type_name(key)
+                        )),
+                    })
+                    .collect()
+            })
+        }
+        Filter::Iterate(term) => {
+            extend_paths(root, eval_paths(term, root, at)?, &|v| match v {
+                JsonValue::Array(items) => {
+                    Ok((0..items.len()).map(Seg::Index).collect())
+                }
+                JsonValue::Object(map) => {
+                    Ok(map.keys().cloned().map(Seg::Key).collect())
+                }
+                other => bail!("Cannot iterate over {}", type_name(other)),
+            })
+        }
+        Filter::Optional(term) => {
+            Ok(eval_paths(term, root, at).unwrap_or_default())
+        }
+        Filter::Pipe(lhs, rhs) => {
+            let mut out = Vec::new();
+            for path in eval_paths(lhs, root, at)? {
+                out.extend(eval_paths(rhs, root, path)?);
+            }
+            Ok(out)
+        }
+        Filter::Comma(lhs, rhs) => {
+            let mut out = eval_paths(lhs, root, at.clone())?;
+            out.extend(eval_paths(rhs, root, at)?);
+            Ok(out)
+        }
+        Filter::Call(name, args) => match (name.as_str(), args.as_slice()) {
+            ("empty", []) => Ok(Vec::new()),
+            ("select", [cond]) => Ok(eval(cond, &current)?
+                .iter()
+                .filter(|v| truthy(v))
+                .map(|_| at.clone())
+                .collect()),
+            _ => Err(anyhow!("{name} is not a valid path expression")),
+        },
+        _ => Err(anyhow!("Invalid path expression")),
+    }
+}
+
+fn extend_paths(
+    root: &JsonValue,
+    paths: Vec<Vec<Seg>>,
+    segments: &dyn Fn(&JsonValue) -> Result<Vec<Seg>>,
+) -> Result<Vec<Vec<Seg>>> {
+    let mut out = Vec::new();
+    for path in paths {
+        for seg in segments(&get_path(root, &path))? {
+            let mut next = path.clone();
+            next.push(seg);
+            out.push(next);
+        }
+    }
+    Ok(out)
+}
+
+fn collect_paths(value: &JsonValue, at: Vec<Seg>, out: &mut Vec<Vec<Seg>>) {
+    out.push(at.clone());
+    match value {
+        JsonValue::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                let mut next = at.clone();
+                next.push(Seg::Index(i));
+                collect_paths(item, next, out);
+            }
+        }
+        JsonValue::Object(map) => {
+            for (key, item) in map {
+                let mut next = at.clone();
+                next.push(Seg::Key(key.clone()));
+                collect_paths(item, next, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn get_path(root: &JsonValue, path: &[Seg]) -> JsonValue {
+    let mut current = root;
+    for seg in path {
+        let next = match (current, seg) {
+            (JsonValue::Object(map), Seg::Key(k)) => map.get(k),
+            (JsonValue::Array(items), Seg::Index(i)) => items.get(*i),
+            _ => None,
+        };
+        match next {
+            Some(value) => current = value,
+            None => return JsonValue::Null,
+        }
+    }
+    current.clone()
+}
+
+fn set_path(
+    root: &mut JsonValue,
+    path: &[Seg],
+    value: JsonValue,
+) -> Result<()> {
+    let mut current = root;
+    for seg in path {
+        if current.is_null() {
+            *current = match seg {
+                Seg::Key(_) => JsonValue::Object(Map::new()),
+                Seg::Index(_) => JsonValue::Array(Vec::new()),
+            };
+        }
+        current = match (current, seg) {
+            (JsonValue::Object(map), Seg::Key(k)) => {
+                map.entry(k.clone()).or_insert(JsonValue::Null)
+            }
+            (JsonValue::Array(items), Seg::Index(i)) => {
+                if items.len() <= *i {
+                    let padding = crate::config::get().max_array_padding();
+                    if *i - items.len() > padding {
+                        return Err(crate::Error::Limit(format!(
+                            "Index {i} is more than {padding} past the end \
+                             of an array of {}",
+                            items.len()
+                        ))
+                        .into());
+                    }
+                    items.resize(*i + 1, JsonValue::Null);
+                }
+                &mut items[*i]
+            }
+            (other, _) => bail!("Cannot assign into {}", type_name(other)),
+        };
+    }
+    *current = value;
+    Ok(())
+}
+
+fn get_path_mut<'a>(
+    root: &'a mut JsonValue,
+    path: &[Seg],
+) -> Option<&'a mut JsonValue> {
+    path.iter()
+        .try_fold(root, |current, seg| match (current, seg) {
+            (JsonValue::Object(map), Seg::Key(k)) => map.get_mut(k),
+            (JsonValue::Array(items), Seg::Index(i)) => items.get_mut(*i),
+            _ => None,
+        })
+}
+
+fn delete_paths(root: &mut JsonValue, mut paths: Vec<Vec<Seg>>) {
+    // Remove the deepest and right-most entries first so that array indices
+    // of the remaining paths stay valid.
+    paths.sort_by(|a, b| {
+        let key = |p: &Vec<Seg>| {
+            p.iter()
+                .map(|s| match s {
+                    Seg::Index(i) => (*i, String::new()),
+                    Seg::Key(k) => (0, k.clone()),
+                })
+                .collect::<Vec<_>>()
+        };
+        key(b).cmp(&key(a))
+    });
+    paths.dedup();
+    for path in paths {
+        let Some((last, parents)) = path.split_last() else {
+            *root = JsonValue::Null;
+            continue;
+        };
+        let Some(current) = get_path_mut(root, parents) else {
+            continue;
+        };
+        match (current, last) {
+            (JsonValue::Object(map), Seg::Key(k)) => {
+                map.shift_remove(k);
+            }
+            (JsonValue::Array(items), Seg::Index(i)) if *i < items.len() => {
+                items.remove(*i);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn query(source: &str, input: JsonValue) -> Vec<JsonValue> {
+        let filter = parse(source).expect("filter should parse");
+        run(&filter, &input).expect("filter should run")
+    }
+
+    fn failure(source: &str, input: JsonValue) -> String {
+        parse(source)
+            .and_then(|filter| run(&filter, &input))
+            .expect_err("filter should fail")
+            .to_string()
+    }
+
+    #[test]
+    fn slices_with_negative_indices() {
+        let input = json!([0, 1, 2, 3, 4]);
+        assert_eq!(query(".[-1]", input.clone()), [json!(4)]);
+        assert_eq!(query(".[-2:]", input.clone()), [json!([3, 4])]);
+        assert_eq!(query(".[:-3]", input.clone()), [json!([0, 1])]);
+        assert_eq!(query(".[1:-1]", input.clone()), [json!([1, 2, 3])]);
+        assert_eq!(query(".[-10:2]", input.clone()), [json!([0, 1])]);
+        assert_eq!(query(".[3:1]", input.clone()), [json!([])]);
+        assert_eq!(query(".[-9]", input), [JsonValue::Null]);
+        assert_eq!(query(".[1:3]", json!("abcd")), [json!("bc")]);
+    }
+
+    #[test]
+    fn recurse_visits_every_value_depth_first() {
+        assert_eq!(
+            query("..", json!({"a": [1, {"b": 2}]})),
+            [
+                json!({"a": [1, {"b": 2}]}),
+                json!([1, {"b": 2}]),
+                json!(1),
+                json!({"b": 2}),
+                json!(2),
+            ]
+        );
+        assert_eq!(query("[..] | length", json!([1, [2, [3]]])), [json!(6)]);
+    }
+
+    #[test]
+    fn select_with_each_comparison() {
+        let input = json!([1, 2, 3]);
+        let cases = [
+            ("==", json!([2])),
+            ("!=", json!([1, 3])),
+            ("<", json!([1])),
+            ("<=", json!([1, 2])),
+            (">", json!([3])),
+            (">=", json!([2, 3])),
+        ];
+        for (op, expected) in cases {
+            let source = format!("[.[] | select(. {op} 2)]");
+            assert_eq!(query(&source, input.clone()), [expected], "{op}");
+        }
+        assert_eq!(
+            query(
+                r#"[.[] | select(.n > 1 and .s == "x")] | length"#,
+                json!([{"n": 2, "s": "x"}, {"n": 2, "s": "y"}, {"n": 0, "s": "x"}])
+            ),
+            [json!(1)]
+        );
+    }
+
+    #[test]
+    fn comparisons_order_values_across_types() {
+        assert_eq!(query("null < false", json!(null)), [json!(true)]);
+        assert_eq!(query("1 < \"a\"", json!(null)), [json!(true)]);
+        assert_eq!(query("\"b\" < []", json!(null)), [json!(true)]);
+        assert_eq!(query("[] < .", json!({})), [json!(true)]);
+    }
+
+    #[test]
+    fn map_applies_to_each_element() {
+        assert_eq!(query("map(. + 1)", json!([1, 2])), [json!([2, 3])]);
+        assert_eq!(
+            query("map(.a)", json!([{"a": 1}, {"b": 2}])),
+            [json!([1, null])]
+        );
+        assert_eq!(query("map(.)", json!({"a": 1, "b": 2})), [json!([1, 2])]);
+    }
+
+    #[test]
+    fn length_of_every_type() {
+        assert_eq!(query("length", json!(null)), [json!(0)]);
+        assert_eq!(query("length", json!(-3.5)), [json!(3.5)]);
+        assert_eq!(query("length", json!("héllo")), [json!(5)]);
+        assert_eq!(query("length", json!([1, 2])), [json!(2)]);
+        assert_eq!(query("length", json!({"a": 1})), [json!(1)]);
+        assert!(failure("length", json!(true)).contains("boolean"));
+    }
+
+    #[test]
+    fn keys_of_every_type() {
+        assert_eq!(query("keys", json!({"b": 1, "a": 2})), [json!(["a", "b"])]);
+        assert_eq!(query("keys", json!(["x", "y"])), [json!([0, 1])]);
+        for input in [json!(null), json!(true), json!(1), json!("s")] {
+            failure("keys", input);
+        }
+    }
+
+    #[test]
+    fn assignment_and_deletion() {
+        assert_eq!(query(".a.b = 1", json!({})), [json!({"a": {"b": 1}})]);
+        assert_eq!(query(".[] |= . + 1", json!([1, 2])), [json!([2, 3])]);
+        assert_eq!(query("del(.[0, 2])", json!([1, 2, 3])), [json!([2])]);
+    }
+
+    #[test]
+    fn parse_errors_are_reported() {
+        for source in ["", ".[", ".a |", "map(", "[1,", ".[1:2:3]", "\"open"] {
+            assert!(parse(source).is_err(), "{source:?} should not parse");
+        }
+        assert!(failure("nosuchfn", json!(null)).contains("nosuchfn"));
+    }
+
+    #[test]
+    fn assignment_past_the_end_is_bounded() {
+        assert_eq!(query(".[3] = 1", json!([0])), [json!([0, null, null, 1])]);
+        let padding = MAX_ARRAY_PADDING;
+        assert_eq!(
+            query(&format!(".[{padding}] = 1"), json!([]))[0]
+                .as_array()
+                .map(Vec::len),
+            Some(padding + 1)
+        );
+        let filter = parse(".[1000000000000000] = 1").unwrap();
+        let err = run(&filter, &json!([])).expect_err("index is too far out");
+        assert_eq!(crate::ErrorKind::of(&err), crate::ErrorKind::Limit);
+        assert!(
+            failure(".a[100000000000000000000] = 1", json!({}))
+                .contains("past the end"),
+            "huge indices do not overflow"
+        );
+    }
+}
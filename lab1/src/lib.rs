@@ -1,45 +1,192 @@
-use fs2::free_space;
 use std::ffi::OsString;
 use std::fs::{self, File};
-use std::io::{self, Read, Write};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::{anyhow, Context, Result};
+use clap::Parser;
 use serde_json::Value as JsonValue;
 use xmltree::Element;
 use zip::write::ExtendedFileOptions;
 use zip::{read::ZipArchive, write::FileOptions, ZipWriter};
 
+mod audit;
+mod c14n;
+mod cli;
+mod config;
+mod convert;
+mod du;
+mod editor;
+mod error;
+mod jcs;
+mod json_query;
+mod manifest;
+mod ndjson;
+mod network;
+mod process;
+mod prompt;
+mod quota;
+mod script;
+mod signing;
+mod system;
+mod tui;
+mod watch;
+mod xml_diff;
+mod xml_edit;
+mod xml_safe;
+mod xpath;
+mod xsd;
+
+pub use error::{Error, ErrorKind};
+
+/// Set by `--dry-run`: mutating operations validate everything as usual and
+/// report what they would change instead of touching the filesystem.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Set by `--json-errors`.
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Prints `err` to stderr and returns the exit status for it (see
+/// [`ErrorKind`]). The report is JSON with `--json-errors` or when
+/// `output.format` is `json`.
+pub fn report_error(err: &anyhow::Error) -> i32 {
+    if JSON_ERRORS.load(Ordering::Relaxed) || config::get().json_output() {
+        eprintln!("{}", error::to_json(err));
+    } else {
+        eprintln!("Error: {err:?}");
+    }
+    ErrorKind::of(err).exit_code()
+}
+
 pub fn run() -> Result<()> {
+    let cli = cli::Cli::parse();
+    JSON_ERRORS.store(cli.json_errors, Ordering::Relaxed);
+    config::init(&cli.settings)?;
+    DRY_RUN.store(cli.dry_run, Ordering::Relaxed);
+    match cli.command {
+        Some(command) => cli::dispatch(command),
+        None => interactive(),
+    }
+}
+
+fn interactive() -> Result<()> {
     loop {
         println!("\nOS Utility Lab (osul)");
-        println!("1. View disk info");
+        if dry_run() {
+            println!("(dry run: nothing will be changed)");
+        }
+        println!("1. View disk and system info");
         println!("2. Filesystem manipulation command utilities");
         println!("3. JSON manipulation command utilities");
         println!("4. XML manipulation command utilities");
         println!("5. Zip files command utilities");
+        println!("6. Convert between JSON, XML, YAML, TOML and CSV");
+        println!("7. JSON Lines (NDJSON) command utilities");
+        println!("8. Watch disk usage");
+        println!("9. Processes");
+        println!("10. Network interfaces and sockets");
+        println!("11. Verify audit log");
+        println!("12. Show configuration");
+        println!("13. Run a script");
+        println!("14. Browse files (full screen)");
         println!("0. Exit");
 
-        match get_choice()? {
-            1 => cmd_disks()?,
-            2 => file_menu()?,
-            3 => json_menu()?,
-            4 => xml_menu()?,
-            5 => zip_menu()?,
-            0 => break,
-            _ => println!("Invalid choice, try again."),
+        let choice = match get_choice() {
+            Err(e) if e.is::<prompt::Cancelled>() => continue,
+            choice => choice?,
+        };
+        if choice == 0 {
+            break;
+        }
+        // Ctrl-C at any prompt below abandons the action, not osul.
+        match main_menu_action(choice) {
+            Err(e) if e.is::<prompt::Cancelled>() => println!("Cancelled"),
+            result => result?,
         }
     }
     Ok(())
 }
 
-fn sanitize_path(input: &Path, allow_nonexistent: bool) -> Result<PathBuf> {
+fn main_menu_action(choice: u32) -> Result<()> {
+    match choice {
+        1 => {
+            cmd_disks(None, false)?;
+            println!();
+            cmd_system(false)?;
+        }
+        2 => file_menu()?,
+        3 => json_menu()?,
+        4 => xml_menu()?,
+        5 => zip_menu()?,
+        6 => {
+            let input = get_input("Enter input file path")?;
+            let output = get_input("Enter output file path")?;
+            convert_file(
+                &PathBuf::from(input),
+                &PathBuf::from(output),
+                None,
+                None,
+                false,
+            )?;
+        }
+        7 => jsonl_menu()?,
+        8 => {
+            let interval = get_input("Refresh interval in seconds [5]")?;
+            let min_free =
+                get_input("Alert below free space (e.g. 1G, empty for none)")?;
+            disk_watch(watch::Options {
+                interval: std::time::Duration::from_secs(
+                    interval.parse().unwrap_or(5),
+                ),
+                warn_percent: 80.0,
+                critical_percent: 90.0,
+                min_free: match min_free.as_str() {
+                    "" => None,
+                    size => Some(system::parse_size(size)?),
+                },
+                alert_log: None,
+                mount: None,
+                count: None,
+            })?;
+        }
+        9 => process_menu()?,
+        10 => network_menu()?,
+        11 => audit_verify(None)?,
+        12 => config_show(false)?,
+        13 => {
+            let path = get_input("Enter script path")?;
+            script_run(Path::new(&path), &[], false, false)?;
+        }
+        14 => tui_browse()?,
+        _ => println!("Invalid choice, try again."),
+    }
+    Ok(())
+}
+
+/// `sandbox.root`, or the working directory if unset; every path osul
+/// touches must lie under it.
+fn sandbox_root() -> Result<PathBuf> {
+    if let Some(root) = config::get().sandbox_root() {
+        return std::fs::canonicalize(&root).with_context(|| {
+            format!("failed to canonicalize sandbox root '{}'", root.display())
+        });
+    }
     let cwd =
         std::env::current_dir().context("getting current working directory")?;
-    let canonical_cwd = std::fs::canonicalize(&cwd).with_context(|| {
+    std::fs::canonicalize(&cwd).with_context(|| {
         format!("failed to canonicalize cwd '{}'", cwd.display())
-    })?;
+    })
+}
+
+fn sanitize_path(input: &Path, allow_nonexistent: bool) -> Result<PathBuf> {
+    let cwd =
+        std::env::current_dir().context("getting current working directory")?;
+    let canonical_root = sandbox_root()?;
 
     let abs = if input.is_absolute() {
         input.to_path_buf()
@@ -51,14 +198,15 @@ fn sanitize_path(input: &Path, allow_nonexistent: bool) -> Result<PathBuf> {
         let canonical = std::fs::canonicalize(&abs).with_context(|| {
             format!("failed to canonicalize '{}'", abs.display())
         })?;
-        if canonical.starts_with(&canonical_cwd) {
+        if canonical.starts_with(&canonical_root) {
             return Ok(canonical);
         } else {
-            return Err(anyhow!(
-                "Access denied: '{}' is outside of working directory '{}'",
+            return Err(Error::Sandbox(format!(
+                "Access denied: '{}' is outside of the sandbox '{}'",
                 canonical.display(),
-                canonical_cwd.display(),
-            ));
+                canonical_root.display(),
+            ))
+            .into());
         }
     }
 
@@ -83,38 +231,61 @@ fn sanitize_path(input: &Path, allow_nonexistent: bool) -> Result<PathBuf> {
             format!("failed to canonicalize ancestor '{}'", ancestor.display())
         })?
     } else {
-        canonical_cwd.clone()
+        canonical_root.clone()
     };
 
     for comp in missing.iter().rev() {
         canonical_base.push(comp);
     }
 
-    if canonical_base.starts_with(&canonical_cwd) {
+    if canonical_base.starts_with(&canonical_root) {
         Ok(canonical_base)
     } else {
-        Err(anyhow!(
-            "Access denied: '{}' is outside of working directory '{}'",
+        Err(Error::Sandbox(format!(
+            "Access denied: '{}' is outside of the sandbox '{}'",
             canonical_base.display(),
-            canonical_cwd.display(),
+            canonical_root.display(),
         ))
+        .into())
     }
 }
 
+/// End of input counts as choosing 0, which leaves every menu.
 fn get_choice() -> Result<u32> {
-    print!("> ");
-    io::stdout().flush()?;
-    let mut buf = String::new();
-    io::stdin().read_line(&mut buf)?;
-    Ok(buf.trim().parse::<u32>().unwrap_or(999))
+    Ok(prompt::read_line("> ", prompt::Completion::None, false)?
+        .map_or(0, |choice| choice.parse::<u32>().unwrap_or(999)))
 }
 
+/// Tab completes paths inside the sandbox. For paths and short answers;
+/// they are kept in the history.
 fn get_input(prompt: &str) -> Result<String> {
-    print!("{prompt}: ");
-    io::stdout().flush()?;
-    let mut buf = String::new();
-    io::stdin().read_line(&mut buf)?;
-    Ok(buf.trim().to_string())
+    prompt_input(prompt, prompt::Completion::Paths, true)
+}
+
+/// For content, values and expressions, which may be sensitive and are
+/// left out of the history.
+fn get_text(prompt: &str) -> Result<String> {
+    prompt_input(prompt, prompt::Completion::None, false)
+}
+
+fn prompt_input(
+    prompt: &str,
+    completion: prompt::Completion,
+    remember: bool,
+) -> Result<String> {
+    prompt::read_line(&format!("{prompt}: "), completion, remember)?
+        .ok_or_else(|| prompt::Cancelled.into())
+}
+
+/// Asks for an entry of the archive at `archive`, completing entry names.
+fn get_entry_name(prompt: &str, archive: &str) -> Result<String> {
+    let names = sanitize_path(Path::new(archive), false)
+        .ok()
+        .and_then(|path| File::open(path).ok())
+        .and_then(|file| ZipArchive::new(file).ok())
+        .map(|archive| archive.file_names().map(str::to_string).collect())
+        .unwrap_or_default();
+    prompt_input(prompt, prompt::Completion::Names(names), true)
 }
 
 fn file_menu() -> Result<()> {
@@ -124,6 +295,11 @@ fn file_menu() -> Result<()> {
         println!("2. Write to file");
         println!("3. Read file");
         println!("4. Delete file");
+        println!("5. Analyze disk usage");
+        println!("6. Quota status");
+        println!("7. Hash files");
+        println!("8. Create manifest");
+        println!("9. Verify manifest");
         println!("0. Cancel");
 
         match get_choice()? {
@@ -134,7 +310,7 @@ fn file_menu() -> Result<()> {
             }
             2 => {
                 let path = get_input("Enter file path")?;
-                let content = get_input("Enter content")?;
+                let content = get_text("Enter content")?;
                 file_write(&PathBuf::from(path), &content)?;
                 return Ok(());
             }
@@ -148,6 +324,56 @@ fn file_menu() -> Result<()> {
                 file_delete(&PathBuf::from(path))?;
                 return Ok(());
             }
+            5 => {
+                let path = get_input("Enter directory (empty for .)")?;
+                let depth = get_input("Max depth to list (empty for all)")?;
+                let exclude = get_input("Exclude globs (comma-separated)")?;
+                let options = du::Options {
+                    max_depth: match depth.as_str() {
+                        "" => None,
+                        depth => Some(depth.parse().context("Invalid depth")?),
+                    },
+                    top: 10,
+                    exclude: exclude
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|glob| !glob.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                };
+                let path = if path.is_empty() { "." } else { &path };
+                disk_usage(Path::new(path), &options, false)?;
+                return Ok(());
+            }
+            6 => {
+                quota_status(false)?;
+                return Ok(());
+            }
+            7 => {
+                let path = get_input("Enter file or directory path")?;
+                let algorithm = get_algorithm()?;
+                hash_paths(&[PathBuf::from(path)], algorithm, false)?;
+                return Ok(());
+            }
+            8 => {
+                let dir = get_input("Enter directory path")?;
+                let output = get_input("Manifest file (empty to print)")?;
+                let algorithm = get_algorithm()?;
+                let output =
+                    (!output.is_empty()).then(|| PathBuf::from(output));
+                manifest_create(
+                    Path::new(&dir),
+                    output.as_deref(),
+                    algorithm,
+                    None,
+                )?;
+                return Ok(());
+            }
+            9 => {
+                let path = get_input("Enter manifest path")?;
+                manifest_verify(Path::new(&path), None, None, false)?;
+                return Ok(());
+            }
             0 => return Ok(()),
             _ => println!("Invalid choice"),
         }
@@ -161,6 +387,11 @@ fn json_menu() -> Result<()> {
         println!("2. New JSON object");
         println!("3. Read JSON file");
         println!("4. Delete JSON file");
+        println!("5. Query/transform JSON file");
+        println!("6. Canonical JSON (RFC 8785) and SHA-256 digest");
+        println!("7. Generate Ed25519 signing key");
+        println!("8. Sign JSON file");
+        println!("9. Verify JSON signature");
         println!("0. Cancel");
 
         match get_choice()? {
@@ -169,7 +400,7 @@ fn json_menu() -> Result<()> {
                 let choice =
                     get_input("Provide content (c) or open editor (e)?")?;
                 if choice == "c" {
-                    let content = get_input("Enter JSON content")?;
+                    let content = get_text("Enter JSON content")?;
                     json_create(PathBuf::from(path), Some(content), false)?;
                 } else {
                     json_create(PathBuf::from(path), None, true)?;
@@ -183,7 +414,7 @@ fn json_menu() -> Result<()> {
             }
             3 => {
                 let path = get_input("Enter file path")?;
-                json_read(&PathBuf::from(path))?;
+                json_read(&PathBuf::from(path), false)?;
                 return Ok(());
             }
             4 => {
@@ -191,6 +422,38 @@ fn json_menu() -> Result<()> {
                 file_delete(&PathBuf::from(path))?;
                 return Ok(());
             }
+            5 => {
+                let path = get_input("Enter file path")?;
+                let filter = get_text("Enter filter (e.g. .items[] | .name)")?;
+                let output = get_input("Output file (empty to print results)")?;
+                let output =
+                    (!output.is_empty()).then(|| PathBuf::from(output));
+                json_query(&PathBuf::from(path), &filter, output.as_deref())?;
+                return Ok(());
+            }
+            6 => {
+                let path = get_input("Enter file path")?;
+                json_read(&PathBuf::from(path.clone()), true)?;
+                json_digest(&PathBuf::from(path))?;
+                return Ok(());
+            }
+            7 => {
+                let path = get_input("Enter secret key path")?;
+                json_keygen(&PathBuf::from(path))?;
+                return Ok(());
+            }
+            8 => {
+                let path = get_input("Enter file path")?;
+                let key = get_input("Enter secret key path")?;
+                json_sign(&PathBuf::from(path), &PathBuf::from(key), None)?;
+                return Ok(());
+            }
+            9 => {
+                let path = get_input("Enter file path")?;
+                let key = get_input("Enter public key path")?;
+                json_verify(&PathBuf::from(path), &PathBuf::from(key), None)?;
+                return Ok(());
+            }
             0 => return Ok(()),
             _ => println!("Invalid choice"),
         }
@@ -206,11 +469,11 @@ fn json_interactive(path: &Path) -> Result<()> {
     println!("Enter key-value pairs. Leave key empty to finish.\n");
 
     loop {
-        let key = get_input("Key (empty to finish)")?;
+        let key = get_text("Key (empty to finish)")?;
         if key.is_empty() {
             break;
         }
-        let value_str = get_input("Value")?;
+        let value_str = get_text("Value")?;
 
         // Try to parse as JSON literal (number, bool, null, array, or object)
         let value: JsonValue = match serde_json::from_str(&value_str) {
@@ -222,11 +485,66 @@ fn json_interactive(path: &Path) -> Result<()> {
 
     let json_obj = JsonValue::Object(map);
     let ser = serde_json::to_vec_pretty(&json_obj)?;
-    fs::write(&path, ser)?;
-    println!("Created interactive JSON file: {}", path.display());
+    if write_file("json_create", &path, ser)? {
+        println!("Created interactive JSON file: {}", path.display());
+    }
     Ok(())
 }
 
+fn jsonl_menu() -> Result<()> {
+    loop {
+        println!("\nJSON Lines Utilities");
+        println!("1. Count records");
+        println!("2. Show first records");
+        println!("3. Show last records");
+        println!("4. Filter records by JSON pointer");
+        println!("5. Validate records");
+        println!("0. Cancel");
+
+        match get_choice()? {
+            1 => {
+                let path = get_input("Enter file path")?;
+                jsonl_count(&PathBuf::from(path))?;
+                return Ok(());
+            }
+            choice @ (2 | 3) => {
+                let path = get_input("Enter file path")?;
+                let n = get_input("Number of records")?.parse::<usize>()?;
+                if choice == 2 {
+                    jsonl_head(&PathBuf::from(path), n)?;
+                } else {
+                    jsonl_tail(&PathBuf::from(path), n)?;
+                }
+                return Ok(());
+            }
+            4 => {
+                let path = get_input("Enter file path")?;
+                let pointer = get_text("JSON pointer (e.g. /user/name)")?;
+                let value =
+                    get_text("Expected JSON value (empty to test presence)")?;
+                let value = (!value.is_empty()).then_some(value);
+                let output = get_input("Output file (empty to print records)")?;
+                let output =
+                    (!output.is_empty()).then(|| PathBuf::from(output));
+                jsonl_filter(
+                    &PathBuf::from(path),
+                    &pointer,
+                    value.as_deref(),
+                    output.as_deref(),
+                )?;
+                return Ok(());
+            }
+            5 => {
+                let path = get_input("Enter file path")?;
+                jsonl_validate(&PathBuf::from(path))?;
+                return Ok(());
+            }
+            0 => return Ok(()),
+            _ => println!("Invalid choice"),
+        }
+    }
+}
+
 fn xml_menu() -> Result<()> {
     loop {
         println!("\nXML Utilities");
@@ -234,6 +552,15 @@ fn xml_menu() -> Result<()> {
         println!("2. Write/append to XML");
         println!("3. Read XML file");
         println!("4. Delete XML file");
+        println!("5. Build XML interactively");
+        println!("6. Query XML with XPath");
+        println!("7. Set text of matching elements");
+        println!("8. Set attribute on matching elements");
+        println!("9. Insert element at matches");
+        println!("10. Remove matching nodes");
+        println!("11. Validate against an XSD schema");
+        println!("12. Canonical XML (Exclusive C14N) and SHA-256 digest");
+        println!("13. Compare two XML files");
         println!("0. Cancel");
 
         match get_choice()? {
@@ -244,13 +571,13 @@ fn xml_menu() -> Result<()> {
             }
             2 => {
                 let path = get_input("Enter file path")?;
-                let content = get_input("Enter XML content or text")?;
+                let content = get_text("Enter XML content or text")?;
                 xml_write(&PathBuf::from(path), &content)?;
                 return Ok(());
             }
             3 => {
                 let path = get_input("Enter file path")?;
-                xml_read(&PathBuf::from(path))?;
+                xml_read(&PathBuf::from(path), false)?;
                 return Ok(());
             }
             4 => {
@@ -263,41 +590,311 @@ fn xml_menu() -> Result<()> {
                 xml_interactive(&PathBuf::from(path))?;
                 return Ok(());
             }
+            6 => {
+                let path = get_input("Enter file path")?;
+                let expr = get_text("Enter XPath (e.g. //item[@id='1'])")?;
+                xml_query(&PathBuf::from(path), &expr)?;
+                return Ok(());
+            }
+            7 => {
+                let path = get_input("Enter file path")?;
+                let expr = get_text("Enter XPath")?;
+                let text = get_text("Enter new text")?;
+                xml_set_text(&PathBuf::from(path), &expr, &text)?;
+                return Ok(());
+            }
+            8 => {
+                let path = get_input("Enter file path")?;
+                let expr = get_text("Enter XPath")?;
+                let name = get_text("Attribute name")?;
+                let value = get_text("Attribute value")?;
+                xml_set_attr(&PathBuf::from(path), &expr, &name, &value)?;
+                return Ok(());
+            }
+            9 => {
+                let path = get_input("Enter file path")?;
+                let expr = get_text("Enter XPath")?;
+                let fragment = get_text("Enter XML element to insert")?;
+                let position = match get_input(
+                    "Position: first, last, before or after (default last)",
+                )?
+                .as_str()
+                {
+                    "first" => xpath::Position::First,
+                    "before" => xpath::Position::Before,
+                    "after" => xpath::Position::After,
+                    _ => xpath::Position::Last,
+                };
+                xml_insert(&PathBuf::from(path), &expr, &fragment, position)?;
+                return Ok(());
+            }
+            10 => {
+                let path = get_input("Enter file path")?;
+                let expr = get_text("Enter XPath")?;
+                xml_remove(&PathBuf::from(path), &expr)?;
+                return Ok(());
+            }
+            11 => {
+                let path = get_input("Enter file path")?;
+                let schema = get_input("Enter schema (.xsd) path")?;
+                xml_validate(&PathBuf::from(path), &PathBuf::from(schema))?;
+                return Ok(());
+            }
+            12 => {
+                let path = get_input("Enter file path")?;
+                xml_read(&PathBuf::from(path.clone()), true)?;
+                xml_digest(&PathBuf::from(path))?;
+                return Ok(());
+            }
+            13 => {
+                let old = get_input("Enter original file path")?;
+                let new = get_input("Enter changed file path")?;
+                xml_compare(&PathBuf::from(old), &PathBuf::from(new))?;
+                return Ok(());
+            }
             0 => return Ok(()),
             _ => println!("Invalid choice"),
         }
     }
 }
 
-fn xml_interactive(path: &Path) -> Result<()> {
-    let path = sanitize_path(path, true)?;
-    println!("\n--- Interactive XML Creator ---");
-    println!("You will create a root element and add child elements.\n");
+fn xml_name_is_valid(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '.' | '_'))
+}
 
-    let root_name = get_input("Enter root element name")?;
-    let mut root = Element::new(root_name.as_str());
+/// Splits `prefix:local`, checking that both parts are valid XML names.
+fn split_qname(qname: &str) -> Result<(Option<&str>, &str)> {
+    let (prefix, local) = match qname.split_once(':') {
+        Some((prefix, local)) => (Some(prefix), local),
+        None => (None, qname),
+    };
+    if !xml_name_is_valid(local)
+        || prefix.is_some_and(|p| !xml_name_is_valid(p))
+    {
+        return Err(anyhow!("'{qname}' is not a valid XML name"));
+    }
+    Ok((prefix, local))
+}
 
-    loop {
-        let tag = get_input("Child tag name (empty to finish)")?;
-        if tag.is_empty() {
-            break;
+/// Looks a prefix (`""` for the default namespace) up on the element at
+/// `cursor` and its ancestors.
+fn resolve_prefix(
+    root: &Element,
+    cursor: &[usize],
+    prefix: &str,
+) -> Option<String> {
+    (0..=cursor.len()).rev().find_map(|depth| {
+        xpath::element_at(root, &cursor[..depth])?
+            .namespaces
+            .as_ref()?
+            .get(prefix)
+            .map(str::to_string)
+    })
+}
+
+fn declare_namespace(element: &mut Element, prefix: &str, uri: &str) {
+    element
+        .namespaces
+        .get_or_insert_with(xmltree::Namespace::empty)
+        .force_put(prefix, uri);
+}
+
+/// Creates an element named `qname` below `cursor`, asking for the URI of a
+/// prefix that is not declared yet and declaring it on the new element.
+fn xml_builder_element(
+    root: Option<&Element>,
+    cursor: &[usize],
+    qname: &str,
+) -> Result<Element> {
+    let (prefix, local) = split_qname(qname)?;
+    let mut element = Element::new(local);
+    let in_scope =
+        |p: &str| root.and_then(|root| resolve_prefix(root, cursor, p));
+    match prefix {
+        Some(prefix) => {
+            let uri = match in_scope(prefix) {
+                Some(uri) => uri,
+                None => {
+                    let uri = get_text(&format!(
+                        "Namespace URI for prefix '{prefix}'"
+                    ))?;
+                    if uri.is_empty() {
+                        return Err(anyhow!(
+                            "Prefix '{prefix}' needs a namespace URI"
+                        ));
+                    }
+                    declare_namespace(&mut element, prefix, &uri);
+                    uri
+                }
+            };
+            element.prefix = Some(prefix.to_string());
+            element.namespace = Some(uri);
         }
-        let value = get_input("Text content")?;
-        let mut child = Element::new(tag.as_str());
-        child
-            .children
-            .push(xmltree::XMLNode::Text(value.to_string()));
-        root.children.push(xmltree::XMLNode::Element(child));
+        None => element.namespace = in_scope(""),
     }
+    Ok(element)
+}
 
-    let mut file = File::create(&path)?;
+fn xml_builder_path(root: &Element, cursor: &[usize]) -> String {
+    (0..=cursor.len())
+        .filter_map(|depth| xpath::element_at(root, &cursor[..depth]))
+        .map(|element| match &element.prefix {
+            Some(prefix) => format!("/{prefix}:{}", element.name),
+            None => format!("/{}", element.name),
+        })
+        .collect()
+}
+
+fn xml_render(root: &Element) -> Result<String> {
+    let mut buf = Vec::new();
     root.write_with_config(
-        &mut file,
+        &mut buf,
         xmltree::EmitterConfig::new().perform_indent(true),
     )?;
+    Ok(String::from_utf8(buf)?)
+}
 
-    println!("Created interactive XML file: {}", path.display());
-    Ok(())
+fn xml_interactive(path: &Path) -> Result<()> {
+    let path = sanitize_path(path, true)?;
+    println!("\n--- Interactive XML Builder ---");
+    println!("Build the document one node at a time, starting at the root.\n");
+
+    let root_name =
+        get_text("Root element name (prefix:name for a namespace)")?;
+    let mut root = xml_builder_element(None, &[], &root_name)?;
+    // Path of element positions from the root to the element being edited.
+    let mut cursor: Vec<usize> = Vec::new();
+
+    loop {
+        println!("\nCurrent element: {}", xml_builder_path(&root, &cursor));
+        println!("1. Add child element and enter it");
+        println!("2. Set attribute");
+        println!("3. Add text");
+        println!("4. Add CDATA section");
+        println!("5. Add comment");
+        println!("6. Declare namespace prefix");
+        println!("7. Go up to parent element");
+        println!("8. Preview document");
+        println!("9. Preview and write file");
+        println!("0. Cancel");
+
+        match get_choice()? {
+            0 => {
+                println!("Discarded the document");
+                return Ok(());
+            }
+            choice => {
+                match xml_builder_step(choice, &mut root, &mut cursor, &path) {
+                    Ok(true) => return Ok(()),
+                    Ok(false) => {}
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+        }
+    }
+}
+
+/// Applies one builder menu choice; returns `true` once the file is written.
+fn xml_builder_step(
+    choice: u32,
+    root: &mut Element,
+    cursor: &mut Vec<usize>,
+    path: &Path,
+) -> Result<bool> {
+    match choice {
+        1 => {
+            let name = get_text("Element name (prefix:name allowed)")?;
+            let child = xml_builder_element(Some(root), cursor, &name)?;
+            let current = xpath::element_at_mut(root, cursor)
+                .ok_or_else(|| anyhow!("Current element vanished"))?;
+            let position = current
+                .children
+                .iter()
+                .filter(|node| node.as_element().is_some())
+                .count();
+            current.children.push(xmltree::XMLNode::Element(child));
+            cursor.push(position);
+        }
+        2 => {
+            let name = get_text("Attribute name (prefix:name allowed)")?;
+            if let (Some(prefix), _) = split_qname(&name)?
+                && prefix != "xml"
+                && resolve_prefix(root, cursor, prefix).is_none()
+            {
+                return Err(anyhow!(
+                    "Prefix '{prefix}' is not declared; declare it first"
+                ));
+            }
+            let value = get_text("Attribute value")?;
+            xpath::element_at_mut(root, cursor)
+                .ok_or_else(|| anyhow!("Current element vanished"))?
+                .attributes
+                .insert(name, value);
+        }
+        3..=5 => {
+            let text = get_text("Content")?;
+            let node = match choice {
+                3 => xmltree::XMLNode::Text(text),
+                4 if text.contains("]]>") => {
+                    return Err(anyhow!("CDATA cannot contain ']]>'"));
+                }
+                4 => xmltree::XMLNode::CData(text),
+                _ if text.contains("--") || text.ends_with('-') => {
+                    return Err(anyhow!(
+                        "Comments cannot contain '--' or end with '-'"
+                    ));
+                }
+                _ => xmltree::XMLNode::Comment(text),
+            };
+            xpath::element_at_mut(root, cursor)
+                .ok_or_else(|| anyhow!("Current element vanished"))?
+                .children
+                .push(node);
+        }
+        6 => {
+            let prefix = get_text("Prefix (empty for the default namespace)")?;
+            if (!prefix.is_empty() && !xml_name_is_valid(&prefix))
+                || prefix.to_ascii_lowercase().starts_with("xml")
+            {
+                return Err(anyhow!("'{prefix}' cannot be used as a prefix"));
+            }
+            let uri = get_text("Namespace URI")?;
+            if uri.is_empty() {
+                return Err(anyhow!("Namespace URI cannot be empty"));
+            }
+            let current = xpath::element_at_mut(root, cursor)
+                .ok_or_else(|| anyhow!("Current element vanished"))?;
+            declare_namespace(current, &prefix, &uri);
+            if prefix.is_empty() && current.prefix.is_none() {
+                current.namespace = Some(uri);
+            }
+        }
+        7 => {
+            if cursor.pop().is_none() {
+                println!("Already at the root element");
+            }
+        }
+        8 => println!("\n{}", xml_render(root)?),
+        9 => {
+            println!("\n{}", xml_render(root)?);
+            let prompt = if path.exists() {
+                format!("Overwrite existing {}? (y/n)", path.display())
+            } else {
+                format!("Write to {}? (y/n)", path.display())
+            };
+            if get_input(&prompt)? == "y" {
+                enforce_xml_schema(root)?;
+                if write_file("xml_create", path, xml_render(root)?)? {
+                    println!("Created XML file: {}", path.display());
+                }
+                return Ok(true);
+            }
+        }
+        _ => println!("Invalid choice"),
+    }
+    Ok(false)
 }
 
 fn zip_menu() -> Result<()> {
@@ -324,7 +921,8 @@ fn zip_menu() -> Result<()> {
             }
             3 => {
                 let archive = get_input("Enter archive path")?;
-                let filename = get_input("Enter filename inside archive")?;
+                let filename =
+                    get_entry_name("Enter filename inside archive", &archive)?;
                 zip_extract(&PathBuf::from(archive), &filename)?;
                 return Ok(());
             }
@@ -339,161 +937,1315 @@ fn zip_menu() -> Result<()> {
     }
 }
 
-fn cmd_disks() -> Result<()> {
-    let disks = sysinfo::Disks::new_with_refreshed_list();
+fn cmd_disks(mount: Option<&Path>, json: bool) -> Result<()> {
+    let disks = system::disks(mount);
+    if json {
+        let disks: Vec<JsonValue> =
+            disks.iter().map(system::DiskInfo::to_json).collect();
+        println!("{}", serde_json::to_string_pretty(&disks)?);
+        return Ok(());
+    }
+    if disks.is_empty() {
+        return Err(anyhow!("No disks mounted at or below the given path"));
+    }
     println!("Logical disks:");
     for disk in &disks {
+        let mut flags = vec![disk.kind.as_str()];
+        if disk.removable {
+            flags.push("removable");
+        }
+        if disk.read_only {
+            flags.push("read-only");
+        }
         println!(
-            "- {} (mounted at {})",
-            disk.name().to_string_lossy(),
-            disk.mount_point().to_string_lossy()
-        );
-        println!(
-            "Filesystem: {}",
-            String::from_utf8_lossy(disk.file_system().as_encoded_bytes())
+            "- {} (mounted at {}) [{}]",
+            disk.name,
+            disk.mount_point.display(),
+            flags.join(", ")
         );
-        println!("Size: {} bytes", disk.total_space());
+        println!("Filesystem: {}", disk.file_system);
+        if let Some(options) = &disk.mount_options {
+            println!("Mount options: {options}");
+        }
         println!(
-            "Used: {} bytes",
-            disk.total_space() - disk.available_space()
+            "Size: {}, used: {} ({}%), available: {}",
+            system::human_bytes(disk.total),
+            system::human_bytes(disk.used()),
+            system::percent(disk.used(), disk.total),
+            system::human_bytes(disk.available)
         );
-        println!("Available: {} bytes", disk.available_space());
+        match &disk.inodes {
+            Some(inodes) => println!(
+                "Inodes: {} of {} used ({}%), {} free",
+                inodes.used(),
+                inodes.total,
+                system::percent(inodes.used(), inodes.total),
+                inodes.free
+            ),
+            None => println!("Inodes: not reported"),
+        }
     }
     Ok(())
 }
 
-fn file_create(path: &Path) -> Result<()> {
-    let path = sanitize_path(path, true)?;
-    if path.exists() {
-        return Err(anyhow!("File '{}' already exists", path.display()));
+fn cmd_system(json: bool) -> Result<()> {
+    let info = system::system_json();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
     }
-    File::create(&path)
-        .with_context(|| format!("Creating file {}", path.display()))?;
-    println!("Created {}", path.display());
+    let text =
+        |value: &JsonValue| value.as_str().unwrap_or("unknown").to_string();
+    let bytes =
+        |value: &JsonValue| system::human_bytes(value.as_u64().unwrap_or(0));
+    let os = &info["os"];
+    println!(
+        "OS: {} ({}), kernel {}, {}",
+        text(&os["long_version"]),
+        text(&os["host_name"]),
+        text(&os["kernel"]),
+        text(&os["arch"])
+    );
+    println!(
+        "Uptime: {}",
+        system::human_duration(info["uptime_seconds"].as_u64().unwrap_or(0))
+    );
+    let load = &info["load_average"];
+    println!("Load average: {} {} {}", load[0], load[1], load[2]);
+    let cpu = &info["cpu"];
+    println!(
+        "CPU: {}, {} logical / {} physical cores, {}% in use",
+        text(&cpu["brand"]),
+        cpu["logical_cores"],
+        cpu["physical_cores"],
+        cpu["usage_percent"]
+    );
+    let memory = &info["memory"];
+    println!(
+        "Memory: {} of {} used ({}%), {} available",
+        bytes(&memory["used_bytes"]),
+        bytes(&memory["total_bytes"]),
+        memory["used_percent"],
+        bytes(&memory["available_bytes"])
+    );
+    let swap = &info["swap"];
+    println!(
+        "Swap: {} of {} used ({}%)",
+        bytes(&swap["used_bytes"]),
+        bytes(&swap["total_bytes"]),
+        swap["used_percent"]
+    );
     Ok(())
 }
 
-fn file_write(path: &Path, content: &str) -> Result<()> {
+fn disk_usage(path: &Path, options: &du::Options, json: bool) -> Result<()> {
     let path = sanitize_path(path, false)?;
-    let mut file = File::create(&path)
-        .with_context(|| format!("Creating/overwriting {}", path.display()))?;
-    file.write_all(content.as_bytes())?;
-    println!("Wrote to {}", path.display());
+    let report = du::analyze(&path, options)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report.to_json())?);
+        return Ok(());
+    }
+    let usage = |usage: &du::Usage| {
+        format!(
+            "{:>10} {:>10}",
+            system::human_bytes(usage.on_disk),
+            system::human_bytes(usage.apparent)
+        )
+    };
+    println!(
+        "{}: {} on disk, {} apparent ({} files, {} directories, {} symlinks, \
+         {} excluded)",
+        path.display(),
+        system::human_bytes(report.total.on_disk),
+        system::human_bytes(report.total.apparent),
+        report.files,
+        report.directories,
+        report.symlinks,
+        report.excluded
+    );
+    println!("\n{:>10} {:>10}  Directory", "On disk", "Apparent");
+    for (dir, total) in &report.listed {
+        println!("{}  {}", usage(total), du::display_relative(dir));
+    }
+    println!("\nLargest files:");
+    for (file, total) in &report.largest_files {
+        println!("{}  {}", usage(total), du::display_relative(file));
+    }
+    println!("\nLargest directories:");
+    for (dir, total) in &report.largest_directories {
+        println!("{}  {}", usage(total), du::display_relative(dir));
+    }
+    println!("\nBy extension:");
+    let mut extensions: Vec<_> = report.extensions.iter().collect();
+    extensions.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.usage.on_disk));
+    for (extension, stats) in extensions {
+        println!(
+            "{}  {extension} ({} files)",
+            usage(&stats.usage),
+            stats.files
+        );
+    }
+    for error in &report.errors {
+        eprintln!("Warning: {error}");
+    }
     Ok(())
 }
 
-fn file_read(path: &Path) -> Result<()> {
-    let path = sanitize_path(path, false)?;
-    let mut content = String::new();
-    let mut file = File::open(&path)
-        .with_context(|| format!("Opening {}", path.display()))?;
-    file.read_to_string(&mut content)?;
-    print!("{content}");
-    Ok(())
+fn disk_watch(mut options: watch::Options) -> Result<()> {
+    if let Some(log) = &options.alert_log {
+        options.alert_log = Some(sanitize_path(log, true)?);
+    }
+    watch::run(&options)
 }
 
-fn file_delete(path: &Path) -> Result<()> {
-    let path = sanitize_path(path, false)?;
-    if path.exists() {
-        fs::remove_file(&path)
-            .with_context(|| format!("Deleting {}", path.display()))?;
-        println!("Deleted {}", path.display());
-        Ok(())
-    } else {
-        Err(anyhow!("File '{}' does not exist", path.display()))
+fn process_menu() -> Result<()> {
+    loop {
+        println!("\nProcesses");
+        println!("1. List processes");
+        println!("2. Show process details");
+        println!("3. Send signal to process");
+        println!("0. Cancel");
+
+        match get_choice()? {
+            1 => {
+                let sort = get_input(
+                    "Sort by (pid, name, user, cpu, mem, start) [cpu]",
+                )?;
+                let sort = match sort.as_str() {
+                    "" => process::SortKey::Cpu,
+                    sort => clap::ValueEnum::from_str(sort, true)
+                        .map_err(|_| anyhow!("Unknown sort key '{sort}'"))?,
+                };
+                let pattern =
+                    get_text("Filter by name or command (empty for all)")?;
+                let pattern = (!pattern.is_empty()).then_some(pattern.as_str());
+                process_list(sort, false, pattern, None, Some(20), false)?;
+                return Ok(());
+            }
+            2 => {
+                let pid = get_input("Enter PID")?;
+                process_show(pid.parse().context("Invalid PID")?, false)?;
+                return Ok(());
+            }
+            3 => {
+                let pid = get_input("Enter PID")?;
+                let signal = get_input("Signal [TERM]")?;
+                let signal = if signal.is_empty() { "TERM" } else { &signal };
+                process_signal(
+                    pid.parse().context("Invalid PID")?,
+                    signal,
+                    false,
+                )?;
+                return Ok(());
+            }
+            0 => return Ok(()),
+            _ => println!("Invalid choice"),
+        }
     }
 }
 
-fn json_create(
-    path_buf: PathBuf,
-    content_: Option<String>,
-    edit: bool,
+fn process_list(
+    sort: process::SortKey,
+    reverse: bool,
+    pattern: Option<&str>,
+    user: Option<&str>,
+    limit: Option<usize>,
+    json: bool,
 ) -> Result<()> {
-    let path = sanitize_path(&path_buf.into_boxed_path(), true)?;
-    if edit {
-        open_in_editor(&path)?;
-        println!("Created via editor: {}", path.display());
+    let mut processes = process::list();
+    process::filter(&mut processes, pattern, user);
+    process::sort(&mut processes, sort, reverse);
+    let shown = limit.unwrap_or(processes.len()).min(processes.len());
+    if json {
+        let processes: Vec<JsonValue> = processes[..shown]
+            .iter()
+            .map(process::ProcessInfo::to_json)
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&processes)?);
         return Ok(());
     }
-    if let Some(content) = content_ {
-        let value: JsonValue = serde_json::from_str(&content)
-            .with_context(|| "CONTENT is not valid JSON")?;
-        let ser = serde_json::to_vec_pretty(&value)?;
-        fs::write(&path, ser)?;
-        println!("Created {} with provided JSON content", path.display());
-        Ok(())
-    } else {
-        Err(anyhow!("Either -c CONTENT or -e must be provided"))
+    println!(
+        "{:>7} {:<10} {:>5} {:>10} {:<20}  Command",
+        "PID", "User", "CPU%", "Memory", "Started"
+    );
+    for process in &processes[..shown] {
+        let user: String = process.user_label().chars().take(10).collect();
+        println!(
+            "{:>7} {:<10} {:>5.1} {:>10} {:<20}  {}",
+            process.pid,
+            user,
+            process.cpu_percent,
+            system::human_bytes(process.memory),
+            system::rfc3339_utc(process.start_time),
+            // One row per process: control characters print as `?`, as in
+            // ps(1).
+            process
+                .command_line()
+                .replace(|c: char| c.is_control(), "?")
+        );
     }
-}
+    if shown < processes.len() {
+        println!("... {} more", processes.len() - shown);
+    }
+    Ok(())
+}
+
+fn process_show(pid: u32, json: bool) -> Result<()> {
+    let process = process::find(pid)?;
+    let open_files = process::open_files(pid);
+    let environment = process::environment(&process);
+    if json {
+        let mut value = process.to_json();
+        value["open_files"] = match &open_files {
+            Ok(files) => files
+                .iter()
+                .map(|(fd, target)| serde_json::json!({ "fd": fd, "target": target }))
+                .collect(),
+            Err(_) => JsonValue::Null,
+        };
+        value["environment"] = match &environment {
+            Ok(environment) => serde_json::json!(environment),
+            Err(_) => JsonValue::Null,
+        };
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+    let path = |path: &Option<PathBuf>| {
+        path.as_ref()
+            .map_or_else(|| "unknown".to_string(), |p| p.display().to_string())
+    };
+    println!("PID: {}", process.pid);
+    if let Some(parent) = process.parent {
+        println!("Parent PID: {parent}");
+    }
+    println!("Name: {}", process.name);
+    println!("User: {}", process.user_label());
+    println!("Status: {}", process.status);
+    println!("Command line: {}", process.command_line());
+    println!("Executable: {}", path(&process.exe));
+    println!("Working directory: {}", path(&process.cwd));
+    println!(
+        "Started: {} (running {})",
+        system::rfc3339_utc(process.start_time),
+        system::human_duration(process.run_time)
+    );
+    println!("CPU: {:.1}%", process.cpu_percent);
+    println!(
+        "Memory: {} resident, {} virtual",
+        system::human_bytes(process.memory),
+        system::human_bytes(process.virtual_memory)
+    );
+    match open_files {
+        Ok(files) => {
+            println!("Open files ({}):", files.len());
+            for (fd, target) in files {
+                println!("  {fd:>4} -> {target}");
+            }
+        }
+        Err(e) => println!("Open files: unavailable ({e:#})"),
+    }
+    match environment {
+        Ok(environment) => {
+            println!("Environment ({} variables):", environment.len());
+            for variable in environment {
+                println!("  {variable}");
+            }
+        }
+        Err(e) => println!("{e:#}"),
+    }
+    Ok(())
+}
+
+fn process_signal(pid: u32, signal: &str, yes: bool) -> Result<()> {
+    let signal = process::parse_signal(signal)?;
+    let process = process::find(pid)?;
+    let name = process::signal_name(signal);
+    if !yes {
+        let answer = get_input(&format!(
+            "Send {name} to {} ({})? [y/N]",
+            pid,
+            process.command_line()
+        ))?;
+        if !answer.eq_ignore_ascii_case("y") {
+            println!("Cancelled");
+            return Ok(());
+        }
+    }
+    process::send_signal(pid, signal)?;
+    println!("Sent {name} to {pid}");
+    Ok(())
+}
+
+fn network_menu() -> Result<()> {
+    loop {
+        println!("\nNetwork");
+        println!("1. List interfaces");
+        println!("2. List listening and established sockets");
+        println!("3. List listening sockets");
+        println!("0. Cancel");
+
+        match get_choice()? {
+            1 => {
+                net_interfaces(false)?;
+                return Ok(());
+            }
+            2 => {
+                net_sockets(
+                    true,
+                    true,
+                    network::StateFilter::ListeningOrEstablished,
+                    false,
+                )?;
+                return Ok(());
+            }
+            3 => {
+                net_sockets(
+                    true,
+                    true,
+                    network::StateFilter::Listening,
+                    false,
+                )?;
+                return Ok(());
+            }
+            0 => return Ok(()),
+            _ => println!("Invalid choice"),
+        }
+    }
+}
+
+fn net_interfaces(json: bool) -> Result<()> {
+    let interfaces = network::interfaces();
+    if json {
+        let interfaces: Vec<JsonValue> =
+            interfaces.iter().map(network::Interface::to_json).collect();
+        println!("{}", serde_json::to_string_pretty(&interfaces)?);
+        return Ok(());
+    }
+    for interface in &interfaces {
+        println!(
+            "- {} (MTU {}{})",
+            interface.name,
+            interface.mtu,
+            interface
+                .mac
+                .as_ref()
+                .map_or_else(String::new, |mac| format!(", MAC {mac}"))
+        );
+        for address in &interface.addresses {
+            println!("  {address}");
+        }
+        println!(
+            "  RX: {} in {} packets ({} errors)",
+            system::human_bytes(interface.received),
+            interface.packets_received,
+            interface.errors_received
+        );
+        println!(
+            "  TX: {} in {} packets ({} errors)",
+            system::human_bytes(interface.transmitted),
+            interface.packets_transmitted,
+            interface.errors_transmitted
+        );
+    }
+    Ok(())
+}
+
+fn net_sockets(
+    tcp: bool,
+    udp: bool,
+    filter: network::StateFilter,
+    json: bool,
+) -> Result<()> {
+    let sockets = network::sockets(tcp, udp, filter)?;
+    if json {
+        let sockets: Vec<JsonValue> =
+            sockets.iter().map(network::Socket::to_json).collect();
+        println!("{}", serde_json::to_string_pretty(&sockets)?);
+        return Ok(());
+    }
+    println!(
+        "{:<5} {:<12} {:<40} {:<40} Process",
+        "Proto", "State", "Local address", "Remote address"
+    );
+    for socket in &sockets {
+        let owners = if socket.owners.is_empty() {
+            format!("? (uid {})", socket.uid)
+        } else {
+            socket
+                .owners
+                .iter()
+                .map(|(pid, name)| format!("{pid}/{name}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        println!(
+            "{:<5} {:<12} {:<40} {:<40} {owners}",
+            socket.protocol.name(),
+            socket.state,
+            socket.local.to_string(),
+            socket.remote.to_string()
+        );
+    }
+    Ok(())
+}
+
+fn audit_verify(log: Option<&Path>) -> Result<()> {
+    let log = log.map_or_else(audit::log_path, Path::to_path_buf);
+    let verification = audit::verify(&log)?;
+    for problem in &verification.problems {
+        println!("{problem}");
+    }
+    if !verification.problems.is_empty() {
+        return Err(anyhow!(
+            "Audit log {} failed verification with {} problem(s)",
+            log.display(),
+            verification.problems.len()
+        ));
+    }
+    if verification.unanchored {
+        println!(
+            "Warning: head file is missing, so truncation at the end of the \
+             log cannot be detected"
+        );
+    }
+    println!(
+        "Audit log {} is intact ({} records)",
+        log.display(),
+        verification.records
+    );
+    Ok(())
+}
+
+fn get_algorithm() -> Result<manifest::Algorithm> {
+    let algorithm = get_input("Algorithm (sha256, sha512, blake3) [sha256]")?;
+    match algorithm.as_str() {
+        "" => Ok(manifest::Algorithm::Sha256),
+        algorithm => clap::ValueEnum::from_str(algorithm, true)
+            .map_err(|_| anyhow!("Unknown algorithm '{algorithm}'")),
+    }
+}
+
+/// Warns on stderr about the entries of a walk that were not hashed.
+fn report_skipped(base: &Path, skipped: &[String], non_utf8: &[String]) {
+    for path in skipped {
+        eprintln!("Skipping {}: not a regular file", base.join(path).display());
+    }
+    for path in non_utf8 {
+        eprintln!(
+            "Skipping {}: name is not valid UTF-8",
+            base.join(path).display()
+        );
+    }
+}
+
+/// Prints `<digest>  <path>` for each file, and for every regular file below
+/// each directory, in the format sha256sum prints.
+fn hash_paths(
+    paths: &[PathBuf],
+    algorithm: manifest::Algorithm,
+    json: bool,
+) -> Result<()> {
+    let mut results = Vec::new();
+    for input in paths {
+        let path = sanitize_path(input, false)?;
+        if !path.is_dir() {
+            let (digest, size) = algorithm.digest_file(&path)?;
+            results.push((input.clone(), digest, size));
+            continue;
+        }
+        let tree = manifest::walk(&path)?;
+        report_skipped(input, &tree.skipped, &tree.non_utf8);
+        for relative in tree.files {
+            let (digest, size) =
+                algorithm.digest_file(&path.join(&relative))?;
+            results.push((input.join(relative), digest, size));
+        }
+    }
+    if json {
+        let files: Vec<JsonValue> = results
+            .iter()
+            .map(|(path, digest, size)| {
+                serde_json::json!({
+                    "path": path.to_string_lossy(),
+                    "digest": digest,
+                    "size": size,
+                })
+            })
+            .collect();
+        let report =
+            serde_json::json!({"algorithm": algorithm.name(), "files": files});
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    for (path, digest, _) in &results {
+        println!("{}", manifest::sum_line(digest, &path.to_string_lossy()));
+    }
+    Ok(())
+}
+
+/// `path` relative to `dir`, if it lies inside it.
+fn relative_in(path: &Path, dir: &Path) -> Option<String> {
+    path.strip_prefix(dir)
+        .ok()
+        .and_then(Path::to_str)
+        .map(str::to_string)
+}
+
+fn manifest_create(
+    dir: &Path,
+    output: Option<&Path>,
+    algorithm: manifest::Algorithm,
+    format: Option<manifest::Format>,
+) -> Result<()> {
+    let dir = sanitize_path(dir, false)?;
+    if !dir.is_dir() {
+        return Err(anyhow!("'{}' is not a directory", dir.display()));
+    }
+    let output = output.map(|path| sanitize_path(path, true)).transpose()?;
+    let format = format.unwrap_or(match &output {
+        Some(path) if path.extension().is_some_and(|ext| ext == "json") => {
+            manifest::Format::Json
+        }
+        _ => manifest::Format::Sha256sum,
+    });
+    // A manifest written into the tree does not list itself.
+    let exclude = output.as_deref().and_then(|path| relative_in(path, &dir));
+    let (manifest, tree) =
+        manifest::create(&dir, algorithm, exclude.as_deref())?;
+    report_skipped(Path::new(""), &tree.skipped, &tree.non_utf8);
+    let text = manifest.render(format)?;
+    let Some(output) = output else {
+        print!("{text}");
+        return Ok(());
+    };
+    if write_file("manifest_create", &output, text)? {
+        println!(
+            "Wrote {} manifest of {} files to {}",
+            algorithm.name(),
+            manifest.entries.len(),
+            output.display()
+        );
+    }
+    Ok(())
+}
+
+fn manifest_verify(
+    path: &Path,
+    dir: Option<&Path>,
+    algorithm: Option<manifest::Algorithm>,
+    json: bool,
+) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Reading {}", path.display()))?;
+    let manifest = manifest::Manifest::parse(&text, algorithm)?;
+    let dir = match dir {
+        Some(dir) => sanitize_path(dir, false)?,
+        None => path.parent().unwrap_or(Path::new("/")).to_path_buf(),
+    };
+    let exclude = relative_in(&path, &dir);
+    let verification = manifest::verify(&manifest, &dir, exclude.as_deref())?;
+    report_skipped(Path::new(""), &[], &verification.non_utf8);
+    if json {
+        let report = verification.to_json(manifest.algorithm);
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for (label, paths) in [
+            ("MODIFIED", &verification.modified),
+            ("MISSING", &verification.missing),
+            ("EXTRA", &verification.extra),
+        ] {
+            for path in paths {
+                println!("{label:<9} {path}");
+            }
+        }
+        println!(
+            "{} OK, {} modified, {} missing, {} extra ({})",
+            verification.ok,
+            verification.modified.len(),
+            verification.missing.len(),
+            verification.extra.len(),
+            manifest.algorithm.name()
+        );
+    }
+    if !verification.is_intact() {
+        return Err(anyhow!(
+            "{} does not match the files in {}",
+            path.display(),
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+fn quota_status(json: bool) -> Result<()> {
+    let quota = quota::Quota::configured();
+    let root = sandbox_root()?;
+    let usage = quota::usage(&root)?;
+    if json {
+        let mut status = quota.to_json(&usage);
+        status["root"] = root.to_string_lossy().into();
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+    println!("Sandbox: {}", root.display());
+    match quota.max_bytes {
+        Some(max) => println!(
+            "Space: {} of {} ({}%)",
+            system::human_bytes(usage.bytes),
+            system::human_bytes(max),
+            system::percent(usage.bytes, max)
+        ),
+        None => {
+            println!("Space: {} (no limit)", system::human_bytes(usage.bytes))
+        }
+    }
+    match quota.max_files {
+        Some(max) => println!(
+            "Files: {} of {} ({}%)",
+            usage.files,
+            max,
+            system::percent(usage.files, max)
+        ),
+        None => println!("Files: {} (no limit)", usage.files),
+    }
+    Ok(())
+}
+
+fn config_show(json: bool) -> Result<()> {
+    let config = config::get();
+    let files = config::files();
+    if json {
+        let files: Vec<JsonValue> = files
+            .iter()
+            .map(|(layer, path)| {
+                serde_json::json!({
+                    "layer": layer,
+                    "path": path.to_string_lossy(),
+                    "found": path.is_file(),
+                })
+            })
+            .collect();
+        let settings: Vec<JsonValue> = config
+            .entries()
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "key": entry.key,
+                    "value": config::value_json(entry.value.as_ref()),
+                    "origin": entry.origin.to_string(),
+                })
+            })
+            .collect();
+        let report =
+            serde_json::json!({ "files": files, "settings": settings });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    for (layer, path) in &files {
+        let state = if path.is_file() { "" } else { " (not found)" };
+        println!("# {layer} file: {}{state}", path.display());
+    }
+    let rows: Vec<(String, String)> = config
+        .entries()
+        .iter()
+        .map(|entry| {
+            let value = entry
+                .value
+                .as_ref()
+                .map_or_else(|| "(unset)".to_string(), ToString::to_string);
+            (format!("{} = {value}", entry.key), entry.origin.to_string())
+        })
+        .collect();
+    let width = rows.iter().map(|(row, _)| row.len()).max().unwrap_or(0);
+    for (row, origin) in rows {
+        println!("{row:width$}  # {origin}");
+    }
+    Ok(())
+}
+
+fn script_run(
+    path: &Path,
+    variables: &[String],
+    stop_on_error: bool,
+    trace: bool,
+) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let source = fs::read_to_string(&path)
+        .with_context(|| format!("Reading script {}", path.display()))?;
+    script::check(&source)
+        .with_context(|| format!("Checking script {}", path.display()))?;
+    let mut variables = script::Variables::new(variables)?;
+    let (mut stop_on_error, mut trace) = (stop_on_error, trace);
+    let (mut commands, mut succeeded) = (0, 0);
+    let mut failed_lines = Vec::new();
+    let mut stopped_at = None;
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let result = script::parse_line(line, &variables).and_then(|step| {
+            match step {
+                None => {}
+                Some(script::Step::Assign(name, value)) => {
+                    variables.set(name, value)
+                }
+                Some(script::Step::StopOnError(on)) => stop_on_error = on,
+                Some(script::Step::Trace(on)) => trace = on,
+                Some(script::Step::Command(words)) => {
+                    if trace {
+                        eprintln!(
+                            "+ {}",
+                            shlex::try_join(words.iter().map(String::as_str))?
+                        );
+                    }
+                    commands += 1;
+                    script_command(&words)?;
+                    succeeded += 1;
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("{}:{number}: {e:#}", path.display());
+            failed_lines.push(number);
+            if stop_on_error {
+                stopped_at = Some(number);
+                break;
+            }
+        }
+    }
+    let failed = failed_lines.len();
+    eprintln!(
+        "Script {}: {commands} command(s) run, {succeeded} succeeded, \
+         {failed} line(s) failed{}",
+        path.display(),
+        stopped_at.map_or_else(String::new, |line| format!(
+            "; stopped at line {line} (set -e)"
+        ))
+    );
+    if failed > 0 {
+        let lines: Vec<String> =
+            failed_lines.iter().map(ToString::to_string).collect();
+        return Err(anyhow!("Script failed on line(s) {}", lines.join(", ")));
+    }
+    Ok(())
+}
+
+/// Runs one script line as if it were given on the command line.
+fn script_command(words: &[String]) -> Result<()> {
+    let args = std::iter::once("osul").chain(words.iter().map(String::as_str));
+    let cli = match cli::Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(e)
+            if matches!(
+                e.kind(),
+                clap::error::ErrorKind::DisplayHelp
+                    | clap::error::ErrorKind::DisplayVersion
+            ) =>
+        {
+            print!("{e}");
+            return Ok(());
+        }
+        Err(e) => return Err(anyhow!("{}", e.to_string().trim_end())),
+    };
+    if !cli.settings.is_empty() {
+        return Err(anyhow!(
+            "--set cannot change the configuration inside a script; pass it \
+             to `osul script` instead"
+        ));
+    }
+    let command = match cli.command {
+        None => {
+            return Err(anyhow!("The interactive menu cannot run in a script"));
+        }
+        Some(cli::Command::Script { .. }) => {
+            return Err(anyhow!("Scripts cannot run other scripts"));
+        }
+        Some(command) => command,
+    };
+    // `--dry-run` on a line applies to that line only.
+    let dry_run = DRY_RUN.load(Ordering::Relaxed);
+    DRY_RUN.store(dry_run || cli.dry_run, Ordering::Relaxed);
+    let result = cli::dispatch(command);
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+    result
+}
+
+fn tui_browse() -> Result<()> {
+    if !io::stdout().is_terminal() || !io::stdin().is_terminal() {
+        return Err(anyhow!("The file browser needs an interactive terminal"));
+    }
+    tui::run(sandbox_root()?)
+}
+
+fn file_create(path: &Path) -> Result<()> {
+    let path = sanitize_path(path, true)?;
+    if path.exists() {
+        return Err(Error::AlreadyExists(format!(
+            "File '{}' already exists",
+            path.display()
+        ))
+        .into());
+    }
+    if write_file("file_create", &path, "")? {
+        println!("Created {}", path.display());
+    }
+    Ok(())
+}
+
+fn file_write(path: &Path, content: &str) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    if write_file("file_write", &path, content)? {
+        println!("Wrote to {}", path.display());
+    }
+    Ok(())
+}
+
+fn file_read(path: &Path) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let mut content = String::new();
+    let mut file = File::open(&path)
+        .with_context(|| format!("Opening {}", path.display()))?;
+    file.read_to_string(&mut content)?;
+    print!("{content}");
+    Ok(())
+}
+
+/// Like `sanitize_path` for an existing entry, but leaves the last component
+/// unresolved, so a symlink names the link rather than its target.
+fn sanitize_entry(input: &Path) -> Result<PathBuf> {
+    match (input.parent(), input.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            Ok(sanitize_path(parent, false)?.join(name))
+        }
+        _ => sanitize_path(input, false),
+    }
+}
+
+fn file_delete(path: &Path) -> Result<()> {
+    // A symlink is removed itself, never the file it points to.
+    let path = sanitize_entry(path)?;
+    if fs::symlink_metadata(&path).is_ok() {
+        let change = audit::Change::begin("file_delete", &path)?;
+        if dry_run() {
+            return change.plan(None);
+        }
+        fs::remove_file(&path)
+            .with_context(|| format!("Deleting {}", path.display()))?;
+        change.commit()?;
+        println!("Deleted {}", path.display());
+        Ok(())
+    } else {
+        Err(Error::NotFound(format!(
+            "File '{}' does not exist",
+            path.display()
+        ))
+        .into())
+    }
+}
+
+fn json_create(
+    path_buf: PathBuf,
+    content_: Option<String>,
+    edit: bool,
+) -> Result<()> {
+    let path = sanitize_path(&path_buf.into_boxed_path(), true)?;
+    if edit {
+        let initial = if path.exists() {
+            fs::read(&path)?
+        } else {
+            Vec::new()
+        };
+        let edited = editor::edit_copy(&initial, ".json")?;
+        serde_json::from_slice::<JsonValue>(&edited)
+            .with_context(|| "Edited content is not valid JSON")?;
+        let path = sanitize_path(&path, true)?;
+        if write_file("json_create", &path, edited)? {
+            println!("Created via editor: {}", path.display());
+        }
+        return Ok(());
+    }
+    if let Some(content) = content_ {
+        let value: JsonValue = serde_json::from_str(&content)
+            .with_context(|| "CONTENT is not valid JSON")?;
+        let ser = serde_json::to_vec_pretty(&value)?;
+        if write_file("json_create", &path, ser)? {
+            println!("Created {} with provided JSON content", path.display());
+        }
+        Ok(())
+    } else {
+        Err(anyhow!("Either -c CONTENT or -e must be provided"))
+    }
+}
 
 fn _json_new(object: &str, path: &Path) -> Result<()> {
     let path = sanitize_path(path, false)?;
     let value: JsonValue = serde_json::from_str(object)
         .with_context(|| "Provided object is not valid JSON")?;
     let ser = serde_json::to_vec_pretty(&value)?;
-    fs::write(&path, ser)?;
-    println!("Wrote JSON object to {}", path.display());
+    if write_file("json_create", &path, ser)? {
+        println!("Wrote JSON object to {}", path.display());
+    }
     Ok(())
 }
 
-fn json_read(path: &Path) -> Result<()> {
-    let path = sanitize_path(path, false)?;
+fn load_json(path: &Path) -> Result<JsonValue> {
     let mut string = String::new();
-    File::open(&path)?.read_to_string(&mut string)?;
-    let value: JsonValue = serde_json::from_str(&string)
-        .with_context(|| "File is not valid JSON")?;
-    let pretty = serde_json::to_string_pretty(&value)?;
-    println!("{pretty}");
+    File::open(path)?.read_to_string(&mut string)?;
+    serde_json::from_str(&string).with_context(|| "File is not valid JSON")
+}
+
+fn json_read(path: &Path, canonical: bool) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let value = load_json(&path)?;
+    if canonical {
+        println!("{}", jcs::canonicalize(&value)?);
+    } else {
+        let pretty = serde_json::to_string_pretty(&value)?;
+        println!("{pretty}");
+    }
+    Ok(())
+}
+
+fn json_digest(path: &Path) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let value = load_json(&path)?;
+    println!("{}  {}", jcs::sha256_hex(&value)?, path.display());
+    Ok(())
+}
+
+fn signature_path(path: &Path, signature: Option<&Path>) -> Result<PathBuf> {
+    match signature {
+        Some(signature) => sanitize_path(signature, true),
+        None => {
+            let mut name = path.as_os_str().to_os_string();
+            name.push(".sig");
+            sanitize_path(Path::new(&name), true)
+        }
+    }
+}
+
+fn json_keygen(path: &Path) -> Result<()> {
+    let secret = sanitize_path(path, true)?;
+    let public = sanitize_path(&signing::public_key_path(&secret), true)?;
+    for key in [&secret, &public] {
+        if key.exists() {
+            return Err(Error::AlreadyExists(format!(
+                "File '{}' already exists",
+                key.display()
+            ))
+            .into());
+        }
+    }
+    let changes = [
+        audit::Change::begin("json_keygen", &secret)?,
+        audit::Change::begin("json_keygen", &public)?,
+    ];
+    let key_file = Some(signing::KEY_FILE_LEN);
+    check_quota(&[(None, key_file), (None, key_file)])?;
+    if dry_run() {
+        for change in changes {
+            change.plan(Some(signing::KEY_FILE_LEN))?;
+        }
+        return Ok(());
+    }
+    signing::generate(&secret, &public)?;
+    for change in changes {
+        change.commit()?;
+    }
+    println!(
+        "Created secret key {} and public key {}",
+        secret.display(),
+        public.display()
+    );
+    Ok(())
+}
+
+fn json_sign(path: &Path, key: &Path, signature: Option<&Path>) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let key = sanitize_path(key, false)?;
+    let canonical = jcs::canonicalize(&load_json(&path)?)?;
+    let signature = signature_path(&path, signature)?;
+    let encoded = signing::sign(&key, canonical.as_bytes())?;
+    if write_file("json_sign", &signature, format!("{encoded}\n"))? {
+        println!("Signed {} into {}", path.display(), signature.display());
+    }
+    Ok(())
+}
+
+fn json_verify(
+    path: &Path,
+    key: &Path,
+    signature: Option<&Path>,
+) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let key = sanitize_path(key, false)?;
+    let canonical = jcs::canonicalize(&load_json(&path)?)?;
+    let signature = sanitize_path(&signature_path(&path, signature)?, false)?;
+    let encoded = fs::read_to_string(&signature)
+        .with_context(|| format!("Reading {}", signature.display()))?;
+    signing::verify(&key, canonical.as_bytes(), &encoded)?;
+    println!("Signature OK for {}", path.display());
     Ok(())
 }
 
+fn json_query(path: &Path, filter: &str, output: Option<&Path>) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let value = load_json(&path)?;
+    let filter = json_query::parse(filter)
+        .with_context(|| format!("Invalid filter '{filter}'"))?;
+    let mut results = json_query::run(&filter, &value)?;
+
+    let Some(output) = output else {
+        for result in &results {
+            println!("{}", serde_json::to_string_pretty(result)?);
+        }
+        return Ok(());
+    };
+    if results.len() != 1 {
+        return Err(anyhow!(
+            "Filter produced {} results, expected exactly one to write; \
+             wrap it in [...] to collect them",
+            results.len()
+        ));
+    }
+    let output = sanitize_path(output, true)?;
+    let ser = serde_json::to_vec_pretty(&results.remove(0))?;
+    if write_file("json_query", &output, ser)? {
+        println!("Wrote query result to {}", output.display());
+    }
+    Ok(())
+}
+
+fn convert_file(
+    input: &Path,
+    output: &Path,
+    from: Option<convert::Format>,
+    to: Option<convert::Format>,
+    strict: bool,
+) -> Result<()> {
+    let input = sanitize_path(input, false)?;
+    let output = sanitize_path(output, true)?;
+    let detect = |path: &Path, given: Option<convert::Format>| {
+        given
+            .or_else(|| convert::Format::from_path(path))
+            .ok_or_else(|| {
+                anyhow!("Cannot detect format of '{}'", path.display())
+            })
+    };
+    let from = detect(&input, from)?;
+    let to = detect(&output, to)?;
+
+    let mut content = String::new();
+    File::open(&input)?.read_to_string(&mut content)?;
+    let conversion = convert::convert(&content, from, to)?;
+    if !conversion.losses.is_empty() {
+        let lines = conversion.losses.lines();
+        if strict {
+            return Err(anyhow!(
+                "Conversion from {from} to {to} would lose data:\n{}",
+                lines.join("\n")
+            ));
+        }
+        for line in lines {
+            eprintln!("Warning: {line}");
+        }
+    }
+    if write_file("convert", &output, conversion.output)? {
+        println!(
+            "Converted {} ({from}) to {} ({to})",
+            input.display(),
+            output.display()
+        );
+    }
+    Ok(())
+}
+
+fn open_jsonl(path: &Path) -> Result<BufReader<File>> {
+    let path = sanitize_path(path, false)?;
+    let file = File::open(&path)
+        .with_context(|| format!("Opening {}", path.display()))?;
+    Ok(BufReader::new(file))
+}
+
+fn jsonl_count(path: &Path) -> Result<()> {
+    println!("{} records", ndjson::count(open_jsonl(path)?)?);
+    Ok(())
+}
+
+fn jsonl_head(path: &Path, n: usize) -> Result<()> {
+    let mut out = io::stdout().lock();
+    ndjson::head(open_jsonl(path)?, &mut out, n)
+}
+
+fn jsonl_tail(path: &Path, n: usize) -> Result<()> {
+    let mut out = io::stdout().lock();
+    ndjson::tail(open_jsonl(path)?, &mut out, n)
+}
+
+fn jsonl_filter(
+    path: &Path,
+    pointer: &str,
+    value: Option<&str>,
+    output: Option<&Path>,
+) -> Result<()> {
+    if !pointer.is_empty() && !pointer.starts_with('/') {
+        return Err(anyhow!("JSON pointer '{pointer}' must start with '/'"));
+    }
+    let expected = value
+        .map(serde_json::from_str::<JsonValue>)
+        .transpose()
+        .with_context(|| "Expected value is not valid JSON")?;
+    let reader = open_jsonl(path)?;
+    let summary = match output {
+        Some(output) => {
+            let output = sanitize_path(output, true)?;
+            let change = audit::Change::begin("jsonl_filter", &output)?;
+            // The output size is only known once filtered, so the quota is
+            // enforced as it is written.
+            let remaining = check_quota(&[(change.before_size(), Some(0))])?;
+            if dry_run() {
+                let mut sink =
+                    quota::Limited::new(ByteCounter::default(), remaining);
+                let summary = ndjson::filter(
+                    reader,
+                    &mut sink,
+                    pointer,
+                    expected.as_ref(),
+                )?;
+                change
+                    .detail(format!("{} matching records", summary.matched))
+                    .plan(Some(sink.into_inner().0))?;
+                return Ok(());
+            }
+            let mut out = quota::Limited::new(
+                BufWriter::new(File::create(&output)?),
+                remaining,
+            );
+            let summary =
+                ndjson::filter(reader, &mut out, pointer, expected.as_ref())
+                    .and_then(|summary| {
+                        out.flush()?;
+                        Ok(summary)
+                    });
+            let summary = match summary {
+                Ok(summary) => summary,
+                Err(e) => {
+                    // Don't leave a partial new file counting against the
+                    // quota.
+                    drop(out);
+                    if change.before_size().is_none() {
+                        let _ = fs::remove_file(&output);
+                    }
+                    return Err(e).with_context(|| {
+                        format!("Writing {}", output.display())
+                    });
+                }
+            };
+            change.commit()?;
+            println!(
+                "Wrote {} matching records to {}",
+                summary.matched,
+                output.display()
+            );
+            summary
+        }
+        None => {
+            let mut out = io::stdout().lock();
+            ndjson::filter(reader, &mut out, pointer, expected.as_ref())?
+        }
+    };
+    if summary.invalid > 0 {
+        eprintln!(
+            "Skipped {} invalid records (first on lines {:?})",
+            summary.invalid, summary.invalid_lines
+        );
+    }
+    Ok(())
+}
+
+fn jsonl_validate(path: &Path) -> Result<()> {
+    let report = ndjson::validate(open_jsonl(path)?)?;
+    for (line, error) in &report.errors {
+        println!("Line {line}: {error}");
+    }
+    if report.invalid == 0 {
+        println!("All {} records are valid JSON", report.valid);
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} of {} records are invalid",
+            report.invalid,
+            report.valid + report.invalid
+        ))
+    }
+}
+
 fn xml_new(path: &Path) -> Result<()> {
     let path = sanitize_path(path, true)?;
     if path.exists() {
-        return Err(anyhow!("File '{}' already exists", path.display()));
+        return Err(Error::AlreadyExists(format!(
+            "File '{}' already exists",
+            path.display()
+        ))
+        .into());
     }
     let root = Element::new("root");
-    let mut file = File::create(&path)?;
-    root.write(&mut file)?;
-    println!("Created XML {}", path.display());
+    enforce_xml_schema(&root)?;
+    let mut output = Vec::new();
+    root.write(&mut output)?;
+    if write_file("xml_create", &path, output)? {
+        println!("Created XML {}", path.display());
+    }
     Ok(())
 }
 
 fn xml_write(path: &Path, content: &str) -> Result<()> {
-    let path = sanitize_path(path, false)?;
-    if !path.exists() {
-        return Err(anyhow!("File '{}' does not exist", path.display()));
-    }
-    let mut file = File::open(&path)?;
-    let mut content_ = String::new();
-    file.read_to_string(&mut content_)?;
-    let mut root = Element::parse(content_.as_bytes())
-        .with_context(|| "Parsing existing XML")?;
-    match Element::parse(content.as_bytes()) {
-        Ok(new_elem) => root.children.push(xmltree::XMLNode::Element(new_elem)),
+    let (path, mut document) = load_xml(path)?;
+    let fragment = match xml_safe::parse(
+        content.as_bytes(),
+        &config::get().xml_limits(),
+    ) {
+        Ok(new_elem) => xml_fragment(&new_elem)?,
+        // Markup that fails the hardened parser is refused rather than
+        // being stored as text, so the reason (e.g. a DOCTYPE) is reported.
+        Err(err) if content.trim_start().starts_with('<') => {
+            return Err(err.context("Parsing XML content to append"));
+        }
         Err(_) => {
             let mut entry = Element::new("entry");
             entry
                 .children
                 .push(xmltree::XMLNode::Text(content.to_string()));
-            root.children.push(xmltree::XMLNode::Element(entry));
+            xml_fragment(&entry)?
         }
+    };
+    document.insert(&[], &fragment, xpath::Position::Last)?;
+    if save_xml("xml_write", &path, document)? {
+        println!("Appended to {}", path.display());
     }
-    let mut out = File::create(&path)?;
-    root.write_with_config(
-        &mut out,
-        xmltree::EmitterConfig::new().perform_indent(true),
-    )?;
-    println!("Appended to {}", path.display());
     Ok(())
 }
 
-fn xml_read(path: &Path) -> Result<()> {
+/// Parses `path` for canonicalization, keeping whitespace-only text.
+fn canonical_document(path: &Path) -> Result<xml_safe::Document> {
+    let limits = config::get().xml_limits();
+    let input = xml_safe::read_file(path, &limits)?;
+    xml_safe::parse_document_with_whitespace(&input, &limits)
+        .with_context(|| format!("Parsing {}", path.display()))
+}
+
+fn xml_read(path: &Path, canonical: bool) -> Result<()> {
     let path = sanitize_path(path, false)?;
-    let mut s = String::new();
-    File::open(&path)?.read_to_string(&mut s)?;
-    let root = Element::parse(s.as_bytes())?;
+    if canonical {
+        println!("{}", c14n::canonicalize(&canonical_document(&path)?));
+        return Ok(());
+    }
+    let root = xml_safe::parse_file(&path, &config::get().xml_limits())?;
     let mut buf = Vec::new();
     root.write_with_config(
         &mut buf,
@@ -504,28 +2256,296 @@ fn xml_read(path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn open_in_editor(path: &Path) -> Result<()> {
-    if !path.exists() {
-        File::create(path)?;
+fn xml_digest(path: &Path) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let document = canonical_document(&path)?;
+    println!("{}  {}", c14n::sha256_hex(&document), path.display());
+    Ok(())
+}
+
+fn xml_compare(old: &Path, new: &Path) -> Result<()> {
+    let limits = config::get().xml_limits();
+    let old = xml_safe::parse_file(&sanitize_path(old, false)?, &limits)?;
+    let new = xml_safe::parse_file(&sanitize_path(new, false)?, &limits)?;
+    let differences = xml_diff::diff(&old, &new);
+    if differences.is_empty() {
+        println!("Documents are equivalent");
+        return Ok(());
+    }
+    for line in &differences {
+        println!("{line}");
+    }
+    Err(anyhow!("{} difference(s)", differences.len()))
+}
+
+fn load_xml(path: &Path) -> Result<(PathBuf, xml_edit::Document)> {
+    let path = sanitize_path(path, false)?;
+    let document =
+        xml_edit::Document::load(&path, &config::get().xml_limits())?;
+    Ok((path, document))
+}
+
+/// Writes the edited document back; everything outside the edited nodes is
+/// kept byte for byte.
+fn save_xml(
+    operation: &'static str,
+    path: &Path,
+    document: xml_edit::Document,
+) -> Result<bool> {
+    let limits = config::get().xml_limits();
+    let output = document.finish(&limits)?;
+    enforce_xml_schema(&xml_safe::parse(output.as_bytes(), &limits)?)?;
+    write_file(operation, path, output)
+}
+
+/// Writes `contents` to `path` and records the change in the audit log.
+/// Returns `false` when `--dry-run` only reported the write.
+fn write_file(
+    operation: &'static str,
+    path: &Path,
+    contents: impl AsRef<[u8]>,
+) -> Result<bool> {
+    write_change(audit::Change::begin(operation, path)?, contents.as_ref())
+}
+
+fn write_change(change: audit::Change, contents: &[u8]) -> Result<bool> {
+    check_quota(&[(change.before_size(), Some(contents.len() as u64))])?;
+    if dry_run() {
+        change.plan(Some(contents.len() as u64))?;
+        return Ok(false);
+    }
+    fs::write(change.path(), contents)
+        .with_context(|| format!("Writing {}", change.path().display()))?;
+    change.commit()?;
+    Ok(true)
+}
+
+/// Fails if replacing files of the `(before, after)` sizes would take the
+/// sandbox over its quota; see `quota::Quota::check`.
+fn check_quota(changes: &[(Option<u64>, Option<u64>)]) -> Result<Option<u64>> {
+    quota::Quota::configured().check(&sandbox_root()?, changes)
+}
+
+/// Counts what would be written, for dry runs of streaming writes.
+#[derive(Default)]
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
     }
-    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
-    let status = Command::new(editor).arg(path).status()?;
-    if status.success() {
+
+    fn flush(&mut self) -> io::Result<()> {
         Ok(())
-    } else {
-        Err(anyhow!("Editor exited with non-zero"))
     }
 }
 
+/// Serializes an element compactly, without an XML declaration, for
+/// splicing into an existing document.
+fn xml_fragment(element: &Element) -> Result<String> {
+    let mut buf = Vec::new();
+    element.write_with_config(
+        &mut buf,
+        xmltree::EmitterConfig::new()
+            .write_document_declaration(false)
+            .pad_self_closing(false),
+    )?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn print_schema_report(report: &xsd::Report) {
+    for error in &report.errors {
+        println!("  {error}");
+    }
+    if report.total > report.errors.len() {
+        println!("  ... and {} more", report.total - report.errors.len());
+    }
+}
+
+/// With `xml.schema` configured, every XML write is checked against that
+/// schema and refused if the result would not be valid.
+fn enforce_xml_schema(root: &Element) -> Result<()> {
+    let Some(schema_path) = config::get().xml_schema() else {
+        return Ok(());
+    };
+    let schema_path = sanitize_path(&schema_path, false)?;
+    let schema = xsd::Schema::load(&schema_path, &config::get().xml_limits())?;
+    let report = schema.validate(root);
+    if report.total == 0 {
+        return Ok(());
+    }
+    println!("Schema {} rejects the result:", schema_path.display());
+    print_schema_report(&report);
+    Err(anyhow!(
+        "Refusing to write XML that violates the schema ({} error(s))",
+        report.total
+    ))
+}
+
+fn xml_validate(path: &Path, schema: &Path) -> Result<()> {
+    let path = sanitize_path(path, false)?;
+    let schema_path = sanitize_path(schema, false)?;
+    let limits = config::get().xml_limits();
+    let schema = xsd::Schema::load(&schema_path, &limits)?;
+    let root = xml_safe::parse_file(&path, &limits)?;
+    let report = schema.validate(&root);
+    if report.total == 0 {
+        println!(
+            "{} is valid against {}",
+            path.display(),
+            schema_path.display()
+        );
+        return Ok(());
+    }
+    println!(
+        "{} is not valid against {}:",
+        path.display(),
+        schema_path.display()
+    );
+    print_schema_report(&report);
+    Err(anyhow!("{} validation error(s)", report.total))
+}
+
+fn xml_select(root: &Element, expr: &str) -> Result<Vec<xpath::Selection>> {
+    Ok(xml_select_nodes(root, expr)?
+        .into_iter()
+        .map(|(selection, _)| selection)
+        .collect())
+}
+
+fn xml_select_nodes<'a>(
+    root: &'a Element,
+    expr: &str,
+) -> Result<Vec<(xpath::Selection, &'a Element)>> {
+    let matches = xpath::XPath::parse(expr)
+        .with_context(|| format!("Parsing XPath '{expr}'"))?
+        .select(root);
+    if matches.is_empty() {
+        return Err(anyhow!("XPath '{expr}' matched nothing"));
+    }
+    Ok(matches)
+}
+
+/// Only elements can be edited; text and attribute selections are refused
+/// instead of silently editing their parent.
+fn matched_elements(matches: &[xpath::Selection]) -> Result<Vec<Vec<usize>>> {
+    matches
+        .iter()
+        .map(|selection| match selection {
+            xpath::Selection::Element(path) => Ok(path.clone()),
+            _ => Err(anyhow!("XPath must select elements for this operation")),
+        })
+        .collect()
+}
+
+fn xml_query(path: &Path, expr: &str) -> Result<()> {
+    let (_, document) = load_xml(path)?;
+    let root = document.tree();
+    for (selection, element) in xml_select_nodes(root, expr)? {
+        match &selection {
+            xpath::Selection::Element(_) => {
+                let mut buf = Vec::new();
+                element.write_with_config(
+                    &mut buf,
+                    xmltree::EmitterConfig::new()
+                        .perform_indent(true)
+                        .write_document_declaration(false),
+                )?;
+                println!("{}", String::from_utf8(buf)?);
+            }
+            _ => println!("{}", xpath::string_value(element, &selection)),
+        }
+    }
+    Ok(())
+}
+
+fn xml_set_text(path: &Path, expr: &str, text: &str) -> Result<()> {
+    let (path, mut document) = load_xml(path)?;
+    let targets = matched_elements(&xml_select(document.tree(), expr)?)?;
+    for target in &targets {
+        document.set_text(target, text)?;
+    }
+    if save_xml("xml_set_text", &path, document)? {
+        println!(
+            "Updated text of {} element(s) in {}",
+            targets.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn xml_set_attr(
+    path: &Path,
+    expr: &str,
+    name: &str,
+    value: &str,
+) -> Result<()> {
+    let (path, mut document) = load_xml(path)?;
+    let targets = matched_elements(&xml_select(document.tree(), expr)?)?;
+    for target in &targets {
+        document.set_attribute(target, name, value)?;
+    }
+    if save_xml("xml_set_attr", &path, document)? {
+        println!(
+            "Set attribute '{name}' on {} element(s) in {}",
+            targets.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn xml_insert(
+    path: &Path,
+    expr: &str,
+    fragment: &str,
+    position: xpath::Position,
+) -> Result<()> {
+    let (path, mut document) = load_xml(path)?;
+    let targets = matched_elements(&xml_select(document.tree(), expr)?)?;
+    let new = xml_safe::parse(fragment.as_bytes(), &config::get().xml_limits())
+        .context("Parsing XML fragment to insert")?;
+    let serialized = xml_fragment(&new)?;
+    for target in &targets {
+        document.insert(target, &serialized, position)?;
+    }
+    if save_xml("xml_insert", &path, document)? {
+        println!(
+            "Inserted <{}> at {} match(es) in {}",
+            new.name,
+            targets.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn xml_remove(path: &Path, expr: &str) -> Result<()> {
+    let (path, mut document) = load_xml(path)?;
+    let matches = xml_select(document.tree(), expr)?;
+    document.remove_selections(&matches)?;
+    if save_xml("xml_remove", &path, document)? {
+        println!("Removed {} node(s) from {}", matches.len(), path.display());
+    }
+    Ok(())
+}
+
 fn zip_create(path: &Path) -> Result<()> {
     let path = sanitize_path(path, true)?;
     if path.exists() {
-        return Err(anyhow!("Archive '{}' already exists", path.display()));
+        return Err(Error::AlreadyExists(format!(
+            "Archive '{}' already exists",
+            path.display()
+        ))
+        .into());
+    }
+    let mut zip = ZipWriter::new(io::Cursor::new(Vec::new()));
+    let archive = zip.finish()?.into_inner();
+    if write_file("zip_create", &path, archive)? {
+        println!("Created archive {}", path.display());
     }
-    let f = File::create(&path)?;
-    let mut zip = ZipWriter::new(f);
-    zip.finish()?;
-    println!("Created archive {}", path.display());
     Ok(())
 }
 
@@ -534,13 +2554,18 @@ fn zip_add(archive_path: &Path, filename: &Path) -> Result<()> {
     let filename = sanitize_path(filename, true)?;
 
     if !archive_path.exists() {
-        return Err(anyhow!(
+        return Err(Error::NotFound(format!(
             "Archive '{}' does not exist",
             archive_path.display()
-        ));
+        ))
+        .into());
     }
     if !filename.exists() {
-        return Err(anyhow!("File '{}' does not exist", filename.display()));
+        return Err(Error::NotFound(format!(
+            "File '{}' does not exist",
+            filename.display()
+        ))
+        .into());
     }
 
     let mut existing: Vec<(String, Vec<u8>)> = Vec::new();
@@ -555,8 +2580,9 @@ fn zip_add(archive_path: &Path, filename: &Path) -> Result<()> {
         }
     }
 
-    let f = File::create(&archive_path)?;
-    let mut zip = ZipWriter::new(f);
+    // Built in memory and written in one go, so a dry run can report the
+    // resulting size and a failure leaves the old archive intact.
+    let mut zip = ZipWriter::new(io::Cursor::new(Vec::new()));
     let options: FileOptions<ExtendedFileOptions> = FileOptions::default()
         .compression_method(zip::CompressionMethod::Stored);
     for (name, data) in existing {
@@ -570,22 +2596,27 @@ fn zip_add(archive_path: &Path, filename: &Path) -> Result<()> {
     fsrc.read_to_end(&mut buf)?;
     zip.start_file(name.clone(), options)?;
     zip.write_all(&buf)?;
-    zip.finish()?;
+    let archive = zip.finish()?.into_inner();
 
-    println!("Added {} to {}", filename.display(), archive_path.display());
+    let change = audit::Change::begin("zip_add", &archive_path)?
+        .detail(format!("entry {name}, {} bytes", buf.len()));
+    if write_change(change, &archive)? {
+        println!("Added {} to {}", filename.display(), archive_path.display());
+    }
     Ok(())
 }
 
 fn zip_extract(archive_path: &Path, filename: &str) -> Result<()> {
-    const EXPANSION_RATIO_LIMIT_PERCENT: u64 = 10_000;
+    let expansion_limit_percent = config::get().max_expansion_percent();
 
     let archive_path = sanitize_path(archive_path, false)?;
 
     if !archive_path.exists() {
-        return Err(anyhow!(
+        return Err(Error::NotFound(format!(
             "Archive '{}' does not exist",
             archive_path.display()
-        ));
+        ))
+        .into());
     }
     let file = File::open(&archive_path)?;
     let mut za = ZipArchive::new(file)?;
@@ -601,39 +2632,73 @@ fn zip_extract(archive_path: &Path, filename: &str) -> Result<()> {
                 let expansion_ratio_percent =
                     (uncompressed_size as u128 * 100) / compressed_size as u128;
 
-                if expansion_ratio_percent
-                    > EXPANSION_RATIO_LIMIT_PERCENT as u128
-                {
-                    return Err(anyhow!(
+                if expansion_ratio_percent > expansion_limit_percent as u128 {
+                    return Err(Error::Limit(format!(
                         "Expansion ratio of {}% exceeds the limit of {}% - potential zip bomb. Aborting.",
                         expansion_ratio_percent,
-                        EXPANSION_RATIO_LIMIT_PERCENT
-                    ));
+                        expansion_limit_percent
+                    )).into());
                 }
             } else if uncompressed_size > 0 {
-                return Err(anyhow!(
+                return Err(Error::Limit(format!(
                         "File has an infinite compression ratio ({} bytes from 0) - potential zip bomb. Aborting.",
                         uncompressed_size
-                    ));
+                    )).into());
             }
 
             let outpath = sanitize_path(Path::new(filename), true)?;
             let parent_dir = outpath.parent().unwrap_or_else(|| Path::new("."));
-            if !parent_dir.exists() {
+            if !parent_dir.exists() && !dry_run() {
                 fs::create_dir_all(parent_dir)?;
             }
-            let free_space = free_space(parent_dir)?;
+            // A dry run creates no directories, so measure the filesystem
+            // of the closest one that exists.
+            let existing_dir = parent_dir
+                .ancestors()
+                .find(|dir| dir.exists())
+                .unwrap_or(parent_dir);
+            let free_space = system::free_space(existing_dir)?;
 
             if uncompressed_size > free_space {
-                return Err(anyhow!(
+                return Err(Error::Limit(format!(
                     "Not enough disk space. Required: {}, Available: {}",
-                    uncompressed_size,
-                    free_space
-                ));
+                    uncompressed_size, free_space
+                ))
+                .into());
             }
 
-            let mut outfile = File::create(&outpath)?;
-            let bytes_written = io::copy(&mut file, &mut outfile)?;
+            let change = audit::Change::begin("zip_extract", &outpath)?
+                .detail(format!("{}: {filename}", archive_path.display()));
+            // The declared size is checked up front; the limit on the copy
+            // catches entries that decompress to more than they declare.
+            let remaining = check_quota(&[(
+                change.before_size(),
+                Some(uncompressed_size),
+            )])?
+            .map(|remaining| remaining + uncompressed_size);
+            if dry_run() {
+                // Decompress anyway so corrupt entries fail as they would.
+                let mut sink =
+                    quota::Limited::new(ByteCounter::default(), remaining);
+                io::copy(&mut file, &mut sink)?;
+                return change.plan(Some(sink.into_inner().0));
+            }
+            let mut outfile =
+                quota::Limited::new(File::create(&outpath)?, remaining);
+            let bytes_written = match io::copy(&mut file, &mut outfile) {
+                Ok(bytes_written) => bytes_written,
+                Err(e) => {
+                    // Don't leave a partial new file counting against the
+                    // quota.
+                    drop(outfile);
+                    if change.before_size().is_none() {
+                        let _ = fs::remove_file(&outpath);
+                    }
+                    return Err(e)
+                        .with_context(|| format!("Extracting {filename}"));
+                }
+            };
+            change.commit()?;
 
             let savings_percent = if uncompressed_size > 0 {
                 let savings = uncompressed_size as f64 - compressed_size as f64;
@@ -652,7 +2717,11 @@ fn zip_extract(archive_path: &Path, filename: &str) -> Result<()> {
         }
     }
     if !found {
-        return Err(anyhow!("File '{}' not found in archive", filename));
+        return Err(Error::NotFound(format!(
+            "File '{}' not found in archive",
+            filename
+        ))
+        .into());
     }
     Ok(())
 }
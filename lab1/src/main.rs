@@ -1,6 +1,5 @@
 fn main() {
     if let Err(err) = osul::run() {
-        eprintln!("Error: {err:?}");
-        std::process::exit(1);
+        std::process::exit(osul::report_error(&err));
     }
 }
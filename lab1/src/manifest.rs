@@ -0,0 +1,462 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256, Sha512};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Algorithm> {
+        [Algorithm::Sha256, Algorithm::Sha512, Algorithm::Blake3]
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+    }
+
+    fn hex_len(self) -> usize {
+        match self {
+            Algorithm::Sha256 | Algorithm::Blake3 => 64,
+            Algorithm::Sha512 => 128,
+        }
+    }
+
+    /// Reads `reader` to the end, returning the lowercase hex digest and
+    /// the number of bytes read.
+    pub fn digest(self, mut reader: impl Read) -> io::Result<(String, u64)> {
+        Ok(match self {
+            Algorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                let size = io::copy(&mut reader, &mut hasher)?;
+                (hex::encode(hasher.finalize()), size)
+            }
+            Algorithm::Sha512 => {
+                let mut hasher = Sha512::new();
+                let size = io::copy(&mut reader, &mut hasher)?;
+                (hex::encode(hasher.finalize()), size)
+            }
+            Algorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                let size = io::copy(&mut reader, &mut hasher)?;
+                (hasher.finalize().to_hex().to_string(), size)
+            }
+        })
+    }
+
+    pub fn digest_file(self, path: &Path) -> Result<(String, u64)> {
+        let file = File::open(path)
+            .with_context(|| format!("Opening {}", path.display()))?;
+        self.digest(file)
+            .with_context(|| format!("Reading {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// `<digest>  <path>` lines, as written by sha256sum, sha512sum and b3sum
+    Sha256sum,
+    Json,
+}
+
+/// Regular files below a directory, as sorted `/`-separated paths relative
+/// to it. Symlinks are not followed, so the walk cannot leave the tree;
+/// they and other special files end up in `skipped`.
+#[derive(Default)]
+pub struct Tree {
+    pub files: Vec<String>,
+    pub skipped: Vec<String>,
+    /// Entries whose names are not valid UTF-8, shown lossily. A manifest
+    /// cannot name them, so they (and anything below them) are left out.
+    pub non_utf8: Vec<String>,
+}
+
+pub fn walk(dir: &Path) -> Result<Tree> {
+    let mut tree = Tree::default();
+    walk_into(dir, "", &mut tree)?;
+    tree.files.sort();
+    Ok(tree)
+}
+
+fn walk_into(dir: &Path, prefix: &str, tree: &mut Tree) -> Result<()> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Reading {}", dir.display()))?;
+    for entry in entries {
+        let entry =
+            entry.with_context(|| format!("Reading {}", dir.display()))?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            tree.non_utf8
+                .push(format!("{prefix}{}", name.to_string_lossy()));
+            continue;
+        };
+        let relative = format!("{prefix}{name}");
+        // DirEntry::file_type does not traverse symlinks.
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk_into(&entry.path(), &format!("{relative}/"), tree)?;
+        } else if file_type.is_file() {
+            tree.files.push(relative);
+        } else {
+            tree.skipped.push(relative);
+        }
+    }
+    Ok(())
+}
+
+pub struct Entry {
+    pub path: String,
+    pub digest: String,
+    /// Only JSON manifests record sizes.
+    pub size: Option<u64>,
+}
+
+pub struct Manifest {
+    pub algorithm: Algorithm,
+    pub entries: Vec<Entry>,
+}
+
+/// One sha256sum line. Like coreutils, names containing a backslash or a
+/// line break are escaped and the line is marked with a leading `\`.
+pub fn sum_line(digest: &str, path: &str) -> String {
+    if path.contains(['\\', '\n', '\r']) {
+        let escaped = path
+            .replace('\\', "\\\\")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r");
+        format!("\\{digest}  {escaped}")
+    } else {
+        format!("{digest}  {path}")
+    }
+}
+
+fn unescape(path: &str) -> Result<String> {
+    let mut out = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            _ => return Err(anyhow!("Invalid escape in '{path}'")),
+        }
+    }
+    Ok(out)
+}
+
+impl Manifest {
+    pub fn render(&self, format: Format) -> Result<String> {
+        match format {
+            Format::Sha256sum => Ok(self
+                .entries
+                .iter()
+                .map(|entry| sum_line(&entry.digest, &entry.path) + "\n")
+                .collect()),
+            Format::Json => {
+                let files: serde_json::Map<String, JsonValue> = self
+                    .entries
+                    .iter()
+                    .map(|entry| {
+                        (
+                            entry.path.clone(),
+                            json!({"digest": entry.digest, "size": entry.size}),
+                        )
+                    })
+                    .collect();
+                let manifest = json!({
+                    "algorithm": self.algorithm.name(),
+                    "files": files,
+                });
+                Ok(serde_json::to_string_pretty(&manifest)? + "\n")
+            }
+        }
+    }
+
+    /// Reads either format. sha256sum-style manifests do not name their
+    /// algorithm: 128-digit digests are SHA-512, 64-digit ones SHA-256
+    /// unless `algorithm` says BLAKE3.
+    pub fn parse(text: &str, algorithm: Option<Algorithm>) -> Result<Manifest> {
+        let mut manifest = if text.trim_start().starts_with('{') {
+            Manifest::parse_json(text, algorithm)
+        } else {
+            Manifest::parse_sums(text, algorithm)
+        }
+        .map_err(|e| crate::Error::Parse(format!("Invalid manifest: {e}")))?;
+        // `find . -type f -exec sha256sum {} +` writes `./`-prefixed paths.
+        for entry in &mut manifest.entries {
+            let mut path = entry.path.as_str();
+            while let Some(rest) = path.strip_prefix("./") {
+                path = rest;
+            }
+            if path.len() != entry.path.len() {
+                entry.path = path.to_string();
+            }
+        }
+        let mut seen = BTreeSet::new();
+        for entry in &manifest.entries {
+            if !seen.insert(entry.path.as_str()) {
+                return Err(crate::Error::Parse(format!(
+                    "Invalid manifest: '{}' is listed twice",
+                    entry.path
+                ))
+                .into());
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn parse_json(
+        text: &str,
+        algorithm: Option<Algorithm>,
+    ) -> Result<Manifest> {
+        let value: JsonValue = serde_json::from_str(text)?;
+        let name = value["algorithm"]
+            .as_str()
+            .ok_or_else(|| anyhow!("missing \"algorithm\""))?;
+        let named = Algorithm::from_name(name)
+            .ok_or_else(|| anyhow!("unknown algorithm '{name}'"))?;
+        if let Some(algorithm) = algorithm
+            && algorithm != named
+        {
+            return Err(anyhow!(
+                "the manifest uses {name}, not {}",
+                algorithm.name()
+            ));
+        }
+        let files = value["files"]
+            .as_object()
+            .ok_or_else(|| anyhow!("missing \"files\" object"))?;
+        let entries = files
+            .iter()
+            .map(|(path, file)| {
+                let digest = file["digest"]
+                    .as_str()
+                    .filter(|digest| is_digest(digest, named))
+                    .ok_or_else(|| anyhow!("bad digest for '{path}'"))?;
+                Ok(Entry {
+                    path: path.clone(),
+                    digest: digest.to_ascii_lowercase(),
+                    size: file["size"].as_u64(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Manifest {
+            algorithm: named,
+            entries,
+        })
+    }
+
+    fn parse_sums(
+        text: &str,
+        algorithm: Option<Algorithm>,
+    ) -> Result<Manifest> {
+        let mut found: Option<Algorithm> = algorithm;
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let malformed = || anyhow!("line {} is malformed", index + 1);
+            let (escaped, line) = match line.strip_prefix('\\') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (digest, rest) = line.split_once(' ').ok_or_else(malformed)?;
+            // A space marks text mode and `*` binary mode; both hash the
+            // same bytes on Unix.
+            let path = rest
+                .strip_prefix(' ')
+                .or_else(|| rest.strip_prefix('*'))
+                .filter(|path| !path.is_empty())
+                .ok_or_else(malformed)?;
+            let path = if escaped {
+                unescape(path)?
+            } else {
+                path.to_string()
+            };
+            let guess = match digest.len() {
+                128 => Algorithm::Sha512,
+                _ => Algorithm::Sha256,
+            };
+            let algorithm = *found.get_or_insert(guess);
+            if !is_digest(digest, algorithm) {
+                return Err(anyhow!(
+                    "line {} does not hold a {} digest",
+                    index + 1,
+                    algorithm.name()
+                ));
+            }
+            entries.push(Entry {
+                path,
+                digest: digest.to_ascii_lowercase(),
+                size: None,
+            });
+        }
+        Ok(Manifest {
+            algorithm: found.unwrap_or(Algorithm::Sha256),
+            entries,
+        })
+    }
+}
+
+fn is_digest(digest: &str, algorithm: Algorithm) -> bool {
+    digest.len() == algorithm.hex_len()
+        && digest.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Hashes the regular files below `dir`, leaving out `exclude` (relative
+/// to `dir`), usually the manifest itself. The returned tree holds what was
+/// skipped; its `files` are moved into the manifest.
+pub fn create(
+    dir: &Path,
+    algorithm: Algorithm,
+    exclude: Option<&str>,
+) -> Result<(Manifest, Tree)> {
+    let mut tree = walk(dir)?;
+    let entries = std::mem::take(&mut tree.files)
+        .into_iter()
+        .filter(|path| Some(path.as_str()) != exclude)
+        .map(|path| {
+            let (digest, size) = algorithm.digest_file(&dir.join(&path))?;
+            Ok(Entry {
+                path,
+                digest,
+                size: Some(size),
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok((Manifest { algorithm, entries }, tree))
+}
+
+#[derive(Default)]
+pub struct Verification {
+    pub ok: usize,
+    pub modified: Vec<String>,
+    pub missing: Vec<String>,
+    pub extra: Vec<String>,
+    /// Files that could not be compared because their names are not UTF-8.
+    pub non_utf8: Vec<String>,
+}
+
+impl Verification {
+    pub fn is_intact(&self) -> bool {
+        self.modified.is_empty()
+            && self.missing.is_empty()
+            && self.extra.is_empty()
+    }
+
+    pub fn to_json(&self, algorithm: Algorithm) -> JsonValue {
+        json!({
+            "algorithm": algorithm.name(),
+            "intact": self.is_intact(),
+            "ok": self.ok,
+            "modified": self.modified,
+            "missing": self.missing,
+            "extra": self.extra,
+            "non_utf8": self.non_utf8,
+        })
+    }
+}
+
+/// Compares the files below `dir` with `manifest`. Only files found by
+/// walking `dir` are opened, so entries naming paths outside it (absolute,
+/// or through `..`) are reported missing rather than read.
+pub fn verify(
+    manifest: &Manifest,
+    dir: &Path,
+    exclude: Option<&str>,
+) -> Result<Verification> {
+    let tree = walk(dir)?;
+    let mut present: BTreeSet<String> = tree
+        .files
+        .into_iter()
+        .filter(|path| Some(path.as_str()) != exclude)
+        .collect();
+    let mut verification = Verification {
+        non_utf8: tree.non_utf8,
+        ..Verification::default()
+    };
+    let mut listed = BTreeMap::new();
+    for entry in &manifest.entries {
+        listed.insert(entry.path.as_str(), entry);
+    }
+    for (path, entry) in listed {
+        if !present.remove(path) {
+            verification.missing.push(path.to_string());
+            continue;
+        }
+        let (digest, size) = manifest.algorithm.digest_file(&dir.join(path))?;
+        if digest == entry.digest && entry.size.is_none_or(|s| s == size) {
+            verification.ok += 1;
+        } else {
+            verification.modified.push(path.to_string());
+        }
+    }
+    verification.extra = present.into_iter().collect();
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMPTY_SHA256: &str =
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn dot_slash_prefixes_are_normalized() {
+        let text =
+            format!("{EMPTY_SHA256}  ./a.txt\n{EMPTY_SHA256} *././sub/b.txt\n");
+        let manifest = Manifest::parse(&text, None).unwrap();
+        let paths: Vec<&str> =
+            manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["a.txt", "sub/b.txt"]);
+
+        let twice = format!("{EMPTY_SHA256}  ./a\n{EMPTY_SHA256}  a\n");
+        assert!(
+            Manifest::parse(&twice, None).is_err(),
+            "`./a` and `a` are the same file"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_skipped_not_fatal() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "").unwrap();
+        fs::write(dir.path().join(OsStr::from_bytes(b"bad\xff")), "").unwrap();
+
+        let (manifest, tree) =
+            create(dir.path(), Algorithm::Sha256, None).unwrap();
+        assert_eq!(manifest.entries.len(), 1);
+        assert_eq!(tree.non_utf8, ["bad\u{fffd}"]);
+
+        let text = format!("{EMPTY_SHA256}  ./a.txt\n");
+        let manifest = Manifest::parse(&text, None).unwrap();
+        let verification = verify(&manifest, dir.path(), None).unwrap();
+        assert!(verification.is_intact());
+        assert_eq!(verification.ok, 1);
+        assert_eq!(verification.non_utf8, ["bad\u{fffd}"]);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Read, Write};
+
+use anyhow::Result;
+use serde_json::Value as JsonValue;
+
+/// Default upper bound for a single record (`parse.jsonl_max_line_bytes`)
+/// so that one runaway line cannot exhaust memory; everything else is
+/// processed one line at a time.
+pub const MAX_LINE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Only the first bad line numbers are kept; the total is always counted.
+pub const MAX_REPORTED_LINES: usize = 100;
+
+/// Streams the non-blank lines of a JSON Lines document together with their
+/// 1-based line numbers, reusing a single buffer.
+pub struct Records<R> {
+    reader: R,
+    buf: Vec<u8>,
+    line: usize,
+    max_line_bytes: u64,
+}
+
+impl<R: BufRead> Records<R> {
+    pub fn new(reader: R) -> Self {
+        Records {
+            reader,
+            buf: Vec::new(),
+            line: 0,
+            max_line_bytes: crate::config::get().jsonl_max_line_bytes(),
+        }
+    }
+
+    pub fn next_raw(&mut self) -> Result<Option<(usize, &[u8])>> {
+        loop {
+            self.buf.clear();
+            let read = (&mut self.reader)
+                .take(self.max_line_bytes.saturating_add(1))
+                .read_until(b'\n', &mut self.buf)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if self.buf.len() as u64 > self.max_line_bytes {
+                return Err(crate::Error::Limit(format!(
+                    "Line {} exceeds the limit of {} bytes",
+                    self.line, self.max_line_bytes
+                ))
+                .into());
+            }
+            if !self.buf.trim_ascii().is_empty() {
+                return Ok(Some((self.line, self.buf.trim_ascii())));
+            }
+        }
+    }
+}
+
+pub fn count<R: BufRead>(reader: R) -> Result<usize> {
+    let mut records = Records::new(reader);
+    let mut total = 0;
+    while records.next_raw()?.is_some() {
+        total += 1;
+    }
+    Ok(total)
+}
+
+pub fn head<R: BufRead, W: Write>(
+    reader: R,
+    out: &mut W,
+    n: usize,
+) -> Result<()> {
+    let mut records = Records::new(reader);
+    for _ in 0..n {
+        match records.next_raw()? {
+            Some((_, raw)) => {
+                out.write_all(raw)?;
+                out.write_all(b"\n")?;
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+pub fn tail<R: BufRead, W: Write>(
+    reader: R,
+    out: &mut W,
+    n: usize,
+) -> Result<()> {
+    let mut records = Records::new(reader);
+    // The window grows as records arrive, so a huge `n` costs nothing up
+    // front.
+    let mut window: VecDeque<Vec<u8>> = VecDeque::new();
+    while let Some((_, raw)) = records.next_raw()? {
+        if n == 0 {
+            continue;
+        }
+        if window.len() == n {
+            window.pop_front();
+        }
+        window.push_back(raw.to_vec());
+    }
+    for raw in window {
+        out.write_all(&raw)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+pub struct FilterSummary {
+    pub matched: usize,
+    pub invalid: usize,
+    pub invalid_lines: Vec<usize>,
+}
+
+/// Copies every record whose value at `pointer` exists and, when `expected`
+/// is given, equals it. Records that are not valid JSON are skipped and
+/// their line numbers reported.
+pub fn filter<R: BufRead, W: Write>(
+    reader: R,
+    out: &mut W,
+    pointer: &str,
+    expected: Option<&JsonValue>,
+) -> Result<FilterSummary> {
+    let mut records = Records::new(reader);
+    let mut summary = FilterSummary {
+        matched: 0,
+        invalid: 0,
+        invalid_lines: Vec::new(),
+    };
+    while let Some((line, raw)) = records.next_raw()? {
+        let Ok(value) = serde_json::from_slice::<JsonValue>(raw) else {
+            summary.invalid += 1;
+            if summary.invalid_lines.len() < MAX_REPORTED_LINES {
+                summary.invalid_lines.push(line);
+            }
+            continue;
+        };
+        let matches = match (value.pointer(pointer), expected) {
+            (Some(found), Some(expected)) => found == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if matches {
+            out.write_all(raw)?;
+            out.write_all(b"\n")?;
+            summary.matched += 1;
+        }
+    }
+    Ok(summary)
+}
+
+pub struct Validation {
+    pub valid: usize,
+    pub invalid: usize,
+    pub errors: Vec<(usize, String)>,
+}
+
+pub fn validate<R: BufRead>(reader: R) -> Result<Validation> {
+    let mut records = Records::new(reader);
+    let mut report = Validation {
+        valid: 0,
+        invalid: 0,
+        errors: Vec::new(),
+    };
+    while let Some((line, raw)) = records.next_raw()? {
+        match serde_json::from_slice::<serde::de::IgnoredAny>(raw) {
+            Ok(_) => report.valid += 1,
+            Err(err) => {
+                report.invalid += 1;
+                if report.errors.len() < MAX_REPORTED_LINES {
+                    report.errors.push((line, err.to_string()));
+                }
+            }
+        }
+    }
+    Ok(report)
+}
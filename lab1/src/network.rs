@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value as JsonValue};
+use sysinfo::Networks;
+
+pub struct Interface {
+    pub name: String,
+    pub mac: Option<String>,
+    /// Addresses in CIDR notation, e.g. `192.168.1.2/24`.
+    pub addresses: Vec<String>,
+    pub mtu: u64,
+    pub received: u64,
+    pub transmitted: u64,
+    pub packets_received: u64,
+    pub packets_transmitted: u64,
+    pub errors_received: u64,
+    pub errors_transmitted: u64,
+}
+
+impl Interface {
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "name": self.name,
+            "mac": self.mac,
+            "addresses": self.addresses,
+            "mtu": self.mtu,
+            "received_bytes": self.received,
+            "transmitted_bytes": self.transmitted,
+            "packets_received": self.packets_received,
+            "packets_transmitted": self.packets_transmitted,
+            "errors_received": self.errors_received,
+            "errors_transmitted": self.errors_transmitted,
+        })
+    }
+}
+
+/// Interfaces sorted by name, with counters totalled since boot.
+pub fn interfaces() -> Vec<Interface> {
+    let networks = Networks::new_with_refreshed_list();
+    let mut interfaces: Vec<Interface> = networks
+        .iter()
+        .map(|(name, data)| Interface {
+            name: name.clone(),
+            mac: (!data.mac_address().is_unspecified())
+                .then(|| data.mac_address().to_string()),
+            addresses: data
+                .ip_networks()
+                .iter()
+                .map(|network| format!("{}/{}", network.addr, network.prefix))
+                .collect(),
+            mtu: data.mtu(),
+            received: data.total_received(),
+            transmitted: data.total_transmitted(),
+            packets_received: data.total_packets_received(),
+            packets_transmitted: data.total_packets_transmitted(),
+            errors_received: data.total_errors_on_received(),
+            errors_transmitted: data.total_errors_on_transmitted(),
+        })
+        .collect();
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    interfaces
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+/// Which sockets `sockets` returns.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StateFilter {
+    /// TCP sockets in LISTEN and unconnected UDP sockets.
+    Listening,
+    /// Listening sockets plus established TCP and connected UDP sockets.
+    ListeningOrEstablished,
+    All,
+}
+
+pub struct Socket {
+    pub protocol: Protocol,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub state: &'static str,
+    pub uid: u32,
+    pub inode: u64,
+    /// Owning processes as (PID, name); empty when the socket belongs to a
+    /// process whose descriptors we are not allowed to read.
+    pub owners: Vec<(u32, String)>,
+}
+
+impl Socket {
+    pub fn is_listening(&self) -> bool {
+        matches!(self.state, "LISTEN" | "UNCONN")
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let owners: Vec<JsonValue> = self
+            .owners
+            .iter()
+            .map(|(pid, name)| json!({ "pid": pid, "name": name }))
+            .collect();
+        json!({
+            "protocol": self.protocol.name(),
+            "local_address": self.local.ip().to_string(),
+            "local_port": self.local.port(),
+            "remote_address": self.remote.ip().to_string(),
+            "remote_port": self.remote.port(),
+            "state": self.state,
+            "uid": self.uid,
+            "inode": self.inode,
+            "processes": owners,
+        })
+    }
+}
+
+/// TCP states as numbered in the kernel's `include/net/tcp_states.h`.
+fn tcp_state(code: u8) -> &'static str {
+    match code {
+        0x01 => "ESTABLISHED",
+        0x02 => "SYN_SENT",
+        0x03 => "SYN_RECV",
+        0x04 => "FIN_WAIT1",
+        0x05 => "FIN_WAIT2",
+        0x06 => "TIME_WAIT",
+        0x07 => "CLOSE",
+        0x08 => "CLOSE_WAIT",
+        0x09 => "LAST_ACK",
+        0x0A => "LISTEN",
+        0x0B => "CLOSING",
+        _ => "UNKNOWN",
+    }
+}
+
+/// UDP reuses the TCP numbering: connected sockets report ESTABLISHED and
+/// unconnected ones CLOSE, which `ss` shows as UNCONN.
+fn udp_state(code: u8) -> &'static str {
+    match code {
+        0x01 => "ESTABLISHED",
+        0x07 => "UNCONN",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Parses `0100007F:0035`. The kernel prints each 32-bit word of the
+/// address in host byte order, so the words are converted back with
+/// `to_ne_bytes`.
+fn parse_address(field: &str) -> Result<SocketAddr> {
+    let (ip, port) = field
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Malformed address '{field}'"))?;
+    let port = u16::from_str_radix(port, 16)?;
+    let mut bytes = Vec::with_capacity(16);
+    for chunk in ip.as_bytes().chunks(8) {
+        let word = u32::from_str_radix(std::str::from_utf8(chunk)?, 16)?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+    let ip = if let Ok(octets) = <[u8; 4]>::try_from(bytes.as_slice()) {
+        IpAddr::V4(Ipv4Addr::from(octets))
+    } else if let Ok(octets) = <[u8; 16]>::try_from(bytes.as_slice()) {
+        IpAddr::V6(Ipv6Addr::from(octets))
+    } else {
+        return Err(anyhow!("Malformed address '{field}'"));
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Reads one `/proc/net/{tcp,tcp6,udp,udp6}` table; a missing table (e.g.
+/// IPv6 disabled) yields no sockets.
+fn read_table(path: &str, protocol: Protocol) -> Result<Vec<Socket>> {
+    let Ok(table) = fs::read_to_string(path) else {
+        return Ok(Vec::new());
+    };
+    let mut sockets = Vec::new();
+    for line in table.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            continue;
+        }
+        let code = u8::from_str_radix(fields[3], 16)
+            .with_context(|| format!("Malformed state in {path}"))?;
+        sockets.push(Socket {
+            protocol,
+            local: parse_address(fields[1])
+                .with_context(|| format!("Parsing {path}"))?,
+            remote: parse_address(fields[2])
+                .with_context(|| format!("Parsing {path}"))?,
+            state: match protocol {
+                Protocol::Tcp => tcp_state(code),
+                Protocol::Udp => udp_state(code),
+            },
+            uid: fields[7].parse()?,
+            inode: fields[9].parse()?,
+            owners: Vec::new(),
+        });
+    }
+    Ok(sockets)
+}
+
+/// Maps socket inodes to the processes holding them by reading the
+/// `socket:[inode]` links in every readable `/proc/<pid>/fd`.
+fn socket_owners() -> HashMap<u64, Vec<(u32, String)>> {
+    let mut owners: HashMap<u64, Vec<(u32, String)>> = HashMap::new();
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return owners;
+    };
+    for process in processes.flatten() {
+        let Some(pid) = process
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let Ok(fds) = fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        let name = fs::read_to_string(process.path().join("comm"))
+            .map(|comm| comm.trim_end().to_string())
+            .unwrap_or_default();
+        for fd in fds.flatten() {
+            let Ok(target) = fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = target
+                .to_str()
+                .and_then(|t| t.strip_prefix("socket:["))
+                .and_then(|t| t.strip_suffix(']'))
+                .and_then(|inode| inode.parse::<u64>().ok());
+            if let Some(inode) = inode {
+                let entry = owners.entry(inode).or_default();
+                if !entry.iter().any(|(owner, _)| *owner == pid) {
+                    entry.push((pid, name.clone()));
+                }
+            }
+        }
+    }
+    owners
+}
+
+pub fn sockets(
+    tcp: bool,
+    udp: bool,
+    filter: StateFilter,
+) -> Result<Vec<Socket>> {
+    let mut sockets = Vec::new();
+    if tcp {
+        sockets.extend(read_table("/proc/net/tcp", Protocol::Tcp)?);
+        sockets.extend(read_table("/proc/net/tcp6", Protocol::Tcp)?);
+    }
+    if udp {
+        sockets.extend(read_table("/proc/net/udp", Protocol::Udp)?);
+        sockets.extend(read_table("/proc/net/udp6", Protocol::Udp)?);
+    }
+    sockets.retain(|socket| match filter {
+        StateFilter::Listening => socket.is_listening(),
+        StateFilter::ListeningOrEstablished => {
+            socket.is_listening() || socket.state == "ESTABLISHED"
+        }
+        StateFilter::All => true,
+    });
+    let mut owners = socket_owners();
+    for socket in &mut sockets {
+        // Inode 0 marks sockets no longer attached to a descriptor, such as
+        // those in TIME_WAIT.
+        if socket.inode != 0 {
+            socket.owners = owners.remove(&socket.inode).unwrap_or_default();
+        }
+    }
+    sockets.sort_by_key(|socket| {
+        (
+            socket.protocol.name(),
+            !socket.is_listening(),
+            socket.local.port(),
+        )
+    });
+    Ok(sockets)
+}
@@ -0,0 +1,249 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde_json::{json, Value as JsonValue};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, Users};
+
+use crate::system;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Pid,
+    Name,
+    User,
+    /// Highest CPU usage first
+    Cpu,
+    /// Highest resident memory first
+    Mem,
+    /// Oldest first
+    Start,
+}
+
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub parent: Option<u32>,
+    pub name: String,
+    pub uid: Option<u32>,
+    pub user: Option<String>,
+    pub status: String,
+    pub cpu_percent: f32,
+    pub memory: u64,
+    pub virtual_memory: u64,
+    pub start_time: u64,
+    pub run_time: u64,
+    pub cmd: Vec<String>,
+    pub exe: Option<PathBuf>,
+    pub cwd: Option<PathBuf>,
+}
+
+impl ProcessInfo {
+    /// The command line, or the name in brackets for kernel threads and
+    /// processes whose command line is not readable, as `ps` shows them.
+    pub fn command_line(&self) -> String {
+        if self.cmd.is_empty() {
+            format!("[{}]", self.name)
+        } else {
+            self.cmd.join(" ")
+        }
+    }
+
+    pub fn user_label(&self) -> String {
+        match (&self.user, self.uid) {
+            (Some(user), _) => user.clone(),
+            (None, Some(uid)) => uid.to_string(),
+            (None, None) => "?".to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "pid": self.pid,
+            "parent": self.parent,
+            "name": self.name,
+            "uid": self.uid,
+            "user": self.user,
+            "status": self.status,
+            "cpu_percent": (f64::from(self.cpu_percent) * 10.0).round() / 10.0,
+            "memory_bytes": self.memory,
+            "virtual_memory_bytes": self.virtual_memory,
+            "start_time": system::rfc3339_utc(self.start_time),
+            "run_time_seconds": self.run_time,
+            "cmd": self.cmd,
+            "exe": self.exe.as_ref().map(|p| p.to_string_lossy()),
+            "cwd": self.cwd.as_ref().map(|p| p.to_string_lossy()),
+        })
+    }
+}
+
+/// Snapshots all processes (threads are left out). CPU usage needs two
+/// samples, so this blocks for sysinfo's minimum update interval.
+pub fn list() -> Vec<ProcessInfo> {
+    let mut system = System::new();
+    let kind = ProcessRefreshKind::everything().without_environ();
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, kind);
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, kind);
+    let users = Users::new_with_refreshed_list();
+    system
+        .processes()
+        .values()
+        .filter(|process| process.thread_kind().is_none())
+        .map(|process| ProcessInfo {
+            pid: process.pid().as_u32(),
+            parent: process.parent().map(Pid::as_u32),
+            name: process.name().to_string_lossy().into_owned(),
+            uid: process.user_id().map(|uid| **uid),
+            user: process
+                .user_id()
+                .and_then(|uid| users.get_user_by_id(uid))
+                .map(|user| user.name().to_string()),
+            status: process.status().to_string(),
+            cpu_percent: process.cpu_usage(),
+            memory: process.memory(),
+            virtual_memory: process.virtual_memory(),
+            start_time: process.start_time(),
+            run_time: process.run_time(),
+            cmd: process
+                .cmd()
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            exe: process.exe().map(PathBuf::from),
+            cwd: process.cwd().map(PathBuf::from),
+        })
+        .collect()
+}
+
+/// Keeps processes whose name or command line contains `pattern`
+/// (case-insensitively) and, when given, that belong to `user`.
+pub fn filter(
+    processes: &mut Vec<ProcessInfo>,
+    pattern: Option<&str>,
+    user: Option<&str>,
+) {
+    let pattern = pattern.map(str::to_lowercase);
+    processes.retain(|process| {
+        pattern.as_ref().is_none_or(|pattern| {
+            process.name.to_lowercase().contains(pattern)
+                || process.command_line().to_lowercase().contains(pattern)
+        }) && user.is_none_or(|user| process.user_label() == user)
+    });
+}
+
+pub fn sort(processes: &mut [ProcessInfo], key: SortKey, reverse: bool) {
+    processes.sort_by(|a, b| {
+        let order = match key {
+            SortKey::Pid => a.pid.cmp(&b.pid),
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::User => a.user_label().cmp(&b.user_label()),
+            SortKey::Cpu => b.cpu_percent.total_cmp(&a.cpu_percent),
+            SortKey::Mem => b.memory.cmp(&a.memory),
+            SortKey::Start => a.start_time.cmp(&b.start_time),
+        };
+        order.then(a.pid.cmp(&b.pid))
+    });
+    if reverse {
+        processes.reverse();
+    }
+}
+
+pub fn find(pid: u32) -> Result<ProcessInfo> {
+    list()
+        .into_iter()
+        .find(|process| process.pid == pid)
+        .ok_or_else(|| anyhow!("No process with PID {pid}"))
+}
+
+/// Descriptors and their targets from `/proc/<pid>/fd`. Reading another
+/// user's descriptors needs privileges, so this fails for them.
+pub fn open_files(pid: u32) -> Result<Vec<(u32, String)>> {
+    let dir = format!("/proc/{pid}/fd");
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("Reading {dir}"))? {
+        let entry = entry?;
+        let Some(fd) = entry.file_name().to_str().and_then(|n| n.parse().ok())
+        else {
+            continue;
+        };
+        // Descriptors can close between listing and reading the link.
+        if let Ok(target) = fs::read_link(entry.path()) {
+            files.push((fd, target.to_string_lossy().into_owned()));
+        }
+    }
+    files.sort_unstable();
+    Ok(files)
+}
+
+/// The environment of `pid`, which is only shown for processes whose real
+/// user is the effective user running osul.
+pub fn environment(process: &ProcessInfo) -> Result<Vec<String>> {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    let euid = unsafe { libc::geteuid() };
+    if process.uid != Some(euid) {
+        return Err(anyhow!(
+            "Environment hidden: process {} belongs to another user",
+            process.pid
+        ));
+    }
+    let path = format!("/proc/{}/environ", process.pid);
+    let raw = fs::read(&path).with_context(|| format!("Reading {path}"))?;
+    Ok(raw
+        .split(|&byte| byte == 0)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| String::from_utf8_lossy(entry).into_owned())
+        .collect())
+}
+
+const SIGNALS: [(&str, libc::c_int); 10] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("TERM", libc::SIGTERM),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+];
+
+/// Accepts `TERM`, `SIGTERM` (any case) or a number such as `15`.
+pub fn parse_signal(input: &str) -> Result<libc::c_int> {
+    let upper = input.trim().to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    if let Some((_, number)) = SIGNALS.iter().find(|(n, _)| *n == name) {
+        return Ok(*number);
+    }
+    match name.parse::<libc::c_int>() {
+        Ok(number) if (1..=64).contains(&number) => Ok(number),
+        _ => Err(anyhow!(
+            "Unknown signal '{input}' (use a number or one of {})",
+            SIGNALS.map(|(name, _)| name).join(", ")
+        )),
+    }
+}
+
+pub fn signal_name(signal: libc::c_int) -> String {
+    SIGNALS
+        .iter()
+        .find(|(_, number)| *number == signal)
+        .map_or_else(|| signal.to_string(), |(name, _)| format!("SIG{name}"))
+}
+
+pub fn send_signal(pid: u32, signal: libc::c_int) -> Result<()> {
+    // kill(2) treats 0 and negative PIDs as process groups; only ever
+    // signal a single positive PID.
+    let target = libc::pid_t::try_from(pid)
+        .ok()
+        .filter(|pid| *pid > 0)
+        .ok_or_else(|| anyhow!("Invalid PID {pid}"))?;
+    // SAFETY: kill has no memory-safety preconditions.
+    if unsafe { libc::kill(target, signal) } != 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| {
+            format!("Sending {} to {pid}", signal_name(signal))
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor};
+
+/// Returned when the user presses Ctrl-C at a prompt; the interactive menu
+/// treats it as "back to the main menu" rather than as a failure.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// What Tab completes at a prompt.
+pub enum Completion {
+    None,
+    /// Files and directories inside the sandbox.
+    Paths,
+    /// Entry names, e.g. of the archive chosen at the previous prompt.
+    Names(Vec<String>),
+}
+
+struct Helper {
+    completion: Completion,
+}
+
+impl Completer for Helper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let typed = &line[..pos];
+        Ok(match &self.completion {
+            Completion::None => (pos, Vec::new()),
+            Completion::Paths => complete_path(typed),
+            Completion::Names(names) => (
+                0,
+                names
+                    .iter()
+                    .filter(|name| name.starts_with(typed))
+                    .map(|name| Pair {
+                        display: name.clone(),
+                        replacement: name.clone(),
+                    })
+                    .collect(),
+            ),
+        })
+    }
+}
+
+/// Completes the last path component of `typed` from the directory it
+/// names. Directories that `sanitize_path` rejects offer nothing, so
+/// completion never reveals what lies outside the sandbox.
+fn complete_path(typed: &str) -> (usize, Vec<Pair>) {
+    let start = typed.rfind('/').map_or(0, |slash| slash + 1);
+    let (dir, prefix) = typed.split_at(start);
+    let dir = if dir.is_empty() { "." } else { dir };
+    let Ok(dir) = crate::sanitize_path(Path::new(dir), false) else {
+        return (start, Vec::new());
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return (start, Vec::new());
+    };
+    let mut candidates: Vec<Pair> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden entries only when asked for, as shells do.
+            if !name.starts_with(prefix)
+                || (name.starts_with('.') && !prefix.starts_with('.'))
+            {
+                return None;
+            }
+            let replacement = if entry.path().is_dir() {
+                format!("{name}/")
+            } else {
+                name.clone()
+            };
+            Some(Pair {
+                display: replacement.clone(),
+                replacement,
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.display.cmp(&b.display));
+    (start, candidates)
+}
+
+impl Hinter for Helper {
+    type Hint = String;
+}
+
+impl Highlighter for Helper {}
+
+impl Validator for Helper {}
+
+impl rustyline::Helper for Helper {}
+
+thread_local! {
+    static EDITOR: RefCell<Option<Editor<Helper, FileHistory>>> =
+        const { RefCell::new(None) };
+}
+
+fn new_editor() -> Result<Editor<Helper, FileHistory>> {
+    let config = crate::config::get();
+    let size = config.history_size();
+    let mut editor = Editor::with_config(
+        Config::builder()
+            .max_history_size(size.max(1))?
+            .auto_add_history(false)
+            .completion_type(CompletionType::List)
+            .build(),
+    )?;
+    if size > 0
+        && let Some(path) = config.history_file()
+    {
+        match editor.load_history(&path) {
+            Ok(()) => {}
+            Err(ReadlineError::Io(e))
+                if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!(
+                "Warning: cannot load history from {}: {e}",
+                path.display()
+            ),
+        }
+    }
+    Ok(editor)
+}
+
+fn remember(editor: &mut Editor<Helper, FileHistory>, line: &str) {
+    let config = crate::config::get();
+    let Some(path) =
+        config.history_file().filter(|_| config.history_size() > 0)
+    else {
+        return;
+    };
+    if line.trim().is_empty()
+        || !editor.add_history_entry(line).unwrap_or(false)
+    {
+        return;
+    }
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = editor.save_history(&path) {
+        eprintln!("Warning: cannot save history to {}: {e}", path.display());
+    }
+}
+
+/// Reads one line after `prompt`, or `None` at end of input. On a terminal
+/// this is a line editor with history (when `remember_line` is set) and Tab
+/// completion; Ctrl-C fails with [`Cancelled`]. Piped input is read plainly.
+pub fn read_line(
+    prompt: &str,
+    completion: Completion,
+    remember_line: bool,
+) -> Result<Option<String>> {
+    if !io::stdin().is_terminal() {
+        print!("{prompt}");
+        io::stdout().flush()?;
+        let mut buf = String::new();
+        return Ok(match io::stdin().read_line(&mut buf)? {
+            0 => None,
+            _ => Some(buf.trim().to_string()),
+        });
+    }
+    EDITOR.with_borrow_mut(|slot| {
+        let editor = match slot {
+            Some(editor) => editor,
+            None => slot.insert(new_editor()?),
+        };
+        editor.set_helper(Some(Helper { completion }));
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if remember_line {
+                    remember(editor, &line);
+                }
+                Ok(Some(line.trim().to_string()))
+            }
+            Err(ReadlineError::Interrupted) => Err(Cancelled.into()),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    })
+}
+
+/// `$XDG_STATE_HOME/osul/history`, falling back to
+/// `~/.local/state/osul/history`.
+pub fn default_history_file() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".local").join("state"))
+        })
+        .map(|dir| dir.join("osul").join("history"))
+}
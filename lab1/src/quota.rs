@@ -0,0 +1,158 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use serde_json::{json, Value as JsonValue};
+
+use crate::{audit, du, system};
+
+/// Limits on the sandbox root, from `quota.bytes` (a size such as `500M`)
+/// and `quota.files`. Unset means unlimited.
+pub struct Quota {
+    pub max_bytes: Option<u64>,
+    pub max_files: Option<u64>,
+}
+
+pub struct Usage {
+    /// Apparent size of all files, which is what writes add to.
+    pub bytes: u64,
+    pub files: u64,
+}
+
+impl Quota {
+    pub fn configured() -> Quota {
+        let config = crate::config::get();
+        Quota {
+            max_bytes: config.quota_bytes(),
+            max_files: config.quota_files(),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_bytes.is_none() && self.max_files.is_none()
+    }
+
+    /// Checks that replacing files of the given sizes (`None` for a file
+    /// that does not exist, before or after) keeps the sandbox within the
+    /// quota. Returns how many more bytes may be written on top of the
+    /// planned sizes, for writes whose final size is not known up front.
+    pub fn check(
+        &self,
+        root: &Path,
+        changes: &[(Option<u64>, Option<u64>)],
+    ) -> Result<Option<u64>> {
+        if self.is_unlimited() {
+            return Ok(None);
+        }
+        let usage = usage(root)?;
+        let (mut bytes, mut files) = (usage.bytes, usage.files);
+        for (before, after) in changes {
+            bytes =
+                bytes.saturating_sub(before.unwrap_or(0)) + after.unwrap_or(0);
+            files = files.saturating_sub(u64::from(before.is_some()))
+                + u64::from(after.is_some());
+        }
+        if let Some(max_files) = self.max_files
+            && files > max_files
+            && files > usage.files
+        {
+            return Err(crate::Error::Limit(format!(
+                "Quota exceeded: {files} files (limit {max_files})"
+            ))
+            .into());
+        }
+        match self.max_bytes {
+            Some(max_bytes) if bytes > max_bytes && bytes > usage.bytes => {
+                Err(crate::Error::Limit(format!(
+                    "Quota exceeded: the sandbox would use {} (limit {})",
+                    system::human_bytes(bytes),
+                    system::human_bytes(max_bytes)
+                ))
+                .into())
+            }
+            Some(max_bytes) => Ok(Some(max_bytes.saturating_sub(bytes))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn to_json(&self, usage: &Usage) -> JsonValue {
+        json!({
+            "bytes": usage.bytes,
+            "files": usage.files,
+            "max_bytes": self.max_bytes,
+            "max_files": self.max_files,
+        })
+    }
+}
+
+/// Current usage of the sandbox. The audit log is osul's own bookkeeping
+/// and is not counted, so recording a change can never exceed the quota.
+pub fn usage(root: &Path) -> Result<Usage> {
+    let report = du::analyze(
+        root,
+        &du::Options {
+            max_depth: Some(0),
+            top: 0,
+            exclude: Vec::new(),
+        },
+    )?;
+    let mut usage = Usage {
+        bytes: report.file_usage.apparent,
+        files: report.files,
+    };
+    let log = audit::log_path();
+    for path in [
+        audit::resolve(&log),
+        audit::resolve(&audit::head_path(&log)),
+    ] {
+        if path.starts_with(root)
+            && let Ok(metadata) = std::fs::symlink_metadata(&path)
+            && metadata.is_file()
+        {
+            usage.bytes = usage.bytes.saturating_sub(metadata.len());
+            usage.files = usage.files.saturating_sub(1);
+        }
+    }
+    Ok(usage)
+}
+
+/// Fails writes once `remaining` bytes have passed through, so a stream
+/// whose size was not known (or was misreported, as an archive entry's can
+/// be) cannot run past the quota.
+pub struct Limited<W> {
+    inner: W,
+    remaining: Option<u64>,
+}
+
+impl<W> Limited<W> {
+    pub fn new(inner: W, remaining: Option<u64>) -> Limited<W> {
+        Limited { inner, remaining }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Limited<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(remaining) = &mut self.remaining {
+            if buf.len() as u64 > *remaining {
+                return Err(io::Error::other(crate::Error::Limit(
+                    "Quota exceeded while writing".to_string(),
+                )));
+            }
+            *remaining -= buf.len() as u64;
+        }
+        self.inner.write(buf).inspect(|&written| {
+            // Give back what the inner writer did not take.
+            if let Some(remaining) = &mut self.remaining {
+                *remaining += (buf.len() - written) as u64;
+            }
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
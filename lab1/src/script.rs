@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// One non-blank, non-comment line of a script.
+#[derive(Debug)]
+pub enum Step {
+    /// An osul command line without the leading `osul`.
+    Command(Vec<String>),
+    /// `NAME=value`
+    Assign(String, String),
+    /// `set -e` / `set +e`: stop at the first failing command.
+    StopOnError(bool),
+    /// `set -x` / `set +x`: echo each command before running it.
+    Trace(bool),
+}
+
+/// Variables from `--var`, then assignments in the script; names not found
+/// there are looked up in the environment.
+pub struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    /// `definitions` are `NAME=VALUE` pairs.
+    pub fn new(definitions: &[String]) -> Result<Variables> {
+        let mut values = HashMap::new();
+        for definition in definitions {
+            let (name, value) =
+                split_assignment(definition).ok_or_else(|| {
+                    anyhow!("Expected NAME=VALUE, got '{definition}'")
+                })?;
+            values.insert(name.to_string(), value.to_string());
+        }
+        Ok(Variables { values })
+    }
+
+    pub fn set(&mut self, name: String, value: String) {
+        self.values.insert(name, value);
+    }
+
+    fn get(&self, name: &str) -> Result<String> {
+        self.values
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+            .ok_or_else(|| anyhow!("Undefined variable '{name}'"))
+    }
+
+    /// Expands the parts of `word` that were not single-quoted or escaped.
+    fn expand_word(&self, word: &Word) -> Result<String> {
+        let mut out = String::new();
+        for (text, expand) in &word.0 {
+            if *expand {
+                out.push_str(&self.expand(text)?);
+            } else {
+                out.push_str(text);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Replaces `$NAME` and `${NAME}` in one word; `$$` is a literal `$`.
+    /// Expansion happens after the line is split, so a value with spaces
+    /// stays a single argument.
+    fn expand(&self, word: &str) -> Result<String> {
+        let mut out = String::with_capacity(word.len());
+        let mut rest = word;
+        while let Some(index) = rest.find('$') {
+            out.push_str(&rest[..index]);
+            rest = &rest[index + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                out.push('$');
+                rest = after;
+            } else if let Some(braced) = rest.strip_prefix('{') {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| anyhow!("Unterminated '${{' in '{word}'"))?;
+                out.push_str(&self.get(&braced[..end])?);
+                rest = &braced[end + 1..];
+            } else {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    bail!("Expected a variable name after '$' in '{word}'");
+                }
+                out.push_str(&self.get(&rest[..end])?);
+                rest = &rest[end..];
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+/// A word of a script line as runs of text, each marked with whether it is
+/// subject to variable expansion: text in single quotes or after a
+/// backslash is not, as in a POSIX shell.
+#[derive(Default)]
+struct Word(Vec<(String, bool)>);
+
+impl Word {
+    fn push(&mut self, c: char, expand: bool) {
+        match self.0.last_mut() {
+            Some((text, last)) if *last == expand => text.push(c),
+            _ => self.0.push((c.to_string(), expand)),
+        }
+    }
+
+    /// The word with quotes removed and nothing expanded.
+    fn text(&self) -> String {
+        self.0.iter().map(|(text, _)| text.as_str()).collect()
+    }
+
+    /// The word without its first `len` bytes of text.
+    fn skip(&self, mut len: usize) -> Word {
+        let mut rest = Word::default();
+        for (text, expand) in &self.0 {
+            if len >= text.len() {
+                len -= text.len();
+            } else {
+                rest.0.push((text[len..].to_string(), *expand));
+                len = 0;
+            }
+        }
+        rest
+    }
+}
+
+/// Splits a line into words like `shlex::split`, but remembers which parts
+/// were quoted so that `'$HOME'` stays literal.
+fn split_words(line: &str) -> Result<Vec<Word>> {
+    let unbalanced = || anyhow!("Unbalanced quotes or trailing backslash");
+    let mut words = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '#' if word.is_none() => break,
+            '\\' => {
+                let escaped = chars.next().ok_or_else(unbalanced)?;
+                word.get_or_insert_default().push(escaped, false);
+            }
+            '\'' => {
+                let word = word.get_or_insert_default();
+                // An empty pair of quotes still makes a word.
+                word.0.push((String::new(), false));
+                loop {
+                    match chars.next().ok_or_else(unbalanced)? {
+                        '\'' => break,
+                        c => word.push(c, false),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                word.0.push((String::new(), true));
+                loop {
+                    match chars.next().ok_or_else(unbalanced)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unbalanced)? {
+                            c @ ('$' | '`' | '"' | '\\') => word.push(c, false),
+                            c => {
+                                word.push('\\', true);
+                                word.push(c, true);
+                            }
+                        },
+                        c => word.push(c, true),
+                    }
+                }
+            }
+            c => word.get_or_insert_default().push(c, true),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Subcommands that wait for keyboard input and would block a script.
+const INTERACTIVE: &[&[&str]] = &[&["tui"], &["xml", "build"]];
+
+/// Names the interactive command `words` would run, if any: one of
+/// [`INTERACTIVE`], or the menu when there is no subcommand at all.
+fn interactive(words: &[String]) -> Option<String> {
+    if words.iter().any(|word| {
+        matches!(word.as_str(), "-h" | "--help" | "-V" | "--version")
+    }) {
+        return None;
+    }
+    let names: Vec<&str> = words
+        .iter()
+        .map(String::as_str)
+        .filter(|word| !word.starts_with('-'))
+        .collect();
+    if names.is_empty() {
+        return Some("the interactive menu".to_string());
+    }
+    INTERACTIVE
+        .iter()
+        .find(|command| names.starts_with(command))
+        .map(|command| format!("`osul {}`", command.join(" ")))
+}
+
+fn interactive_error(command: &str) -> anyhow::Error {
+    anyhow!("{command} is interactive and cannot run in a script")
+}
+
+/// Rejects a script that names an interactive command on any line before
+/// anything runs. Commands hidden behind variables are caught by
+/// [`parse_line`] when their line is reached.
+pub fn check(source: &str) -> Result<()> {
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        // Malformed lines are reported when they are run.
+        let Ok(words) = split_words(trimmed) else {
+            continue;
+        };
+        let words: Vec<String> = words.iter().map(Word::text).collect();
+        let is_step = matches!(words.as_slice(), [set, _] if set == "set")
+            || words.first().and_then(|w| split_assignment(w)).is_some();
+        if !is_step && let Some(command) = interactive(&words) {
+            return Err(interactive_error(&command))
+                .with_context(|| format!("line {}", index + 1));
+        }
+    }
+    Ok(())
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn split_assignment(word: &str) -> Option<(&str, &str)> {
+    word.split_once('=').filter(|(name, _)| is_name(name))
+}
+
+/// Parses one line, returning `None` for blank lines and `#` comments.
+/// Words are split as a POSIX shell would, and variables expanded in each.
+pub fn parse_line(line: &str, variables: &Variables) -> Result<Option<Step>> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Ok(None);
+    }
+    let split = split_words(trimmed)?;
+    let words: Vec<String> = split.iter().map(Word::text).collect();
+    if let [word] = words.as_slice()
+        && let Some((name, _)) = split_assignment(word)
+    {
+        return Ok(Some(Step::Assign(
+            name.to_string(),
+            variables.expand_word(&split[0].skip(name.len() + 1))?,
+        )));
+    }
+    match words.as_slice() {
+        [] => Ok(None),
+        [set, flag] if set == "set" => match flag.as_str() {
+            "-e" => Ok(Some(Step::StopOnError(true))),
+            "+e" => Ok(Some(Step::StopOnError(false))),
+            "-x" => Ok(Some(Step::Trace(true))),
+            "+x" => Ok(Some(Step::Trace(false))),
+            _ => Err(anyhow!(
+                "Unknown option 'set {flag}' (use -e, +e, -x or +x)"
+            )),
+        },
+        [first, ..] if split_assignment(first).is_some() => Err(anyhow!(
+            "An assignment takes a single value; quote values with spaces"
+        )),
+        _ => {
+            let words: Vec<String> = split
+                .iter()
+                .map(|word| variables.expand_word(word))
+                .collect::<Result<_>>()?;
+            if let Some(command) = interactive(&words) {
+                return Err(interactive_error(&command));
+            }
+            Ok(Some(Step::Command(words)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: &str) -> Vec<String> {
+        let variables = Variables::new(&["NAME=a b".to_string()]).unwrap();
+        match parse_line(line, &variables) {
+            Ok(Some(Step::Command(words))) => words,
+            other => panic!("{line:?} parsed as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn single_quotes_and_escapes_are_not_expanded() {
+        assert_eq!(command("file read $NAME"), ["file", "read", "a b"]);
+        assert_eq!(command("file read \"$NAME\""), ["file", "read", "a b"]);
+        assert_eq!(command("file read '$NAME'"), ["file", "read", "$NAME"]);
+        assert_eq!(command("file read \\$NAME"), ["file", "read", "$NAME"]);
+        assert_eq!(command("file read \"\\$NAME\""), ["file", "read", "$NAME"]);
+        assert_eq!(
+            command("file write x '$NAME is '\"$NAME\""),
+            ["file", "write", "x", "$NAME is a b"]
+        );
+        assert_eq!(command("file write x ''"), ["file", "write", "x", ""]);
+
+        let mut variables = Variables::new(&[]).unwrap();
+        variables.set("NAME".into(), "a b".into());
+        match parse_line("V='$NAME'-$NAME", &variables).unwrap() {
+            Some(Step::Assign(name, value)) => {
+                assert_eq!((name.as_str(), value.as_str()), ("V", "$NAME-a b"))
+            }
+            other => panic!("parsed as {other:?}"),
+        }
+        assert!(parse_line("file read 'open", &variables).is_err());
+    }
+
+    #[test]
+    fn interactive_commands_are_rejected() {
+        assert!(check("file read a\n# tui\nset -e\n").is_ok());
+        assert!(check("tui --help\n").is_ok());
+        for script in
+            ["tui\n", "file read a\nxml build out.xml\n", "--dry-run\n"]
+        {
+            assert!(check(script).is_err(), "{script:?} was accepted");
+        }
+        let error = check("file read a\n\n  tui\n").unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "line 3: `osul tui` is interactive and cannot run in a script"
+        );
+
+        let variables = Variables::new(&["UI=tui".to_string()]).unwrap();
+        assert!(check("$UI\n").is_ok(), "only known once the line runs");
+        assert!(parse_line("$UI", &variables).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+const SECRET_KEY_LABEL: &str = "osul-ed25519-secret";
+const PUBLIC_KEY_LABEL: &str = "osul-ed25519-public";
+
+/// Size of either key file: label, space, hex-encoded key and newline.
+pub const KEY_FILE_LEN: u64 =
+    (SECRET_KEY_LABEL.len() + 2 + 2 * ed25519_dalek::SECRET_KEY_LENGTH) as u64;
+
+/// Public keys are stored next to the secret key with this suffix appended.
+pub const PUBLIC_KEY_SUFFIX: &str = ".pub";
+
+pub fn public_key_path(secret_path: &Path) -> PathBuf {
+    let mut name = secret_path.as_os_str().to_os_string();
+    name.push(PUBLIC_KEY_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Generates a key pair and writes the secret key (readable by the owner
+/// only) to `secret_path` and the public key to `public_path`.
+pub fn generate(secret_path: &Path, public_path: &Path) -> Result<()> {
+    let mut seed = [0u8; ed25519_dalek::SECRET_KEY_LENGTH];
+    getrandom::fill(&mut seed)
+        .map_err(|err| anyhow!("Gathering randomness failed: {err}"))?;
+    let key = SigningKey::from_bytes(&seed);
+
+    let mut secret = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(secret_path)
+        .with_context(|| format!("Creating {}", secret_path.display()))?;
+    writeln!(secret, "{SECRET_KEY_LABEL} {}", hex::encode(key.to_bytes()))?;
+    fs::write(
+        public_path,
+        format!(
+            "{PUBLIC_KEY_LABEL} {}\n",
+            hex::encode(key.verifying_key().to_bytes())
+        ),
+    )
+    .with_context(|| format!("Creating {}", public_path.display()))?;
+    Ok(())
+}
+
+fn read_key<const N: usize>(path: &Path, label: &str) -> Result<[u8; N]> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Reading key {}", path.display()))?;
+    let encoded = content
+        .trim()
+        .strip_prefix(label)
+        .ok_or_else(|| {
+            anyhow!("'{}' is not an {label} key file", path.display())
+        })?
+        .trim();
+    hex::decode(encoded)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("Key in '{}' is malformed", path.display()))
+}
+
+pub fn sign(secret_path: &Path, message: &[u8]) -> Result<String> {
+    let key = SigningKey::from_bytes(&read_key(secret_path, SECRET_KEY_LABEL)?);
+    Ok(hex::encode(key.sign(message).to_bytes()))
+}
+
+pub fn verify(
+    public_path: &Path,
+    message: &[u8],
+    signature: &str,
+) -> Result<()> {
+    let key =
+        VerifyingKey::from_bytes(&read_key(public_path, PUBLIC_KEY_LABEL)?)
+            .map_err(|_| {
+                anyhow!("Public key in '{}' is invalid", public_path.display())
+            })?;
+    let bytes: [u8; ed25519_dalek::SIGNATURE_LENGTH] =
+        hex::decode(signature.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow!("Signature is malformed"))?;
+    key.verify(message, &Signature::from_bytes(&bytes))
+        .map_err(|_| anyhow!("Signature verification failed"))
+}
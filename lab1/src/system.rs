@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::ffi::{CString, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value as JsonValue};
+use sysinfo::{Disks, System};
+
+/// Formats a byte count with binary units, e.g. `1.5 GiB`.
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Parses sizes such as `512`, `64K`, `1.5G` or `10GiB`. Units are binary
+/// (`K` = 1024) whether or not the `i` is written.
+pub fn parse_size(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{input}'"))?;
+    let exponent = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 1,
+        "m" | "mb" | "mib" => 2,
+        "g" | "gb" | "gib" => 3,
+        "t" | "tb" | "tib" => 4,
+        _ => return Err(anyhow!("Unknown size unit in '{input}'")),
+    };
+    let bytes = number * 1024f64.powi(exponent);
+    if bytes >= u64::MAX as f64 {
+        return Err(anyhow!("Size '{input}' is too large"));
+    }
+    Ok(bytes as u64)
+}
+
+/// Free bytes on the filesystem holding `path`, the figure `zip_extract`
+/// checks before writing. Unlike `DiskInfo::available` this includes
+/// blocks reserved for root.
+pub fn free_space(path: &Path) -> Result<u64> {
+    fs2::free_space(path)
+        .with_context(|| format!("Querying free space for {}", path.display()))
+}
+
+/// The current time as an RFC 3339 UTC timestamp, e.g.
+/// `2024-05-01T12:00:00Z`.
+pub fn utc_timestamp() -> String {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    rfc3339_utc(seconds)
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 UTC timestamp.
+pub fn rfc3339_utc(seconds: u64) -> String {
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    // Civil date from days since the epoch (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
+
+pub fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        // One decimal place is plenty and keeps the JSON output stable.
+        (part as f64 * 1000.0 / total as f64).round() / 10.0
+    }
+}
+
+pub struct Inodes {
+    pub total: u64,
+    pub free: u64,
+}
+
+impl Inodes {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+}
+
+/// Inode counts from `statvfs(3)`; `None` when the call fails or the
+/// filesystem does not track inodes (e.g. FAT), which reports zero.
+pub fn inodes(path: &Path) -> Option<Inodes> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stats` is only read after
+    // statvfs reports success, at which point it has been filled in.
+    let stats = unsafe {
+        if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stats.assume_init()
+    };
+    #[allow(clippy::unnecessary_cast)] // fsfilcnt_t is not u64 everywhere
+    let (total, free) = (stats.f_files as u64, stats.f_ffree as u64);
+    (total > 0).then_some(Inodes { total, free })
+}
+
+/// Undoes the octal escapes (`\040` for a space) used in `/proc/self/mounts`.
+fn unescape_mount_field(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok()
+        });
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(out))
+}
+
+/// Mount options keyed by mount point. Empty where `/proc` is unavailable.
+fn mount_options() -> HashMap<PathBuf, String> {
+    let Ok(mounts) = std::fs::read_to_string("/proc/self/mounts") else {
+        return HashMap::new();
+    };
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = unescape_mount_field(fields.nth(1)?);
+            let options = fields.nth(1)?.to_string();
+            Some((mount_point, options))
+        })
+        .collect()
+}
+
+pub struct DiskInfo {
+    pub name: String,
+    pub mount_point: PathBuf,
+    pub file_system: String,
+    pub kind: String,
+    pub removable: bool,
+    pub read_only: bool,
+    pub total: u64,
+    pub available: u64,
+    pub mount_options: Option<String>,
+    pub inodes: Option<Inodes>,
+}
+
+impl DiskInfo {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let options = self
+            .mount_options
+            .as_ref()
+            .map(|options| options.split(',').collect::<Vec<_>>());
+        let inodes = self.inodes.as_ref().map(|inodes| {
+            json!({
+                "total": inodes.total,
+                "used": inodes.used(),
+                "free": inodes.free,
+                "used_percent": percent(inodes.used(), inodes.total),
+            })
+        });
+        json!({
+            "name": self.name,
+            "mount_point": self.mount_point.to_string_lossy(),
+            "file_system": self.file_system,
+            "kind": self.kind,
+            "removable": self.removable,
+            "read_only": self.read_only,
+            "total_bytes": self.total,
+            "used_bytes": self.used(),
+            "available_bytes": self.available,
+            "used_percent": percent(self.used(), self.total),
+            "mount_options": options,
+            "inodes": inodes,
+        })
+    }
+}
+
+/// Lists disks, keeping only those mounted at or below `mount` when given.
+pub fn disks(mount: Option<&Path>) -> Vec<DiskInfo> {
+    let options = mount_options();
+    Disks::new_with_refreshed_list()
+        .iter()
+        .filter(|disk| mount.is_none_or(|m| disk.mount_point().starts_with(m)))
+        .map(|disk| DiskInfo {
+            name: disk.name().to_string_lossy().into_owned(),
+            mount_point: disk.mount_point().to_path_buf(),
+            file_system: disk.file_system().to_string_lossy().into_owned(),
+            kind: disk.kind().to_string(),
+            removable: disk.is_removable(),
+            read_only: disk.is_read_only(),
+            total: disk.total_space(),
+            available: disk.available_space(),
+            mount_options: options.get(disk.mount_point()).cloned(),
+            inodes: inodes(disk.mount_point()),
+        })
+        .collect()
+}
+
+/// Memory, swap, CPU, load, uptime and OS details. CPU usage needs two
+/// samples, so this blocks for sysinfo's minimum update interval.
+pub fn system_json() -> JsonValue {
+    let mut system = System::new();
+    system.refresh_memory();
+    system.refresh_cpu_all();
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    system.refresh_cpu_usage();
+    let load = System::load_average();
+    let cpu = system.cpus().first();
+    json!({
+        "os": {
+            "name": System::name(),
+            "version": System::os_version(),
+            "long_version": System::long_os_version(),
+            "kernel": System::kernel_version(),
+            "host_name": System::host_name(),
+            "arch": System::cpu_arch(),
+        },
+        "uptime_seconds": System::uptime(),
+        "boot_time": System::boot_time(),
+        "load_average": [load.one, load.five, load.fifteen],
+        "cpu": {
+            "brand": cpu.map(|c| c.brand().trim().to_string()),
+            "frequency_mhz": cpu.map(|c| c.frequency()),
+            "logical_cores": system.cpus().len(),
+            "physical_cores": System::physical_core_count(),
+            "usage_percent": (f64::from(system.global_cpu_usage()) * 10.0)
+                .round()
+                / 10.0,
+        },
+        "memory": {
+            "total_bytes": system.total_memory(),
+            "used_bytes": system.used_memory(),
+            "available_bytes": system.available_memory(),
+            "used_percent": percent(system.used_memory(), system.total_memory()),
+        },
+        "swap": {
+            "total_bytes": system.total_swap(),
+            "used_bytes": system.used_swap(),
+            "used_percent": percent(system.used_swap(), system.total_swap()),
+        },
+    })
+}
+
+/// Formats seconds as e.g. `3d 4h 5m`.
+pub fn human_duration(seconds: u64) -> String {
+    let (days, hours, minutes) = (
+        seconds / 86_400,
+        seconds % 86_400 / 3_600,
+        seconds % 3_600 / 60,
+    );
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
@@ -0,0 +1,729 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use ratatui::crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers,
+};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen};
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{
+    Block, Clear, List, ListItem, ListState, Paragraph, Wrap,
+};
+use ratatui::{DefaultTerminal, Frame};
+use zip::ZipArchive;
+
+use crate::xml_safe;
+
+/// Text previews read at most this much of a file.
+const PREVIEW_BYTES: u64 = 64 * 1024;
+/// JSON and XML are only pretty-printed up to this size.
+const PARSE_PREVIEW_BYTES: u64 = 1024 * 1024;
+const HELP: &str = "↑↓ move  →/Enter open  ← close  PgUp/PgDn scroll  \
+                    n new  w write  d delete  x extract  r refresh  q quit";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Dir,
+    File,
+    Symlink,
+    Other,
+}
+
+struct Node {
+    path: PathBuf,
+    name: String,
+    depth: usize,
+    kind: Kind,
+}
+
+/// A mutating operation, run through the same functions as the menus and
+/// the CLI once the user has confirmed it.
+enum Action {
+    Create(PathBuf),
+    Write(PathBuf, String),
+    Delete(PathBuf),
+    Extract(PathBuf, String),
+}
+
+enum Input {
+    Create { dir: PathBuf },
+    Write { path: PathBuf },
+}
+
+enum Dialog {
+    Input {
+        title: String,
+        value: String,
+        purpose: Input,
+    },
+    Pick {
+        archive: PathBuf,
+        entries: Vec<String>,
+        state: ListState,
+    },
+    Confirm {
+        message: String,
+        action: Action,
+    },
+}
+
+struct App {
+    root: PathBuf,
+    /// Directories shown open. Symlinks are never opened, so the tree
+    /// cannot lead out of the sandbox.
+    expanded: HashSet<PathBuf>,
+    nodes: Vec<Node>,
+    list: ListState,
+    preview: Vec<String>,
+    scroll: u16,
+    dialog: Option<Dialog>,
+    status: String,
+    quit: bool,
+}
+
+/// Full-screen browser over `root`, the sandbox.
+pub fn run(root: PathBuf) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let mut app = App::new(root);
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn new(root: PathBuf) -> App {
+        let mut app = App {
+            root,
+            expanded: HashSet::new(),
+            nodes: Vec::new(),
+            list: ListState::default(),
+            preview: Vec::new(),
+            scroll: 0,
+            dialog: None,
+            status: String::new(),
+            quit: false,
+        };
+        app.rebuild();
+        app
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.modifiers.contains(KeyModifiers::CONTROL)
+                && key.code == KeyCode::Char('c')
+            {
+                // Ctrl-C backs out of a dialog first, like Esc.
+                if self.dialog.take().is_none() {
+                    self.quit = true;
+                }
+                continue;
+            }
+            if let Some(action) = self.handle_key(key.code) {
+                self.perform(terminal, action)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&Node> {
+        self.list.selected().and_then(|index| self.nodes.get(index))
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) if relative.as_os_str().is_empty() => ".".into(),
+            Ok(relative) => format!("./{}", relative.display()),
+            Err(_) => path.display().to_string(),
+        }
+    }
+
+    /// Re-reads the visible part of the tree, keeping the selection on the
+    /// same path where it still exists.
+    fn rebuild(&mut self) {
+        let selected = self.selected().map(|node| node.path.clone());
+        self.nodes.clear();
+        let root = self.root.clone();
+        self.walk(&root, 0);
+        let index = selected
+            .and_then(|path| self.nodes.iter().position(|n| n.path == path))
+            .or_else(|| {
+                self.list
+                    .selected()
+                    .map(|index| index.min(self.nodes.len().saturating_sub(1)))
+            })
+            .or(Some(0))
+            .filter(|_| !self.nodes.is_empty());
+        self.list.select(index);
+        self.refresh_preview();
+    }
+
+    fn walk(&mut self, dir: &Path, depth: usize) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut children: Vec<Node> = entries
+            .flatten()
+            .map(|entry| {
+                let kind = match entry.file_type() {
+                    Ok(t) if t.is_symlink() => Kind::Symlink,
+                    Ok(t) if t.is_dir() => Kind::Dir,
+                    Ok(t) if t.is_file() => Kind::File,
+                    _ => Kind::Other,
+                };
+                Node {
+                    path: entry.path(),
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    depth,
+                    kind,
+                }
+            })
+            .collect();
+        children.sort_by(|a, b| {
+            (a.kind != Kind::Dir, &a.name).cmp(&(b.kind != Kind::Dir, &b.name))
+        });
+        for child in children {
+            let open =
+                child.kind == Kind::Dir && self.expanded.contains(&child.path);
+            let path = child.path.clone();
+            self.nodes.push(child);
+            if open {
+                self.walk(&path, depth + 1);
+            }
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        if !self.nodes.is_empty() {
+            self.list.select(Some(index.min(self.nodes.len() - 1)));
+            self.refresh_preview();
+        }
+    }
+
+    fn refresh_preview(&mut self) {
+        self.scroll = 0;
+        self.preview = match self.selected() {
+            Some(node) => preview(node),
+            None => vec!["(empty directory)".to_string()],
+        };
+    }
+
+    /// Returns the action to run once a confirmation is accepted.
+    fn handle_key(&mut self, code: KeyCode) -> Option<Action> {
+        if let Some(dialog) = self.dialog.take() {
+            return self.handle_dialog_key(dialog, code);
+        }
+        let current = self.list.selected().unwrap_or(0);
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.select(current + 1),
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.select(current.saturating_sub(1))
+            }
+            KeyCode::Home | KeyCode::Char('g') => self.select(0),
+            KeyCode::End | KeyCode::Char('G') => self.select(usize::MAX),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Right | KeyCode::Enter | KeyCode::Char('l') => {
+                if let Some(node) = self.selected()
+                    && node.kind == Kind::Dir
+                {
+                    self.expanded.insert(node.path.clone());
+                    self.rebuild();
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.close_or_parent(),
+            KeyCode::Char('r') => {
+                self.rebuild();
+                self.status = "Refreshed".to_string();
+            }
+            KeyCode::Char('n') => {
+                let dir = match self.selected() {
+                    Some(node) if node.kind == Kind::Dir => node.path.clone(),
+                    Some(node) => node
+                        .path
+                        .parent()
+                        .map_or_else(|| self.root.clone(), Path::to_path_buf),
+                    None => self.root.clone(),
+                };
+                self.dialog = Some(Dialog::Input {
+                    title: format!("New file in {}", self.relative(&dir)),
+                    value: String::new(),
+                    purpose: Input::Create { dir },
+                });
+            }
+            KeyCode::Char('w') => match self.selected() {
+                Some(node) if node.kind == Kind::File => {
+                    let path = node.path.clone();
+                    self.dialog = Some(Dialog::Input {
+                        title: format!(
+                            "Replace contents of {}",
+                            self.relative(&path)
+                        ),
+                        value: String::new(),
+                        purpose: Input::Write { path },
+                    });
+                }
+                _ => self.status = "Select a file to write".to_string(),
+            },
+            KeyCode::Char('d') => match self.selected() {
+                Some(node)
+                    if matches!(node.kind, Kind::File | Kind::Symlink) =>
+                {
+                    let path = node.path.clone();
+                    self.dialog = Some(Dialog::Confirm {
+                        message: format!("Delete {}?", self.relative(&path)),
+                        action: Action::Delete(path),
+                    });
+                }
+                _ => self.status = "Select a file to delete".to_string(),
+            },
+            KeyCode::Char('x') => self.start_extract(),
+            _ => {}
+        }
+        None
+    }
+
+    fn close_or_parent(&mut self) {
+        let Some(node) = self.selected() else {
+            return;
+        };
+        let path = node.path.clone();
+        if node.kind == Kind::Dir && self.expanded.remove(&path) {
+            self.rebuild();
+            return;
+        }
+        let parent = path.parent().map(Path::to_path_buf);
+        if let Some(index) = parent
+            .and_then(|parent| self.nodes.iter().position(|n| n.path == parent))
+        {
+            self.select(index);
+        }
+    }
+
+    fn start_extract(&mut self) {
+        let Some(node) = self.selected().filter(|node| node.kind == Kind::File)
+        else {
+            self.status = "Select an archive to extract from".to_string();
+            return;
+        };
+        let archive = node.path.clone();
+        match archive_entries(&archive) {
+            Ok(entries) if entries.is_empty() => {
+                self.status = "The archive has no files".to_string();
+            }
+            Ok(entries) => {
+                self.dialog = Some(Dialog::Pick {
+                    archive,
+                    entries,
+                    state: ListState::default().with_selected(Some(0)),
+                });
+            }
+            Err(e) => self.status = format!("Not an archive: {e}"),
+        }
+    }
+
+    fn handle_dialog_key(
+        &mut self,
+        dialog: Dialog,
+        code: KeyCode,
+    ) -> Option<Action> {
+        match dialog {
+            Dialog::Confirm { action, .. }
+                if matches!(code, KeyCode::Char('y' | 'Y')) =>
+            {
+                return Some(action);
+            }
+            Dialog::Confirm { message, action } => {
+                if !matches!(code, KeyCode::Char('n' | 'N') | KeyCode::Esc) {
+                    self.dialog = Some(Dialog::Confirm { message, action });
+                }
+            }
+            Dialog::Input {
+                mut value,
+                title,
+                purpose,
+            } => match code {
+                KeyCode::Esc => {}
+                KeyCode::Enter => self.submit_input(value, purpose),
+                KeyCode::Backspace => {
+                    value.pop();
+                    self.dialog = Some(Dialog::Input {
+                        title,
+                        value,
+                        purpose,
+                    });
+                }
+                KeyCode::Char(c) => {
+                    value.push(c);
+                    self.dialog = Some(Dialog::Input {
+                        title,
+                        value,
+                        purpose,
+                    });
+                }
+                _ => {
+                    self.dialog = Some(Dialog::Input {
+                        title,
+                        value,
+                        purpose,
+                    })
+                }
+            },
+            Dialog::Pick {
+                archive,
+                entries,
+                mut state,
+            } => match code {
+                KeyCode::Esc => {}
+                KeyCode::Enter => {
+                    if let Some(entry) =
+                        state.selected().and_then(|i| entries.get(i))
+                    {
+                        self.dialog = Some(Dialog::Confirm {
+                            message: format!(
+                                "Extract {entry} from {} into the working directory?",
+                                self.relative(&archive)
+                            ),
+                            action: Action::Extract(archive, entry.clone()),
+                        });
+                    }
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    state.select_next();
+                    self.dialog = Some(Dialog::Pick {
+                        archive,
+                        entries,
+                        state,
+                    });
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    state.select_previous();
+                    self.dialog = Some(Dialog::Pick {
+                        archive,
+                        entries,
+                        state,
+                    });
+                }
+                _ => {
+                    self.dialog = Some(Dialog::Pick {
+                        archive,
+                        entries,
+                        state,
+                    })
+                }
+            },
+        }
+        None
+    }
+
+    fn submit_input(&mut self, value: String, purpose: Input) {
+        self.dialog = Some(match purpose {
+            Input::Create { .. } if value.trim().is_empty() => return,
+            Input::Create { dir } => {
+                let path = dir.join(value.trim());
+                Dialog::Confirm {
+                    message: format!("Create {}?", self.relative(&path)),
+                    action: Action::Create(path),
+                }
+            }
+            Input::Write { path } => Dialog::Confirm {
+                message: format!(
+                    "Overwrite {} with {} bytes?",
+                    self.relative(&path),
+                    value.len()
+                ),
+                action: Action::Write(path, value),
+            },
+        });
+    }
+
+    /// Leaves the full-screen view while the operation runs, so that its
+    /// messages (including dry-run reports) read as they do elsewhere.
+    fn perform(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        action: Action,
+    ) -> Result<()> {
+        ratatui::restore();
+        println!();
+        let result = match &action {
+            Action::Create(path) => crate::file_create(path),
+            Action::Write(path, content) => crate::file_write(path, content),
+            Action::Delete(path) => crate::file_delete(path),
+            Action::Extract(archive, entry) => {
+                crate::zip_extract(archive, entry)
+            }
+        };
+        if let Err(e) = &result {
+            println!("Error: {e:#}");
+        }
+        print!("\nPress Enter to return to the browser");
+        io::stdout().flush()?;
+        io::stdin().read_line(&mut String::new())?;
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        terminal.clear()?;
+        self.status = match result {
+            Ok(()) => "Done".to_string(),
+            Err(e) => format!("Failed: {e}"),
+        };
+        if let Action::Create(path) = &action
+            && let Some(parent) = path.parent()
+        {
+            self.expanded.insert(parent.to_path_buf());
+        }
+        self.rebuild();
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)])
+                .areas(frame.area());
+        let [tree, preview] = Layout::horizontal([
+            Constraint::Percentage(35),
+            Constraint::Percentage(65),
+        ])
+        .areas(main);
+
+        let items: Vec<ListItem> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let marker = match node.kind {
+                    Kind::Dir if self.expanded.contains(&node.path) => "▾ ",
+                    Kind::Dir => "▸ ",
+                    Kind::Symlink => "@ ",
+                    _ => "  ",
+                };
+                let suffix = if node.kind == Kind::Dir { "/" } else { "" };
+                ListItem::new(format!(
+                    "{}{marker}{}{suffix}",
+                    "  ".repeat(node.depth),
+                    node.name
+                ))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::bordered().title(format!(" {} ", self.root.display())),
+            )
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, tree, &mut self.list);
+
+        let title = self.selected().map_or_else(String::new, |node| {
+            format!(" {} ", self.relative(&node.path))
+        });
+        let lines: Vec<Line> = self
+            .preview
+            .iter()
+            .map(|line| Line::raw(line.as_str()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::bordered().title(title))
+                .scroll((self.scroll, 0)),
+            preview,
+        );
+
+        let mut status_line = if self.status.is_empty() {
+            HELP.to_string()
+        } else {
+            format!("{}  |  {HELP}", self.status)
+        };
+        if crate::dry_run() {
+            status_line.insert_str(0, "[dry run] ");
+        }
+        frame.render_widget(
+            Paragraph::new(status_line)
+                .style(Style::new().add_modifier(Modifier::REVERSED)),
+            status,
+        );
+
+        if let Some(dialog) = &mut self.dialog {
+            draw_dialog(frame, dialog);
+        }
+    }
+}
+
+fn draw_dialog(frame: &mut Frame, dialog: &mut Dialog) {
+    match dialog {
+        Dialog::Input { title, value, .. } => {
+            let area = centered(frame.area(), 60, 3);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(format!("{value}▏")).block(
+                    Block::bordered()
+                        .title(format!(" {title} "))
+                        .title_bottom(" Enter accept · Esc cancel "),
+                ),
+                area,
+            );
+        }
+        Dialog::Confirm { message, .. } => {
+            let area = centered(frame.area(), 60, 4);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(message.as_str())
+                    .wrap(Wrap { trim: true })
+                    .block(
+                        Block::bordered()
+                            .title(" Confirm ")
+                            .title_bottom(" y yes · n no "),
+                    ),
+                area,
+            );
+        }
+        Dialog::Pick { entries, state, .. } => {
+            let height = u16::try_from(entries.len())
+                .unwrap_or(u16::MAX)
+                .saturating_add(2);
+            let area = centered(frame.area(), 60, height.min(20));
+            frame.render_widget(Clear, area);
+            let list = List::new(entries.iter().map(String::as_str))
+                .block(
+                    Block::bordered()
+                        .title(" Extract which entry? ")
+                        .title_bottom(" Enter choose · Esc cancel "),
+                )
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(list, area, state);
+        }
+    }
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] =
+        Layout::horizontal([Constraint::Length(width.min(area.width))])
+            .flex(Flex::Center)
+            .areas(area);
+    let [area] =
+        Layout::vertical([Constraint::Length(height.min(area.height))])
+            .flex(Flex::Center)
+            .areas(area);
+    area
+}
+
+fn archive_entries(path: &Path) -> Result<Vec<String>> {
+    let path = crate::sanitize_path(path, false)?;
+    let archive = ZipArchive::new(File::open(path)?)?;
+    Ok(archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .map(str::to_string)
+        .collect())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// Tabs and control characters would garble the terminal.
+fn printable(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| {
+            line.replace('\t', "    ")
+                .chars()
+                .map(|c| if c.is_control() { '?' } else { c })
+                .collect()
+        })
+        .collect()
+}
+
+/// Pretty JSON, indented XML, archive listings and plain text; anything
+/// else is described rather than shown.
+fn preview(node: &Node) -> Vec<String> {
+    match preview_lines(node) {
+        Ok(lines) => lines,
+        Err(e) => vec![format!("Cannot preview: {e:#}")],
+    }
+}
+
+fn preview_lines(node: &Node) -> Result<Vec<String>> {
+    // Follows symlinks only to targets inside the sandbox.
+    let path = crate::sanitize_path(&node.path, false)?;
+    let metadata = fs::metadata(&path)?;
+    let mut lines = Vec::new();
+    if node.kind == Kind::Symlink {
+        lines.push(format!("Symlink to {}", path.display()));
+        lines.push(String::new());
+    }
+    if metadata.is_dir() {
+        let mut names: Vec<String> = fs::read_dir(&path)?
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        lines.push(format!("Directory with {} entries", names.len()));
+        lines.extend(names);
+        return Ok(lines);
+    }
+    let size = metadata.len();
+    if has_extension(&path, "zip") {
+        let mut archive = ZipArchive::new(File::open(&path)?)?;
+        lines.push(format!("Zip archive, {} entries", archive.len()));
+        lines.push(format!("{:>12} {:>12}  name", "size", "compressed"));
+        for index in 0..archive.len() {
+            let entry = archive.by_index(index)?;
+            lines.push(format!(
+                "{:>12} {:>12}  {}",
+                entry.size(),
+                entry.compressed_size(),
+                entry.name()
+            ));
+        }
+        return Ok(lines);
+    }
+    if size <= PARSE_PREVIEW_BYTES {
+        if has_extension(&path, "json") {
+            let text = fs::read_to_string(&path)?;
+            match serde_json::from_str::<serde_json::Value>(&text) {
+                Ok(value) => {
+                    lines.extend(printable(&serde_json::to_string_pretty(
+                        &value,
+                    )?));
+                    return Ok(lines);
+                }
+                Err(e) => lines.push(format!("Invalid JSON: {e}")),
+            }
+        } else if has_extension(&path, "xml") {
+            match xml_safe::parse_file(
+                &path,
+                &crate::config::get().xml_limits(),
+            ) {
+                Ok(root) => {
+                    lines.extend(printable(&crate::xml_render(&root)?));
+                    return Ok(lines);
+                }
+                Err(e) => lines.push(format!("Invalid XML: {e:#}")),
+            }
+        }
+    }
+    let mut buf = Vec::new();
+    File::open(&path)?
+        .take(PREVIEW_BYTES)
+        .read_to_end(&mut buf)?;
+    if buf.contains(&0) {
+        lines.push(format!("Binary file, {} bytes", size));
+        return Ok(lines);
+    }
+    lines.extend(printable(&String::from_utf8_lossy(&buf)));
+    if size > PREVIEW_BYTES {
+        lines.push(format!("… {} more bytes", size - PREVIEW_BYTES));
+    }
+    Ok(lines)
+}
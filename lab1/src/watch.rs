@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+
+use crate::system::{self, DiskInfo};
+
+pub struct Options {
+    pub interval: Duration,
+    /// Used percentage at which a filesystem is shown as `WARN`.
+    pub warn_percent: f64,
+    /// Used percentage at which a filesystem is shown as `CRIT`.
+    pub critical_percent: f64,
+    /// Alert when free space on a filesystem drops below this many bytes.
+    pub min_free: Option<u64>,
+    /// Append alerts as JSON lines here in addition to stderr.
+    pub alert_log: Option<PathBuf>,
+    pub mount: Option<PathBuf>,
+    /// Stop after this many refreshes; run until interrupted otherwise.
+    pub count: Option<u64>,
+}
+
+impl Options {
+    pub fn validate(&self) -> Result<()> {
+        let valid = 0.0..=100.0;
+        if !valid.contains(&self.warn_percent)
+            || !valid.contains(&self.critical_percent)
+        {
+            return Err(anyhow!("Thresholds must be percentages (0-100)"));
+        }
+        if self.warn_percent > self.critical_percent {
+            return Err(anyhow!(
+                "Warning threshold {}% is above the critical threshold {}%",
+                self.warn_percent,
+                self.critical_percent
+            ));
+        }
+        if self.interval.is_zero() {
+            return Err(anyhow!("Interval must be positive"));
+        }
+        Ok(())
+    }
+}
+
+/// Refreshes the disk list every interval and prints one line per
+/// filesystem. Free space comes from `fs2::free_space`, as in
+/// `zip_extract`; an alert is raised when it falls below `min_free` and
+/// again (as `recovered`) once it is back above, not on every refresh.
+pub fn run(options: &Options) -> Result<()> {
+    options.validate()?;
+    let color = std::io::stdout().is_terminal();
+    let mut low: HashSet<PathBuf> = HashSet::new();
+    let mut refreshes = 0;
+    loop {
+        let disks = system::disks(options.mount.as_deref());
+        if disks.is_empty() {
+            return Err(anyhow!("No disks mounted at or below the given path"));
+        }
+        if color {
+            // Redraw in place, like watch(1).
+            print!("\x1b[2J\x1b[H");
+        }
+        println!(
+            "{} (every {}s, warn {}%, critical {}%)",
+            system::utc_timestamp(),
+            options.interval.as_secs_f64(),
+            options.warn_percent,
+            options.critical_percent
+        );
+        println!(
+            "{:<6} {:>7} {:>10} {:>10}  Mount point",
+            "Status", "Used", "Free", "Size"
+        );
+        for disk in &disks {
+            let free =
+                system::free_space(&disk.mount_point).unwrap_or(disk.available);
+            print_row(disk, free, options, color);
+            if let Some(limit) = options.min_free {
+                let is_low = free < limit;
+                let was_low = low.contains(&disk.mount_point);
+                if is_low != was_low {
+                    alert(disk, free, limit, is_low, options)?;
+                    if is_low {
+                        low.insert(disk.mount_point.clone());
+                    } else {
+                        low.remove(&disk.mount_point);
+                    }
+                }
+            }
+        }
+        std::io::stdout().flush()?;
+        refreshes += 1;
+        if options.count.is_some_and(|count| refreshes >= count) {
+            return Ok(());
+        }
+        std::thread::sleep(options.interval);
+    }
+}
+
+fn print_row(disk: &DiskInfo, free: u64, options: &Options, color: bool) {
+    let used = system::percent(disk.used(), disk.total);
+    let low = options.min_free.is_some_and(|limit| free < limit);
+    let (status, code) = if used >= options.critical_percent {
+        ("CRIT", "31")
+    } else if used >= options.warn_percent || low {
+        ("WARN", "33")
+    } else {
+        ("OK", "32")
+    };
+    let status = if color {
+        format!("\x1b[{code}m{status:<6}\x1b[0m")
+    } else {
+        format!("{status:<6}")
+    };
+    println!(
+        "{status} {:>6}% {:>10} {:>10}  {}{}",
+        used,
+        system::human_bytes(free),
+        system::human_bytes(disk.total),
+        disk.mount_point.display(),
+        if low { " (low free space)" } else { "" }
+    );
+}
+
+fn alert(
+    disk: &DiskInfo,
+    free: u64,
+    limit: u64,
+    is_low: bool,
+    options: &Options,
+) -> Result<()> {
+    let event = if is_low {
+        "low_free_space"
+    } else {
+        "recovered"
+    };
+    eprintln!(
+        "ALERT {event}: {} has {} free (limit {})",
+        disk.mount_point.display(),
+        system::human_bytes(free),
+        system::human_bytes(limit)
+    );
+    if let Some(path) = &options.alert_log {
+        let record = json!({
+            "timestamp": system::utc_timestamp(),
+            "event": event,
+            "mount_point": disk.mount_point.to_string_lossy(),
+            "free_bytes": free,
+            "limit_bytes": limit,
+            "total_bytes": disk.total,
+            "used_percent": system::percent(disk.used(), disk.total),
+        });
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| {
+            format!("Opening alert log {}", path.display())
+        })?;
+        writeln!(log, "{record}")?;
+    }
+    Ok(())
+}
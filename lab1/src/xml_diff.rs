@@ -0,0 +1,118 @@
+use std::collections::BTreeSet;
+
+use xmltree::{Element, XMLNode};
+
+/// Compares two documents structurally and returns one line per difference:
+/// `+` for added, `-` for removed and `~` for changed elements, attributes
+/// and text. Attribute order, prefixes, comments and whitespace around text
+/// are ignored. Children are paired by namespace, name and position among
+/// siblings of that name, and reported with XPath-style paths such as
+/// `/config/server[2]/@port` that `xml query` accepts.
+pub fn diff(old: &Element, new: &Element) -> Vec<String> {
+    let mut out = Vec::new();
+    if key(old) != key(new) {
+        out.push(format!(
+            "~ /: root element <{}> -> <{}>",
+            qualified(old),
+            qualified(new)
+        ));
+        return out;
+    }
+    compare(old, new, &format!("/{}", qualified(old)), &mut out);
+    out
+}
+
+fn qualified(element: &Element) -> String {
+    match &element.prefix {
+        Some(prefix) => format!("{prefix}:{}", element.name),
+        None => element.name.clone(),
+    }
+}
+
+fn key(element: &Element) -> (Option<&str>, &str) {
+    (element.namespace.as_deref(), &element.name)
+}
+
+fn text_of(element: &Element) -> String {
+    let text: String = element
+        .children
+        .iter()
+        .filter_map(|child| match child {
+            XMLNode::Text(text) | XMLNode::CData(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    text.trim().to_string()
+}
+
+fn compare(old: &Element, new: &Element, path: &str, out: &mut Vec<String>) {
+    let names: BTreeSet<&String> =
+        old.attributes.keys().chain(new.attributes.keys()).collect();
+    for name in names {
+        match (old.attributes.get(name), new.attributes.get(name)) {
+            (Some(before), Some(after)) if before != after => {
+                out.push(format!("~ {path}/@{name}: {before:?} -> {after:?}"))
+            }
+            (Some(before), None) => {
+                out.push(format!("- {path}/@{name} = {before:?}"))
+            }
+            (None, Some(after)) => {
+                out.push(format!("+ {path}/@{name} = {after:?}"))
+            }
+            _ => {}
+        }
+    }
+
+    let (before, after) = (text_of(old), text_of(new));
+    match (before.is_empty(), after.is_empty()) {
+        _ if before == after => {}
+        (false, true) => out.push(format!("- {path}/text() = {before:?}")),
+        (true, false) => out.push(format!("+ {path}/text() = {after:?}")),
+        _ => out.push(format!("~ {path}/text(): {before:?} -> {after:?}")),
+    }
+
+    let old_children: Vec<&Element> = old
+        .children
+        .iter()
+        .filter_map(XMLNode::as_element)
+        .collect();
+    let new_children: Vec<&Element> = new
+        .children
+        .iter()
+        .filter_map(XMLNode::as_element)
+        .collect();
+    // Names in order of first appearance, old document first.
+    let mut keys: Vec<(_, String)> = Vec::new();
+    for child in old_children.iter().chain(&new_children) {
+        if !keys.iter().any(|(k, _)| *k == key(child)) {
+            keys.push((key(child), qualified(child)));
+        }
+    }
+    for (child_key, label) in keys {
+        let olds: Vec<&Element> = old_children
+            .iter()
+            .copied()
+            .filter(|c| key(c) == child_key)
+            .collect();
+        let news: Vec<&Element> = new_children
+            .iter()
+            .copied()
+            .filter(|c| key(c) == child_key)
+            .collect();
+        let indexed = olds.len() > 1 || news.len() > 1;
+        for i in 0..olds.len().max(news.len()) {
+            let child_path = if indexed {
+                format!("{path}/{label}[{}]", i + 1)
+            } else {
+                format!("{path}/{label}")
+            };
+            match (olds.get(i), news.get(i)) {
+                (Some(before), Some(after)) => {
+                    compare(before, after, &child_path, out)
+                }
+                (Some(_), None) => out.push(format!("- {child_path}")),
+                _ => out.push(format!("+ {child_path}")),
+            }
+        }
+    }
+}
@@ -0,0 +1,709 @@
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use xmltree::Element;
+
+use crate::xml_safe::{self, XmlLimits};
+use crate::xpath::{Position, Selection};
+
+/// Byte spans of one element in the source text.
+#[derive(Debug)]
+struct ElementSpan {
+    name: String,
+    start: usize,
+    /// End of the last attribute, or of the name when there are none.
+    attrs_end: usize,
+    /// Just past the `>` of the start tag.
+    start_tag_end: usize,
+    /// Start of the end tag; equals `end` for self-closing elements.
+    end_tag_start: usize,
+    end: usize,
+    self_closing: bool,
+    attributes: Vec<AttributeSpan>,
+    children: Vec<Child>,
+}
+
+#[derive(Debug)]
+struct AttributeSpan {
+    name: String,
+    span: Range<usize>,
+    value: Range<usize>,
+    quote: char,
+}
+
+#[derive(Debug)]
+enum Child {
+    Element(ElementSpan),
+    /// Character data or a CDATA section.
+    Text(Range<usize>),
+    Other,
+}
+
+/// An XML document that is edited by splicing its source text, so the
+/// declaration, comments, processing instructions, attribute order, quoting
+/// and whitespace survive untouched outside the edited nodes. Elements are
+/// addressed with the same paths as [`crate::xpath`].
+pub struct Document {
+    source: String,
+    tree: Element,
+    root: ElementSpan,
+    indent_unit: String,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_attribute(value: &str, quote: char) -> String {
+    let escaped = value.replace('&', "&amp;").replace('<', "&lt;");
+    match quote {
+        '\'' => escaped.replace('\'', "&apos;"),
+        _ => escaped.replace('"', "&quot;"),
+    }
+}
+
+struct Scanner<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start_matches(|c: char| {
+            matches!(c, ' ' | '\t' | '\r' | '\n')
+        });
+        self.pos = self.src.len() - trimmed.len();
+    }
+
+    /// Moves past the next occurrence of `pattern`.
+    fn skip_past(&mut self, pattern: &str) -> Result<()> {
+        let offset = self.rest().find(pattern).ok_or_else(|| {
+            anyhow!("Unterminated markup, expected '{pattern}'")
+        })?;
+        self.pos += offset + pattern.len();
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let len = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '='))
+            .unwrap_or(self.rest().len());
+        if len == 0 {
+            bail!("Expected a name at byte {}", self.pos);
+        }
+        let name = self.rest()[..len].to_string();
+        self.pos += len;
+        Ok(name)
+    }
+
+    /// Skips a DOCTYPE, including an internal subset with quoted strings.
+    fn skip_doctype(&mut self) -> Result<()> {
+        let mut depth = 0usize;
+        let mut quote = None;
+        for (offset, c) in self.rest().char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '[') => depth += 1,
+                (None, ']') => depth = depth.saturating_sub(1),
+                (None, '>') if depth == 0 => {
+                    self.pos += offset + 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        bail!("Unterminated DOCTYPE")
+    }
+
+    /// Skips comments, PIs, the declaration and a DOCTYPE; returns `false`
+    /// when the next markup is not one of those.
+    fn skip_misc(&mut self) -> Result<bool> {
+        if self.rest().starts_with("<!--") {
+            self.skip_past("-->")?;
+        } else if self.rest().starts_with("<?") {
+            self.skip_past("?>")?;
+        } else if self.rest().starts_with("<!DOCTYPE") {
+            self.skip_doctype()?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn element(&mut self) -> Result<ElementSpan> {
+        let start = self.pos;
+        self.pos += 1;
+        let name = self.name()?;
+        let mut attrs_end = self.pos;
+        let mut attributes = Vec::new();
+        let self_closing = loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                break true;
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break false;
+            }
+            let attr_start = self.pos;
+            let attr_name = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                bail!("Expected '=' after attribute '{attr_name}'");
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = self
+                .rest()
+                .chars()
+                .next()
+                .filter(|c| matches!(c, '"' | '\''))
+                .ok_or_else(|| anyhow!("Unquoted value for '{attr_name}'"))?;
+            let value_start = self.pos + 1;
+            let value_len =
+                self.src[value_start..].find(quote).ok_or_else(|| {
+                    anyhow!("Unterminated value for '{attr_name}'")
+                })?;
+            self.pos = value_start + value_len + 1;
+            attrs_end = self.pos;
+            attributes.push(AttributeSpan {
+                name: attr_name,
+                span: attr_start..self.pos,
+                value: value_start..value_start + value_len,
+                quote,
+            });
+        };
+        let start_tag_end = self.pos;
+        let mut element = ElementSpan {
+            name,
+            start,
+            attrs_end,
+            start_tag_end,
+            end_tag_start: start_tag_end,
+            end: start_tag_end,
+            self_closing,
+            attributes,
+            children: Vec::new(),
+        };
+        if self_closing {
+            return Ok(element);
+        }
+
+        loop {
+            let src = self.src;
+            let rest = &src[self.pos..];
+            if rest.is_empty() {
+                bail!("Element <{}> is not closed", element.name);
+            } else if rest.starts_with("</") {
+                element.end_tag_start = self.pos;
+                self.skip_past(">")?;
+                element.end = self.pos;
+                return Ok(element);
+            } else if rest.starts_with("<![CDATA[") {
+                let text_start = self.pos;
+                self.skip_past("]]>")?;
+                element.children.push(Child::Text(text_start..self.pos));
+            } else if self.skip_misc()? {
+                element.children.push(Child::Other);
+            } else if rest.starts_with('<') {
+                element.children.push(Child::Element(self.element()?));
+            } else {
+                let text_start = self.pos;
+                self.pos += rest.find('<').unwrap_or(rest.len());
+                element.children.push(Child::Text(text_start..self.pos));
+            }
+        }
+    }
+}
+
+impl ElementSpan {
+    fn elements(&self) -> impl Iterator<Item = &ElementSpan> {
+        self.children.iter().filter_map(|child| match child {
+            Child::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+}
+
+impl Document {
+    /// Reads a UTF-8 document, never buffering more than `max_bytes`.
+    pub fn load(path: &Path, limits: &XmlLimits) -> Result<Document> {
+        let file = File::open(path)
+            .with_context(|| format!("Opening {}", path.display()))?;
+        let mut source = String::new();
+        file.take(limits.max_bytes as u64 + 1)
+            .read_to_string(&mut source)
+            .with_context(|| {
+                format!("{} is not UTF-8 encoded", path.display())
+            })?;
+        Document::parse(source, limits)
+            .with_context(|| format!("Parsing {}", path.display()))
+    }
+
+    /// Parses `source`, first running it through the hardened parser so the
+    /// same limits apply as everywhere else.
+    pub fn parse(source: String, limits: &XmlLimits) -> Result<Document> {
+        let tree = xml_safe::parse(source.as_bytes(), limits)?;
+        let mut scanner = Scanner {
+            src: &source,
+            pos: 0,
+        };
+        loop {
+            scanner.skip_whitespace();
+            if !scanner.skip_misc()? {
+                break;
+            }
+        }
+        let root = scanner.element()?;
+        let mut document = Document {
+            indent_unit: String::new(),
+            source,
+            tree,
+            root,
+            edits: Vec::new(),
+        };
+        document.indent_unit = document.detect_indent_unit();
+        Ok(document)
+    }
+
+    /// The parsed tree as of loading, for XPath evaluation.
+    pub fn tree(&self) -> &Element {
+        &self.tree
+    }
+
+    fn element(&self, path: &[usize]) -> Result<&ElementSpan> {
+        path.iter()
+            .try_fold(&self.root, |element, &i| element.elements().nth(i))
+            .ok_or_else(|| anyhow!("No element at {path:?}"))
+    }
+
+    /// Leading whitespace of the line `pos` is on, if nothing else precedes
+    /// `pos` on that line.
+    fn line_indent(&self, pos: usize) -> Option<&str> {
+        let line_start = self.source[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let indent = &self.source[line_start..pos];
+        indent
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+            .then_some(indent)
+    }
+
+    /// Guesses one indentation level from the first nested element that sits
+    /// on its own line, falling back to two spaces.
+    fn detect_indent_unit(&self) -> String {
+        fn walk(doc: &Document, element: &ElementSpan) -> Option<String> {
+            let outer = doc.line_indent(element.start)?;
+            for child in element.elements() {
+                if let Some(inner) = doc.line_indent(child.start)
+                    && inner.len() > outer.len()
+                    && inner.starts_with(outer)
+                {
+                    return Some(inner[outer.len()..].to_string());
+                }
+                if let Some(unit) = walk(doc, child) {
+                    return Some(unit);
+                }
+            }
+            None
+        }
+        walk(self, &self.root).unwrap_or_else(|| "  ".to_string())
+    }
+
+    fn splice(&mut self, range: Range<usize>, replacement: String) {
+        self.edits.push((range, replacement));
+    }
+
+    pub fn set_attribute(
+        &mut self,
+        path: &[usize],
+        name: &str,
+        value: &str,
+    ) -> Result<()> {
+        let element = self.element(path)?;
+        let edit = match element.attributes.iter().find(|a| a.name == name) {
+            Some(attr) => {
+                (attr.value.clone(), escape_attribute(value, attr.quote))
+            }
+            None => {
+                let quote = element.attributes.last().map_or('"', |a| a.quote);
+                let at = element.attrs_end;
+                (
+                    at..at,
+                    format!(
+                        " {name}={quote}{}{quote}",
+                        escape_attribute(value, quote)
+                    ),
+                )
+            }
+        };
+        self.splice(edit.0, edit.1);
+        Ok(())
+    }
+
+    /// Removes an attribute given by its qualified name or, failing that,
+    /// its local name, together with the whitespace before it.
+    pub fn remove_attribute(
+        &mut self,
+        path: &[usize],
+        name: &str,
+    ) -> Result<()> {
+        let element = self.element(path)?;
+        let attr = element
+            .attributes
+            .iter()
+            .find(|a| a.name == name)
+            .or_else(|| {
+                element.attributes.iter().find(|a| {
+                    a.name
+                        .split_once(':')
+                        .is_some_and(|(_, local)| local == name)
+                })
+            })
+            .ok_or_else(|| anyhow!("Attribute '{name}' not found"))?;
+        let start = self.source[..attr.span.start].trim_end().len();
+        self.splice(start..attr.span.end, String::new());
+        Ok(())
+    }
+
+    /// Text nodes that carry content; whitespace between child elements is
+    /// formatting and is left alone.
+    fn text_ranges(&self, element: &ElementSpan) -> Vec<Range<usize>> {
+        let has_elements = element.elements().next().is_some();
+        element
+            .children
+            .iter()
+            .filter_map(|child| match child {
+                Child::Text(range)
+                    if !has_elements
+                        || !self.source[range.clone()].trim().is_empty() =>
+                {
+                    Some(range.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Replaces the text content of an element, keeping child elements,
+    /// comments and the whitespace that lays them out.
+    pub fn set_text(&mut self, path: &[usize], text: &str) -> Result<()> {
+        let element = self.element(path)?;
+        let escaped = escape_text(text);
+        if element.self_closing {
+            if !text.is_empty() {
+                let edit = (
+                    element.attrs_end..element.end,
+                    format!(">{escaped}</{}>", element.name),
+                );
+                self.splice(edit.0, edit.1);
+            }
+            return Ok(());
+        }
+        let mut ranges = self.text_ranges(element).into_iter();
+        match ranges.next() {
+            Some(first) => self.splice(first, escaped),
+            None => {
+                let at = element.start_tag_end;
+                self.splice(at..at, escaped);
+            }
+        }
+        for range in ranges {
+            self.splice(range, String::new());
+        }
+        Ok(())
+    }
+
+    pub fn remove_text(&mut self, path: &[usize]) -> Result<()> {
+        for range in self.text_ranges(self.element(path)?) {
+            self.splice(range, String::new());
+        }
+        Ok(())
+    }
+
+    fn insert_before(
+        &self,
+        element: &ElementSpan,
+        fragment: &str,
+    ) -> (Range<usize>, String) {
+        let at = element.start;
+        match self.line_indent(at) {
+            Some(indent) => (at..at, format!("{fragment}\n{indent}")),
+            None => (at..at, fragment.to_string()),
+        }
+    }
+
+    fn insert_after(
+        &self,
+        element: &ElementSpan,
+        fragment: &str,
+    ) -> (Range<usize>, String) {
+        let at = element.end;
+        match self.line_indent(element.start) {
+            Some(indent) => (at..at, format!("\n{indent}{fragment}")),
+            None => (at..at, fragment.to_string()),
+        }
+    }
+
+    /// Adds the first child element, on its own line unless the element
+    /// already holds text (mixed content).
+    fn insert_into_empty(
+        &self,
+        element: &ElementSpan,
+        fragment: &str,
+    ) -> (Range<usize>, String) {
+        let content =
+            &self.source[element.start_tag_end..element.end_tag_start];
+        let body = match self.line_indent(element.start) {
+            Some(indent) if content.trim().is_empty() => {
+                format!("\n{indent}{}{fragment}\n{indent}", self.indent_unit)
+            }
+            _ => format!("{content}{fragment}"),
+        };
+        if element.self_closing {
+            (
+                element.attrs_end..element.end,
+                format!(">{body}</{}>", element.name),
+            )
+        } else {
+            (element.start_tag_end..element.end_tag_start, body)
+        }
+    }
+
+    /// Inserts `fragment` relative to the element at `path`, indenting it
+    /// like its new neighbours when they sit on lines of their own.
+    pub fn insert(
+        &mut self,
+        path: &[usize],
+        fragment: &str,
+        position: Position,
+    ) -> Result<()> {
+        let target = self.element(path)?;
+        if path.is_empty()
+            && matches!(position, Position::Before | Position::After)
+        {
+            bail!("Cannot insert a sibling of the root element");
+        }
+        let (range, replacement) = match position {
+            Position::Before => self.insert_before(target, fragment),
+            Position::After => self.insert_after(target, fragment),
+            Position::First => match target.elements().next() {
+                Some(first) => self.insert_before(first, fragment),
+                None => self.insert_into_empty(target, fragment),
+            },
+            Position::Last => match target.elements().last() {
+                Some(last) => self.insert_after(last, fragment),
+                None => self.insert_into_empty(target, fragment),
+            },
+        };
+        self.splice(range, replacement);
+        Ok(())
+    }
+
+    /// Removes an element along with the indentation and line break in front
+    /// of it when it sits on a line of its own.
+    pub fn remove_element(&mut self, path: &[usize]) -> Result<()> {
+        if path.is_empty() {
+            bail!("Cannot remove the root element");
+        }
+        let element = self.element(path)?;
+        let mut start = element.start;
+        if let Some(indent) = self.line_indent(start) {
+            let line_start = start - indent.len();
+            if self.source[..line_start].ends_with("\r\n") {
+                start = line_start - 2;
+            } else if self.source[..line_start].ends_with('\n') {
+                start = line_start - 1;
+            }
+        }
+        let end = element.end;
+        self.splice(start..end, String::new());
+        Ok(())
+    }
+
+    /// Removes everything `selections` matched. Matches inside an element
+    /// that is going away are removed with it rather than edited separately.
+    pub fn remove_selections(
+        &mut self,
+        selections: &[Selection],
+    ) -> Result<()> {
+        let mut elements: Vec<&[usize]> = selections
+            .iter()
+            .filter_map(|selection| match selection {
+                Selection::Element(path) => Some(path.as_slice()),
+                _ => None,
+            })
+            .collect();
+        // Outermost first, so nested matches find their ancestor.
+        elements.sort_by_key(|path| path.len());
+        let mut removed: Vec<&[usize]> = Vec::new();
+        for path in elements {
+            if !removed.iter().any(|r| path.starts_with(r)) {
+                self.remove_element(path)?;
+                removed.push(path);
+            }
+        }
+        for selection in selections {
+            match selection {
+                Selection::Element(_) => {}
+                Selection::Attribute(path, _) | Selection::Text(path)
+                    if removed.iter().any(|r| path.starts_with(r)) => {}
+                Selection::Attribute(path, name) => {
+                    self.remove_attribute(path, name)?
+                }
+                Selection::Text(path) => self.remove_text(path)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the queued edits and checks that the result is still
+    /// well-formed before handing it back.
+    pub fn finish(mut self, limits: &XmlLimits) -> Result<String> {
+        self.edits
+            .sort_by_key(|(range, _)| (range.start, range.end));
+        for pair in self.edits.windows(2) {
+            if pair[1].0.start < pair[0].0.end {
+                bail!("Edits overlap at byte {}", pair[1].0.start);
+            }
+        }
+        let mut output = self.source;
+        for (range, replacement) in self.edits.into_iter().rev() {
+            output.replace_range(range, &replacement);
+        }
+        xml_safe::parse(output.as_bytes(), limits)
+            .context("Edited document is no longer well-formed")?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xpath::XPath;
+
+    const SOURCE: &str = "<?xml version='1.0' encoding='UTF-8'?>
+<!-- inventory -->
+<?render mode=\"full\"?>
+<store name='main' kind=\"retail\">
+    <!-- first shelf -->
+    <item id='a' price=\"1\">apple</item>
+    <?keep this?>
+    <item id='b' price=\"2\">pear</item>
+    <empty/>
+</store>
+<!-- trailer -->
+";
+
+    fn edit(apply: impl FnOnce(&mut Document)) -> String {
+        let limits = XmlLimits::default();
+        let mut document =
+            Document::parse(SOURCE.to_string(), &limits).unwrap();
+        apply(&mut document);
+        document.finish(&limits).unwrap()
+    }
+
+    fn select(document: &Document, expr: &str) -> Vec<Selection> {
+        XPath::parse(expr)
+            .unwrap()
+            .select(document.tree())
+            .into_iter()
+            .map(|(selection, _)| selection)
+            .collect()
+    }
+
+    #[test]
+    fn untouched_document_is_byte_identical() {
+        assert_eq!(edit(|_| {}), SOURCE);
+    }
+
+    #[test]
+    fn set_text_keeps_everything_else() {
+        let output = edit(|doc| doc.set_text(&[1], "quince & fig").unwrap());
+        assert_eq!(
+            output,
+            SOURCE.replace(">pear<", ">quince &amp; fig<"),
+            "only the text node changes"
+        );
+    }
+
+    #[test]
+    fn set_attribute_keeps_quoting() {
+        let output = edit(|doc| {
+            doc.set_attribute(&[0], "id", "it's").unwrap();
+            doc.set_attribute(&[0], "price", "say \"3\"").unwrap();
+            doc.set_attribute(&[], "open", "yes").unwrap();
+        });
+        let expected = SOURCE
+            .replace("id='a'", "id='it&apos;s'")
+            .replace("price=\"1\"", "price=\"say &quot;3&quot;\"")
+            .replace("kind=\"retail\">", "kind=\"retail\" open=\"yes\">");
+        assert_eq!(output, expected, "each attribute keeps its own quotes");
+    }
+
+    #[test]
+    fn insert_follows_indentation() {
+        let output = edit(|doc| {
+            doc.insert(&[1], "<item id='c'/>", Position::After).unwrap();
+            doc.insert(&[2], "<slot/>", Position::First).unwrap();
+        });
+        let expected = SOURCE
+            .replace("pear</item>\n", "pear</item>\n    <item id='c'/>\n")
+            .replace("<empty/>", "<empty>\n        <slot/>\n    </empty>");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn remove_takes_its_line_with_it() {
+        let output = edit(|doc| {
+            doc.remove_element(&[0]).unwrap();
+            doc.remove_attribute(&[], "kind").unwrap();
+        });
+        let expected = SOURCE
+            .replace("\n    <item id='a' price=\"1\">apple</item>", "")
+            .replace(" kind=\"retail\"", "");
+        assert_eq!(output, expected, "comments and PIs around it stay");
+    }
+
+    #[test]
+    fn matches_inside_removed_elements_go_with_them() {
+        let output = edit(|doc| {
+            // The attribute and text come first in document order.
+            let mut matches = select(doc, "//item[@id='a']/@price");
+            matches.extend(select(doc, "//item[@id='a']/text()"));
+            matches.extend(select(doc, "/store/item"));
+            matches.extend(select(doc, "//@kind"));
+            doc.remove_selections(&matches).unwrap();
+        });
+        let expected = SOURCE
+            .replace("\n    <item id='a' price=\"1\">apple</item>", "")
+            .replace("\n    <item id='b' price=\"2\">pear</item>", "")
+            .replace(" kind=\"retail\"", "");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn nested_element_matches_are_removed_once() {
+        let limits = XmlLimits::default();
+        let source = "<a>\n  <b>\n    <b/>\n  </b>\n</a>\n";
+        let mut document =
+            Document::parse(source.to_string(), &limits).unwrap();
+        let matches = select(&document, "//b");
+        assert_eq!(matches.len(), 2);
+        document.remove_selections(&matches).unwrap();
+        assert_eq!(document.finish(&limits).unwrap(), "<a>\n</a>\n");
+    }
+}
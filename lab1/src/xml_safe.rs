@@ -0,0 +1,345 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use xml::reader::{EventReader, ParserConfig2, XmlEvent};
+use xmltree::{Element, XMLNode};
+
+/// Limits applied to every untrusted XML document before it is turned into
+/// an [`Element`]. DTDs are rejected unless `allow_internal_dtd` is set, and
+/// external entities are never resolved either way.
+#[derive(Debug, Clone)]
+pub struct XmlLimits {
+    pub max_bytes: usize,
+    pub max_depth: usize,
+    pub max_attributes: usize,
+    pub max_entity_expansion: usize,
+    pub max_entity_depth: u8,
+    pub allow_internal_dtd: bool,
+}
+
+impl Default for XmlLimits {
+    fn default() -> Self {
+        XmlLimits {
+            max_bytes: 10 * 1024 * 1024,
+            max_depth: 128,
+            max_attributes: 256,
+            max_entity_expansion: 64 * 1024,
+            max_entity_depth: 4,
+            allow_internal_dtd: false,
+        }
+    }
+}
+
+fn limit_error(limit: &str, value: usize, max: usize) -> anyhow::Error {
+    crate::Error::Limit(format!(
+        "XML rejected: {limit} limit exceeded ({value} > {max})"
+    ))
+    .into()
+}
+
+fn check_doctype(doctype: &str, limits: &XmlLimits) -> Result<()> {
+    if !limits.allow_internal_dtd {
+        return Err(crate::Error::Limit(
+            "XML rejected: DOCTYPE declarations are not allowed".into(),
+        )
+        .into());
+    }
+    let upper = doctype.to_ascii_uppercase();
+    if upper.contains("SYSTEM") || upper.contains("PUBLIC") {
+        return Err(crate::Error::Limit(
+            "XML rejected: external DTDs and entities are not allowed".into(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Undoes two quirks of xml-rs in processing instruction data: the
+/// whitespace separating it from the target is kept, and every `/` comes
+/// back as `</` (it is lexed as a closing tag start).
+fn pi_data(data: String) -> String {
+    data.trim_start_matches([' ', '\t', '\r', '\n'])
+        .replace("</", "/")
+}
+
+/// Reads a file without ever buffering more than `max_bytes` (plus one, so
+/// that the parser can tell the file is too large).
+pub fn read_file(path: &Path, limits: &XmlLimits) -> Result<Vec<u8>> {
+    let file = File::open(path)
+        .with_context(|| format!("Opening {}", path.display()))?;
+    let mut input = Vec::new();
+    file.take(limits.max_bytes as u64 + 1)
+        .read_to_end(&mut input)?;
+    Ok(input)
+}
+
+/// Reads and parses a file without ever buffering more than `max_bytes`.
+pub fn parse_file(path: &Path, limits: &XmlLimits) -> Result<Element> {
+    parse(&read_file(path, limits)?, limits)
+        .with_context(|| format!("Parsing {}", path.display()))
+}
+
+/// Parses `input` into its root element, enforcing `limits`.
+pub fn parse(input: &[u8], limits: &XmlLimits) -> Result<Element> {
+    parse_document(input, limits).map(|document| document.root)
+}
+
+/// The root element and the comments and processing instructions around it.
+pub struct Document {
+    pub root: Element,
+    /// Top-level comments and processing instructions, before and after the
+    /// root, in document order.
+    pub misc: Vec<XMLNode>,
+    /// How many of `misc` come before the root.
+    pub prolog: usize,
+}
+
+/// Like [`parse`], but also keeps the nodes outside the root element.
+pub fn parse_document(input: &[u8], limits: &XmlLimits) -> Result<Document> {
+    read_document(input, limits, false)
+}
+
+/// Like [`parse_document`], but keeps whitespace-only text inside the root
+/// element as text nodes, as canonicalization must.
+pub fn parse_document_with_whitespace(
+    input: &[u8],
+    limits: &XmlLimits,
+) -> Result<Document> {
+    read_document(input, limits, true)
+}
+
+fn read_document(
+    input: &[u8],
+    limits: &XmlLimits,
+    keep_whitespace: bool,
+) -> Result<Document> {
+    if input.len() > limits.max_bytes {
+        return Err(limit_error("max_bytes", input.len(), limits.max_bytes));
+    }
+    let config = ParserConfig2::new()
+        .ignore_comments(false)
+        .max_entity_expansion_length(limits.max_entity_expansion)
+        .max_entity_expansion_depth(limits.max_entity_depth)
+        .max_data_length(limits.max_bytes)
+        .max_attribute_length(limits.max_bytes);
+    let mut reader = EventReader::new_with_config(input, config);
+    let mut doctype_checked = false;
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+    let mut misc = Vec::new();
+    let mut prolog = 0;
+
+    loop {
+        let event = reader.next().map_err(|err| {
+            if err.msg() == "Entity too big" {
+                crate::Error::Limit(format!(
+                    "XML rejected: max_entity_expansion limit exceeded \
+                     (entities may expand to {} bytes, {} levels deep)",
+                    limits.max_entity_expansion, limits.max_entity_depth
+                ))
+            } else {
+                crate::Error::Parse(format!("Malformed XML: {err}"))
+            }
+        })?;
+        if !doctype_checked && let Some(doctype) = reader.doctype() {
+            check_doctype(doctype, limits)?;
+            doctype_checked = true;
+        }
+        match event {
+            XmlEvent::StartElement {
+                name,
+                attributes,
+                namespace,
+            } => {
+                if stack.len() + 1 > limits.max_depth {
+                    return Err(limit_error(
+                        "max_depth",
+                        stack.len() + 1,
+                        limits.max_depth,
+                    ));
+                }
+                if attributes.len() > limits.max_attributes {
+                    return Err(limit_error(
+                        "max_attributes",
+                        attributes.len(),
+                        limits.max_attributes,
+                    ));
+                }
+                let mut element = Element::new(&name.local_name);
+                element.prefix = name.prefix;
+                element.namespace = name.namespace;
+                if !namespace.is_essentially_empty() {
+                    element.namespaces = Some(namespace);
+                }
+                // Prefixed attributes keep their prefix (`xml:lang`) so their
+                // namespace can be looked up in `element.namespaces`.
+                for attribute in attributes {
+                    let name = match attribute.name.prefix {
+                        Some(prefix) => {
+                            format!("{prefix}:{}", attribute.name.local_name)
+                        }
+                        None => attribute.name.local_name,
+                    };
+                    element.attributes.insert(name, attribute.value);
+                }
+                stack.push(element);
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().expect("parser balances elements");
+                match stack.last_mut() {
+                    Some(parent) => {
+                        parent.children.push(XMLNode::Element(element))
+                    }
+                    None if root.is_none() => root = Some(element),
+                    None => {}
+                }
+            }
+            XmlEvent::Characters(text) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(XMLNode::Text(text));
+                }
+            }
+            XmlEvent::Whitespace(text) if keep_whitespace => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(XMLNode::Text(text));
+                }
+            }
+            XmlEvent::CData(text) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(XMLNode::CData(text));
+                }
+            }
+            XmlEvent::Comment(text) => match stack.last_mut() {
+                Some(parent) => parent.children.push(XMLNode::Comment(text)),
+                None => {
+                    misc.push(XMLNode::Comment(text));
+                    prolog += usize::from(root.is_none());
+                }
+            },
+            XmlEvent::ProcessingInstruction { name, data } => {
+                let node =
+                    XMLNode::ProcessingInstruction(name, data.map(pi_data));
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(node),
+                    None => {
+                        misc.push(node);
+                        prolog += usize::from(root.is_none());
+                    }
+                }
+            }
+            XmlEvent::EndDocument => break,
+            XmlEvent::StartDocument { .. } | XmlEvent::Whitespace(_) => {}
+        }
+    }
+    let root = root.ok_or_else(|| {
+        crate::Error::Parse("Malformed XML: no root element".into())
+    })?;
+    Ok(Document { root, misc, prolog })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(input: &str, limits: &XmlLimits) -> String {
+        parse(input.as_bytes(), limits)
+            .expect_err("document should be rejected")
+            .to_string()
+    }
+
+    const XXE: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE foo [ <!ENTITY xxe SYSTEM "file:///etc/passwd"> ]>
+<foo>&xxe;</foo>"#;
+
+    const BILLION_LAUGHS: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE lolz [
+  <!ENTITY lol "lol">
+  <!ENTITY lol1 "&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;">
+  <!ENTITY lol2 "&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;">
+  <!ENTITY lol3 "&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;">
+  <!ENTITY lol4 "&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;">
+  <!ENTITY lol5 "&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;">
+  <!ENTITY lol6 "&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;&lol5;">
+  <!ENTITY lol7 "&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;&lol6;">
+  <!ENTITY lol8 "&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;&lol7;">
+  <!ENTITY lol9 "&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;&lol8;">
+]>
+<lolz>&lol9;</lolz>"#;
+
+    #[test]
+    fn plain_document_is_accepted() {
+        let root = parse(
+            br#"<config a="1"><!-- note --><item>x</item></config>"#,
+            &XmlLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(root.name, "config");
+        assert_eq!(root.attributes.get("a").map(String::as_str), Some("1"));
+        assert_eq!(root.children.len(), 2);
+    }
+
+    #[test]
+    fn external_entity_is_rejected() {
+        let err = rejected(XXE, &XmlLimits::default());
+        assert!(err.contains("DOCTYPE"), "{err}");
+    }
+
+    #[test]
+    fn external_entity_is_rejected_even_with_internal_dtds_allowed() {
+        let limits = XmlLimits {
+            allow_internal_dtd: true,
+            ..XmlLimits::default()
+        };
+        let err = rejected(XXE, &limits);
+        assert!(err.contains("external"), "{err}");
+    }
+
+    #[test]
+    fn billion_laughs_is_rejected() {
+        let err = rejected(BILLION_LAUGHS, &XmlLimits::default());
+        assert!(err.contains("DOCTYPE"), "{err}");
+    }
+
+    #[test]
+    fn billion_laughs_hits_entity_expansion_limit() {
+        let limits = XmlLimits {
+            allow_internal_dtd: true,
+            max_entity_depth: 16,
+            ..XmlLimits::default()
+        };
+        let err = rejected(BILLION_LAUGHS, &limits);
+        assert!(err.contains("max_entity_expansion"), "{err}");
+    }
+
+    #[test]
+    fn deep_nesting_hits_depth_limit() {
+        let limits = XmlLimits {
+            max_depth: 10,
+            ..XmlLimits::default()
+        };
+        let doc = format!("{}{}", "<a>".repeat(11), "</a>".repeat(11));
+        let err = rejected(&doc, &limits);
+        assert!(err.contains("max_depth"), "{err}");
+    }
+
+    #[test]
+    fn attribute_flood_hits_attribute_limit() {
+        let attributes: String =
+            (0..300).map(|i| format!(" a{i}=\"x\"")).collect();
+        let err = rejected(&format!("<a{attributes}/>"), &XmlLimits::default());
+        assert!(err.contains("max_attributes"), "{err}");
+    }
+
+    #[test]
+    fn oversized_document_hits_size_limit() {
+        let limits = XmlLimits {
+            max_bytes: 64,
+            ..XmlLimits::default()
+        };
+        let doc = format!("<a>{}</a>", "x".repeat(100));
+        let err = rejected(&doc, &limits);
+        assert!(err.contains("max_bytes"), "{err}");
+    }
+}
@@ -0,0 +1,857 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use xmltree::{Element, XMLNode};
+
+/// Elements are addressed by the positions of element children on the way
+/// down from the root element, so `[]` is the root and `[0, 2]` is the third
+/// child element of the root's first child element.
+pub type ElementPath = Vec<usize>;
+
+/// Result of evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    Element(ElementPath),
+    Attribute(ElementPath, String),
+    Text(ElementPath),
+}
+
+/// Compiled expression in a practical subset of XPath 1.0: absolute and
+/// relative location paths with `/` and `//`, the `*`, `.` and `..` steps,
+/// predicates on position (`[2]`, `[last()]`), attributes (`[@id]`,
+/// `[@id='x']`), text (`[name='x']`, `[.='x']`, `[text()='x']`) combined with
+/// `and`/`or`, and a final `text()`, `@name` or `@*` step.
+#[derive(Debug, Clone)]
+pub struct XPath {
+    steps: Vec<Step>,
+    target: Target,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    Child,
+    DescendantOrSelf,
+    SelfNode,
+    Parent,
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    axis: Axis,
+    name: Option<String>,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Elements,
+    Text,
+    Attribute(Option<String>),
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    Position(usize),
+    Last,
+    Or(Box<Predicate>, Box<Predicate>),
+    And(Box<Predicate>, Box<Predicate>),
+    Exists(Operand),
+    Compare(Operand, CmpOp, String),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Attribute(String),
+    Child(String),
+    Text,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Slash,
+    DoubleSlash,
+    Name(String),
+    Star,
+    Dot,
+    DotDot,
+    At,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Literal(String),
+    Number(usize),
+    Op(String),
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '/' if next == Some('/') => {
+                tokens.push(Token::DoubleSlash);
+                i += 2;
+            }
+            '/' => {
+                tokens.push(Token::Slash);
+                i += 1;
+            }
+            '.' if next == Some('.') => {
+                tokens.push(Token::DotDot);
+                i += 2;
+            }
+            '.' => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            '*' => {
+                tokens.push(Token::Star);
+                i += 1;
+            }
+            '@' => {
+                tokens.push(Token::At);
+                i += 1;
+            }
+            '[' => {
+                tokens.push(Token::LBracket);
+                i += 1;
+            }
+            ']' => {
+                tokens.push(Token::RBracket);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&q| q == c)
+                    .ok_or_else(|| anyhow!("Unterminated string in XPath"))?;
+                tokens.push(Token::Literal(
+                    chars[i + 1..i + 1 + end].iter().collect(),
+                ));
+                i += end + 2;
+            }
+            '=' => {
+                tokens.push(Token::Op("=".into()));
+                i += 1;
+            }
+            '!' | '<' | '>' => {
+                if next == Some('=') {
+                    tokens.push(Token::Op(format!("{c}=")));
+                    i += 2;
+                } else if c == '!' {
+                    bail!("Unexpected '!' in XPath");
+                } else {
+                    tokens.push(Token::Op(c.to_string()));
+                    i += 1;
+                }
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[start..i].iter().collect();
+                tokens.push(Token::Number(digits.parse()?));
+            }
+            c if is_name_char(c) => {
+                let start = i;
+                while i < chars.len() && is_name_char(chars[i]) {
+                    i += 1;
+                }
+                tokens.push(Token::Name(chars[start..i].iter().collect()));
+            }
+            _ => bail!("Unexpected character '{c}' in XPath"),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Expected {token:?} in XPath, found {:?}",
+                self.peek()
+            ))
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Name(w)) if w == word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Name(name)) => Ok(name),
+            other => Err(anyhow!("Expected a name in XPath, found {other:?}")),
+        }
+    }
+
+    fn predicate(&mut self) -> Result<Predicate> {
+        let mut lhs = self.conjunction()?;
+        while self.eat_word("or") {
+            let rhs = self.conjunction()?;
+            lhs = Predicate::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn conjunction(&mut self) -> Result<Predicate> {
+        let mut lhs = self.condition()?;
+        while self.eat_word("and") {
+            let rhs = self.condition()?;
+            lhs = Predicate::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn condition(&mut self) -> Result<Predicate> {
+        if let Some(Token::Number(n)) = self.peek().cloned() {
+            self.pos += 1;
+            if n == 0 {
+                bail!("XPath positions start at 1");
+            }
+            return Ok(Predicate::Position(n));
+        }
+        if self.eat(&Token::LParen) {
+            let inner = self.predicate()?;
+            self.expect(&Token::RParen)?;
+            return Ok(inner);
+        }
+        let operand = if self.eat(&Token::At) {
+            Operand::Attribute(self.name()?)
+        } else if self.eat(&Token::Dot) {
+            Operand::Text
+        } else {
+            let name = self.name()?;
+            if self.eat(&Token::LParen) {
+                self.expect(&Token::RParen)?;
+                match name.as_str() {
+                    "last" => return Ok(Predicate::Last),
+                    "text" => Operand::Text,
+                    _ => bail!("Unsupported XPath function {name}()"),
+                }
+            } else {
+                Operand::Child(name)
+            }
+        };
+        let Some(Token::Op(op)) = self.peek().cloned() else {
+            return Ok(Predicate::Exists(operand));
+        };
+        self.pos += 1;
+        let op = match op.as_str() {
+            "=" => CmpOp::Eq,
+            "!=" => CmpOp::Ne,
+            "<" => CmpOp::Lt,
+            "<=" => CmpOp::Le,
+            ">" => CmpOp::Gt,
+            _ => CmpOp::Ge,
+        };
+        let value = match self.next() {
+            Some(Token::Literal(s)) => s,
+            Some(Token::Number(n)) => n.to_string(),
+            other => bail!("Expected a literal in XPath, found {other:?}"),
+        };
+        Ok(Predicate::Compare(operand, op, value))
+    }
+
+    fn predicates(&mut self) -> Result<Vec<Predicate>> {
+        let mut predicates = Vec::new();
+        while self.eat(&Token::LBracket) {
+            predicates.push(self.predicate()?);
+            self.expect(&Token::RBracket)?;
+        }
+        Ok(predicates)
+    }
+}
+
+impl XPath {
+    pub fn parse(source: &str) -> Result<XPath> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        let mut steps = Vec::new();
+        let mut target = Target::Elements;
+        let mut axis = Axis::Child;
+        if parser.eat(&Token::DoubleSlash) {
+            steps.push(Step {
+                axis: Axis::DescendantOrSelf,
+                name: None,
+                predicates: Vec::new(),
+            });
+        } else {
+            parser.eat(&Token::Slash);
+        }
+
+        loop {
+            match parser.next() {
+                Some(Token::Dot) => steps.push(Step {
+                    axis: Axis::SelfNode,
+                    name: None,
+                    predicates: Vec::new(),
+                }),
+                Some(Token::DotDot) => steps.push(Step {
+                    axis: Axis::Parent,
+                    name: None,
+                    predicates: Vec::new(),
+                }),
+                Some(Token::At) => {
+                    target = if parser.eat(&Token::Star) {
+                        Target::Attribute(None)
+                    } else {
+                        Target::Attribute(Some(parser.name()?))
+                    };
+                }
+                Some(Token::Star) => steps.push(Step {
+                    axis,
+                    name: None,
+                    predicates: parser.predicates()?,
+                }),
+                Some(Token::Name(name)) if name == "text" => {
+                    if parser.eat(&Token::LParen) {
+                        parser.expect(&Token::RParen)?;
+                        target = Target::Text;
+                    } else {
+                        steps.push(Step {
+                            axis,
+                            name: Some(name),
+                            predicates: parser.predicates()?,
+                        });
+                    }
+                }
+                Some(Token::Name(name)) => steps.push(Step {
+                    axis,
+                    name: Some(name),
+                    predicates: parser.predicates()?,
+                }),
+                other => bail!("Unexpected {other:?} in XPath step"),
+            }
+            if !matches!(target, Target::Elements) {
+                break;
+            }
+            match parser.next() {
+                None => break,
+                Some(Token::Slash) => axis = Axis::Child,
+                Some(Token::DoubleSlash) => {
+                    steps.push(Step {
+                        axis: Axis::DescendantOrSelf,
+                        name: None,
+                        predicates: Vec::new(),
+                    });
+                    axis = Axis::Child;
+                }
+                Some(other) => bail!("Unexpected {other:?} in XPath"),
+            }
+        }
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected {token:?} after the end of the XPath");
+        }
+        Ok(XPath { steps, target })
+    }
+
+    /// The matches in document order, each with the element it belongs to
+    /// so that callers need not look it up by path again.
+    pub fn select<'a>(
+        &self,
+        root: &'a Element,
+    ) -> Vec<(Selection, &'a Element)> {
+        // `None` stands for the document node above the root element. Each
+        // node carries its ancestors so that steps never walk down from the
+        // root again; the map keeps document order and drops duplicates.
+        let mut context: BTreeMap<Option<ElementPath>, Vec<&Element>> =
+            BTreeMap::from([(None, Vec::new())]);
+        for step in &self.steps {
+            let mut next = BTreeMap::new();
+            for (path, chain) in context {
+                let candidates = step_candidates(root, path, chain, step);
+                next.extend(filter_predicates(candidates, step));
+            }
+            context = next;
+        }
+
+        let elements = context
+            .into_iter()
+            .filter_map(|(path, chain)| Some((path?, *chain.last()?)));
+        match &self.target {
+            Target::Elements => elements
+                .map(|(path, element)| (Selection::Element(path), element))
+                .collect(),
+            Target::Text => elements
+                .filter(|(_, element)| {
+                    element.children.iter().any(|c| {
+                        matches!(c, XMLNode::Text(_) | XMLNode::CData(_))
+                    })
+                })
+                .map(|(path, element)| (Selection::Text(path), element))
+                .collect(),
+            Target::Attribute(name) => elements
+                .flat_map(|(path, element)| {
+                    let mut names: Vec<&String> = match name {
+                        Some(name) => element
+                            .attributes
+                            .keys()
+                            .filter(|k| *k == name)
+                            .collect(),
+                        None => element.attributes.keys().collect(),
+                    };
+                    names.sort();
+                    names
+                        .into_iter()
+                        .map(|n| {
+                            let selection =
+                                Selection::Attribute(path.clone(), n.clone());
+                            (selection, element)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+        }
+    }
+}
+
+fn qualified_name(element: &Element) -> String {
+    match &element.prefix {
+        Some(prefix) => format!("{prefix}:{}", element.name),
+        None => element.name.clone(),
+    }
+}
+
+fn name_matches(element: &Element, name: &Option<String>) -> bool {
+    match name {
+        None => true,
+        Some(name) if name.contains(':') => qualified_name(element) == *name,
+        Some(name) => element.name == *name,
+    }
+}
+
+fn child_elements(element: &Element) -> impl Iterator<Item = &Element> {
+    element.children.iter().filter_map(XMLNode::as_element)
+}
+
+pub fn element_at<'a>(
+    root: &'a Element,
+    path: &[usize],
+) -> Option<&'a Element> {
+    path.iter()
+        .try_fold(root, |element, &i| child_elements(element).nth(i))
+}
+
+pub fn element_at_mut<'a>(
+    root: &'a mut Element,
+    path: &[usize],
+) -> Option<&'a mut Element> {
+    path.iter().try_fold(root, |element, &i| {
+        element
+            .children
+            .iter_mut()
+            .filter_map(XMLNode::as_mut_element)
+            .nth(i)
+    })
+}
+
+/// A node reached by a step: its path and the elements from the root down
+/// to it, `None` and empty for the document node.
+type Node<'a> = (Option<ElementPath>, Vec<&'a Element>);
+
+fn descendants<'a>(
+    path: &ElementPath,
+    chain: &[&'a Element],
+    out: &mut Vec<Node<'a>>,
+) {
+    let Some(element) = chain.last() else {
+        return;
+    };
+    for (i, child) in child_elements(element).enumerate() {
+        let mut child_path = path.clone();
+        child_path.push(i);
+        let mut child_chain = chain.to_vec();
+        child_chain.push(child);
+        descendants(&child_path, &child_chain, out);
+        out.push((Some(child_path), child_chain));
+    }
+}
+
+fn step_candidates<'a>(
+    root: &'a Element,
+    path: Option<ElementPath>,
+    mut chain: Vec<&'a Element>,
+    step: &Step,
+) -> Vec<Node<'a>> {
+    let candidates: Vec<Node<'a>> = match (step.axis, path) {
+        (Axis::SelfNode, path) => vec![(path, chain)],
+        (Axis::Parent, None) => Vec::new(),
+        (Axis::Parent, Some(mut path)) => {
+            chain.pop();
+            match path.pop() {
+                Some(_) => vec![(Some(path), chain)],
+                None => vec![(None, chain)],
+            }
+        }
+        (Axis::Child, None) => vec![(Some(Vec::new()), vec![root])],
+        (Axis::Child, Some(path)) => chain
+            .last()
+            .into_iter()
+            .flat_map(|element| child_elements(element))
+            .enumerate()
+            .map(|(i, child)| {
+                let mut child_path = path.clone();
+                child_path.push(i);
+                let mut child_chain = chain.clone();
+                child_chain.push(child);
+                (Some(child_path), child_chain)
+            })
+            .collect(),
+        (Axis::DescendantOrSelf, None) => {
+            let mut out = vec![(None, Vec::new())];
+            let root_chain = vec![root];
+            descendants(&Vec::new(), &root_chain, &mut out);
+            out.push((Some(Vec::new()), root_chain));
+            out
+        }
+        (Axis::DescendantOrSelf, Some(path)) => {
+            let mut out = Vec::new();
+            descendants(&path, &chain, &mut out);
+            out.push((Some(path), chain));
+            out
+        }
+    };
+    if step.axis == Axis::DescendantOrSelf {
+        return candidates;
+    }
+    candidates
+        .into_iter()
+        .filter(|(_, chain)| match chain.last() {
+            None => step.name.is_none(),
+            Some(element) => name_matches(element, &step.name),
+        })
+        .collect()
+}
+
+fn filter_predicates<'a>(
+    mut candidates: Vec<Node<'a>>,
+    step: &Step,
+) -> Vec<Node<'a>> {
+    for predicate in &step.predicates {
+        let last = candidates.len();
+        candidates = candidates
+            .into_iter()
+            .enumerate()
+            .filter(|(i, (_, chain))| {
+                chain
+                    .last()
+                    .is_some_and(|e| holds(predicate, e, i + 1, last))
+            })
+            .map(|(_, candidate)| candidate)
+            .collect();
+    }
+    candidates
+}
+
+fn text_of(element: &Element) -> String {
+    element
+        .children
+        .iter()
+        .filter_map(|c| match c {
+            XMLNode::Text(t) | XMLNode::CData(t) => Some(t.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn operand_values(element: &Element, operand: &Operand) -> Vec<String> {
+    match operand {
+        Operand::Attribute(name) => {
+            element.attributes.get(name).cloned().into_iter().collect()
+        }
+        Operand::Child(name) => child_elements(element)
+            .filter(|child| name_matches(child, &Some(name.clone())))
+            .map(text_of)
+            .collect(),
+        Operand::Text => vec![text_of(element)],
+    }
+}
+
+fn compare(actual: &str, op: CmpOp, expected: &str) -> bool {
+    if let (Ok(a), Ok(b)) =
+        (actual.trim().parse::<f64>(), expected.trim().parse::<f64>())
+    {
+        return match op {
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+            CmpOp::Lt => a < b,
+            CmpOp::Le => a <= b,
+            CmpOp::Gt => a > b,
+            CmpOp::Ge => a >= b,
+        };
+    }
+    match op {
+        CmpOp::Eq => actual == expected,
+        CmpOp::Ne => actual != expected,
+        // XPath 1.0 compares non-numeric strings as NaN, which is never
+        // ordered.
+        _ => false,
+    }
+}
+
+fn holds(
+    predicate: &Predicate,
+    element: &Element,
+    position: usize,
+    last: usize,
+) -> bool {
+    match predicate {
+        Predicate::Position(n) => position == *n,
+        Predicate::Last => position == last,
+        Predicate::Or(a, b) => {
+            holds(a, element, position, last)
+                || holds(b, element, position, last)
+        }
+        Predicate::And(a, b) => {
+            holds(a, element, position, last)
+                && holds(b, element, position, last)
+        }
+        Predicate::Exists(operand) => match operand {
+            Operand::Text => !text_of(element).is_empty(),
+            _ => !operand_values(element, operand).is_empty(),
+        },
+        Predicate::Compare(operand, op, expected) => {
+            operand_values(element, operand)
+                .iter()
+                .any(|actual| compare(actual, *op, expected))
+        }
+    }
+}
+
+/// Where `xml insert` places the new element relative to each match.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    /// As the first child
+    First,
+    /// As the last child
+    Last,
+    /// As the preceding sibling
+    Before,
+    /// As the following sibling
+    After,
+}
+
+/// Returns the string value of a selection of `element`: the concatenated
+/// text for elements and text nodes and the value for attributes.
+pub fn string_value(element: &Element, selection: &Selection) -> String {
+    match selection {
+        Selection::Element(_) | Selection::Text(_) => text_of(element),
+        Selection::Attribute(_, name) => {
+            element.attributes.get(name).cloned().unwrap_or_default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xml_safe::{self, XmlLimits};
+
+    const LIBRARY: &str = r#"<lib xmlns:x="urn:x">
+        <shelf id="s1">
+            <book id="b1" lang="en"><title>Dune</title><price>9</price></book>
+            <book id="b2"><title>Emma</title><price>12.5</price></book>
+            <x:note>hi</x:note>
+        </shelf>
+        <shelf id="s2">
+            <book id="b3" lang="fr"><title>Nana</title><price>7</price></book>
+        </shelf>
+    </lib>"#;
+
+    fn parse_xml(source: &str) -> Element {
+        xml_safe::parse(source.as_bytes(), &XmlLimits::default()).unwrap()
+    }
+
+    fn select(expr: &str) -> Vec<Selection> {
+        let root = parse_xml(LIBRARY);
+        XPath::parse(expr)
+            .unwrap()
+            .select(&root)
+            .into_iter()
+            .map(|(selection, _)| selection)
+            .collect()
+    }
+
+    fn elements(paths: &[&[usize]]) -> Vec<Selection> {
+        paths
+            .iter()
+            .map(|path| Selection::Element(path.to_vec()))
+            .collect()
+    }
+
+    fn values(expr: &str) -> Vec<String> {
+        let root = parse_xml(LIBRARY);
+        XPath::parse(expr)
+            .unwrap()
+            .select(&root)
+            .into_iter()
+            .map(|(selection, element)| string_value(element, &selection))
+            .collect()
+    }
+
+    #[test]
+    fn absolute_and_descendant_paths() {
+        assert_eq!(select("/lib/shelf"), elements(&[&[0], &[1]]));
+        assert_eq!(select("/shelf"), [], "the root is named lib");
+        assert_eq!(
+            select("/lib/shelf/book"),
+            elements(&[&[0, 0], &[0, 1], &[1, 0]])
+        );
+        assert_eq!(select("//book"), select("/lib/shelf/book"));
+        assert_eq!(
+            select("//shelf//price"),
+            elements(&[&[0, 0, 1], &[0, 1, 1], &[1, 0, 1]])
+        );
+        assert_eq!(select("//book/*").len(), 6);
+        assert_eq!(select("/lib/shelf/."), select("/lib/shelf"));
+        assert_eq!(select("lib/shelf/book"), select("/lib/shelf/book"));
+    }
+
+    #[test]
+    fn parents_are_selected_once_in_document_order() {
+        assert_eq!(select("//title/.."), select("//book"));
+        assert_eq!(select("//book/.."), elements(&[&[0], &[1]]));
+        assert_eq!(select("//price/../../.."), elements(&[&[]]));
+        assert_eq!(
+            select("/lib/.."),
+            [],
+            "the document node is not an element"
+        );
+    }
+
+    #[test]
+    fn position_predicates_count_per_parent() {
+        assert_eq!(select("//book[1]"), elements(&[&[0, 0], &[1, 0]]));
+        assert_eq!(select("//book[last()]"), elements(&[&[0, 1], &[1, 0]]));
+        assert_eq!(select("/lib/shelf[2]/book"), elements(&[&[1, 0]]));
+        assert_eq!(select("//book[3]"), []);
+    }
+
+    #[test]
+    fn value_predicates() {
+        assert_eq!(select("//book[@lang]"), elements(&[&[0, 0], &[1, 0]]));
+        assert_eq!(select("//book[@lang='fr']"), elements(&[&[1, 0]]));
+        assert_eq!(select("//book[@id!='b1']"), elements(&[&[0, 1], &[1, 0]]));
+        assert_eq!(select("//book[price>8]"), elements(&[&[0, 0], &[0, 1]]));
+        assert_eq!(select("//book[price>='12.50']"), elements(&[&[0, 1]]));
+        assert_eq!(
+            select("//book[price<8 or @id='b2']"),
+            elements(&[&[0, 1], &[1, 0]])
+        );
+        assert_eq!(
+            select("//book[title='Emma' and price='12.5']"),
+            elements(&[&[0, 1]])
+        );
+        assert_eq!(values("//title[.='Dune']"), ["Dune"]);
+        assert_eq!(values("//title[text()='Nana']"), ["Nana"]);
+        assert_eq!(select("//title[.>'A']"), [], "strings are not ordered");
+    }
+
+    #[test]
+    fn attribute_and_text_targets() {
+        assert_eq!(values("//book/@id"), ["b1", "b2", "b3"]);
+        assert_eq!(
+            select("//book[1]/@*"),
+            [
+                Selection::Attribute(vec![0, 0], "id".into()),
+                Selection::Attribute(vec![0, 0], "lang".into()),
+                Selection::Attribute(vec![1, 0], "id".into()),
+                Selection::Attribute(vec![1, 0], "lang".into()),
+            ]
+        );
+        assert_eq!(
+            select("//book[2]/title/text()"),
+            [Selection::Text(vec![0, 1, 0])]
+        );
+        assert_eq!(select("/lib/text()"), [], "no text directly in lib");
+    }
+
+    #[test]
+    fn prefixed_names() {
+        assert_eq!(select("//x:note"), elements(&[&[0, 2]]));
+        assert_eq!(select("//note"), elements(&[&[0, 2]]));
+        assert_eq!(select("//y:note"), []);
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for expr in ["//book[", "/lib/", "//book]", "//book[@id=]", "@"] {
+            assert!(XPath::parse(expr).is_err(), "{expr} should not parse");
+        }
+    }
+
+    #[test]
+    fn many_siblings() {
+        let source = format!("<r>{}</r>", "<a><b/></a>".repeat(20_000));
+        let root = parse_xml(&source);
+        let count =
+            |expr: &str| XPath::parse(expr).unwrap().select(&root).len();
+        assert_eq!(count("//a"), 20_000);
+        assert_eq!(count("//b/.."), 20_000);
+        assert_eq!(count("/r/a/.."), 1);
+        assert_eq!(count("//a[last()]/b"), 1);
+    }
+}
@@ -0,0 +1,989 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::LazyLock;
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use xmltree::{Element, XMLNode};
+
+use crate::xml_safe::{self, XmlLimits};
+
+const XSD_NS: &str = "http://www.w3.org/2001/XMLSchema";
+
+/// Only the first errors are kept; validation still visits every element.
+pub const MAX_REPORTED_ERRORS: usize = 100;
+
+/// Attributes in this namespace (`xsi:schemaLocation` and friends) may
+/// appear on any element.
+const XSI_NS: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// A schema in the supported XSD subset: global and local element
+/// declarations, named and anonymous complex and simple types, `sequence`
+/// and `choice` with `minOccurs`/`maxOccurs`, attributes with `use`,
+/// `fixed` and simple types, `simpleContent` extensions, and restrictions
+/// with pattern, enumeration, length and range facets. Element names are
+/// matched by local name and attributes are expected to be unqualified.
+/// Anything else in the schema is rejected when it is loaded.
+#[derive(Debug, Default)]
+pub struct Schema {
+    elements: HashMap<String, ElementDecl>,
+    complex_types: HashMap<String, ComplexType>,
+    simple_types: HashMap<String, SimpleType>,
+}
+
+#[derive(Debug)]
+struct ElementDecl {
+    name: String,
+    ty: TypeRef,
+}
+
+#[derive(Debug)]
+enum TypeRef {
+    Any,
+    Named(String),
+    /// The type of the global element with this name (`ref="..."`).
+    OfElement(String),
+    Complex(Box<ComplexType>),
+    Simple(SimpleRef),
+}
+
+#[derive(Debug)]
+enum SimpleRef {
+    Builtin(Builtin),
+    Named(String),
+    Inline(Box<SimpleType>),
+}
+
+#[derive(Debug, Default)]
+struct ComplexType {
+    mixed: bool,
+    content: Content,
+    attributes: Vec<AttributeDecl>,
+    any_attribute: bool,
+}
+
+#[derive(Debug, Default)]
+enum Content {
+    #[default]
+    Empty,
+    Particle(Particle),
+    Simple(SimpleRef),
+}
+
+#[derive(Debug)]
+struct Particle {
+    term: Term,
+    min: usize,
+    max: Option<usize>,
+}
+
+#[derive(Debug)]
+enum Term {
+    Element(ElementDecl),
+    Sequence(Vec<Particle>),
+    Choice(Vec<Particle>),
+}
+
+#[derive(Debug)]
+struct AttributeDecl {
+    name: String,
+    ty: SimpleRef,
+    required: bool,
+    fixed: Option<String>,
+}
+
+#[derive(Debug)]
+struct SimpleType {
+    base: SimpleRef,
+    facets: Facets,
+}
+
+#[derive(Debug, Default)]
+struct Facets {
+    patterns: Vec<Regex>,
+    enumeration: Vec<String>,
+    length: Option<usize>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    min_inclusive: Option<String>,
+    max_inclusive: Option<String>,
+    min_exclusive: Option<String>,
+    max_exclusive: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Builtin {
+    AnySimple,
+    String,
+    NormalizedString,
+    Token,
+    Boolean,
+    Decimal,
+    Integer {
+        min: Option<i128>,
+        max: Option<i128>,
+    },
+    Float,
+    Date,
+    DateTime,
+    Time,
+    AnyUri,
+    Name,
+    NcName,
+}
+
+fn builtin(name: &str) -> Option<Builtin> {
+    let integer =
+        |min: Option<i128>, max: Option<i128>| Builtin::Integer { min, max };
+    Some(match name {
+        "anySimpleType" => Builtin::AnySimple,
+        "string" => Builtin::String,
+        "normalizedString" => Builtin::NormalizedString,
+        "token" | "language" | "NMTOKEN" => Builtin::Token,
+        "boolean" => Builtin::Boolean,
+        "decimal" => Builtin::Decimal,
+        "integer" => integer(None, None),
+        "long" => integer(Some(i64::MIN.into()), Some(i64::MAX.into())),
+        "int" => integer(Some(i32::MIN.into()), Some(i32::MAX.into())),
+        "short" => integer(Some(i16::MIN.into()), Some(i16::MAX.into())),
+        "byte" => integer(Some(i8::MIN.into()), Some(i8::MAX.into())),
+        "nonNegativeInteger" => integer(Some(0), None),
+        "positiveInteger" => integer(Some(1), None),
+        "nonPositiveInteger" => integer(None, Some(0)),
+        "negativeInteger" => integer(None, Some(-1)),
+        "unsignedLong" => integer(Some(0), Some(u64::MAX.into())),
+        "unsignedInt" => integer(Some(0), Some(u32::MAX.into())),
+        "unsignedShort" => integer(Some(0), Some(u16::MAX.into())),
+        "unsignedByte" => integer(Some(0), Some(u8::MAX.into())),
+        "float" | "double" => Builtin::Float,
+        "date" => Builtin::Date,
+        "dateTime" => Builtin::DateTime,
+        "time" => Builtin::Time,
+        "anyURI" => Builtin::AnyUri,
+        "Name" => Builtin::Name,
+        "NCName" | "ID" | "IDREF" | "ENTITY" => Builtin::NcName,
+        _ => return None,
+    })
+}
+
+static DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^-?\d{4,}-\d{2}-\d{2}(Z|[+-]\d{2}:\d{2})?$").unwrap()
+});
+static TIME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})?$").unwrap()
+});
+static DATE_TIME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^-?\d{4,}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})?$",
+    )
+    .unwrap()
+});
+static DECIMAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[+-]?(\d+(\.\d*)?|\.\d+)$").unwrap());
+
+fn is_ncname(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '.' | '_'))
+}
+
+impl Builtin {
+    /// Applies the type's whitespace handling.
+    fn normalize(self, raw: &str) -> String {
+        match self {
+            Builtin::String | Builtin::AnySimple => raw.to_string(),
+            Builtin::NormalizedString => raw.replace(['\t', '\r', '\n'], " "),
+            _ => raw.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    fn describe(self) -> String {
+        match self {
+            Builtin::Boolean => "a boolean".into(),
+            Builtin::Decimal => "a decimal number".into(),
+            Builtin::Integer { min, max } => match (min, max) {
+                (Some(min), Some(max)) => {
+                    format!("an integer in {min}..={max}")
+                }
+                (Some(min), None) => format!("an integer >= {min}"),
+                (None, Some(max)) => format!("an integer <= {max}"),
+                (None, None) => "an integer".into(),
+            },
+            Builtin::Float => "a floating-point number".into(),
+            Builtin::Date => "a date (YYYY-MM-DD)".into(),
+            Builtin::DateTime => "a date and time (YYYY-MM-DDThh:mm:ss)".into(),
+            Builtin::Time => "a time (hh:mm:ss)".into(),
+            Builtin::AnyUri => "a URI".into(),
+            Builtin::Name => "an XML name".into(),
+            Builtin::NcName => "an XML name without a colon".into(),
+            _ => "a string".into(),
+        }
+    }
+
+    fn check(self, value: &str) -> bool {
+        match self {
+            Builtin::AnySimple
+            | Builtin::String
+            | Builtin::NormalizedString
+            | Builtin::Token => true,
+            Builtin::Boolean => matches!(value, "true" | "false" | "1" | "0"),
+            Builtin::Decimal => DECIMAL.is_match(value),
+            Builtin::Integer { min, max } => {
+                value.parse::<i128>().is_ok_and(|n| {
+                    min.is_none_or(|min| n >= min)
+                        && max.is_none_or(|max| n <= max)
+                })
+            }
+            // Rust also accepts spellings like "inf" and "nan", XSD only
+            // the three special values below.
+            Builtin::Float => {
+                matches!(value, "INF" | "-INF" | "NaN")
+                    || value.parse::<f64>().is_ok()
+                        && !value.contains(['i', 'I', 'n', 'N'])
+            }
+            Builtin::Date => DATE.is_match(value),
+            Builtin::DateTime => DATE_TIME.is_match(value),
+            Builtin::Time => TIME.is_match(value),
+            Builtin::AnyUri => !value.contains(char::is_whitespace),
+            Builtin::Name => {
+                value.split(':').all(is_ncname) && !value.is_empty()
+            }
+            Builtin::NcName => is_ncname(value),
+        }
+    }
+}
+
+/// Translates an XSD regular expression, which is implicitly anchored and
+/// has a few escapes of its own, into the `regex` dialect.
+fn compile_pattern(pattern: &str) -> Result<Regex> {
+    if pattern.contains("-[") {
+        bail!("Character class subtraction is not supported in '{pattern}'");
+    }
+    let translated = pattern
+        .replace(r"\i", "[_:A-Za-z]")
+        .replace(r"\I", "[^_:A-Za-z]")
+        .replace(r"\c", "[-._:A-Za-z0-9]")
+        .replace(r"\C", "[^-._:A-Za-z0-9]");
+    Regex::new(&format!("^(?:{translated})$"))
+        .with_context(|| format!("Invalid pattern '{pattern}'"))
+}
+
+fn is_xsd(element: &Element, local: &str) -> bool {
+    element.name == local && element.namespace.as_deref() == Some(XSD_NS)
+}
+
+fn xsd_children(element: &Element) -> impl Iterator<Item = &Element> {
+    element
+        .children
+        .iter()
+        .filter_map(XMLNode::as_element)
+        .filter(|child| !is_xsd(child, "annotation"))
+}
+
+fn required_attr<'a>(element: &'a Element, name: &str) -> Result<&'a str> {
+    element
+        .attributes
+        .get(name)
+        .map(String::as_str)
+        .ok_or_else(|| {
+            anyhow!("xs:{} is missing the '{name}' attribute", element.name)
+        })
+}
+
+fn occurs(element: &Element) -> Result<(usize, Option<usize>)> {
+    let min = match element.attributes.get("minOccurs") {
+        Some(value) => value
+            .parse()
+            .with_context(|| format!("Invalid minOccurs '{value}'"))?,
+        None => 1,
+    };
+    let max = match element.attributes.get("maxOccurs").map(String::as_str) {
+        Some("unbounded") => None,
+        Some(value) => Some(
+            value
+                .parse()
+                .with_context(|| format!("Invalid maxOccurs '{value}'"))?,
+        ),
+        None => Some(1),
+    };
+    if max.is_some_and(|max| max < min) {
+        bail!("maxOccurs is smaller than minOccurs on xs:{}", element.name);
+    }
+    Ok((min, max))
+}
+
+/// Resolves a `type`/`base` QName: names in the XSD namespace are built-in
+/// types, everything else refers to a type defined in the schema.
+fn type_name(element: &Element, qname: &str) -> Result<SimpleRef> {
+    let (prefix, local) = qname.split_once(':').unwrap_or(("", qname));
+    let namespace = element
+        .namespaces
+        .as_ref()
+        .and_then(|namespaces| namespaces.get(prefix));
+    if namespace == Some(XSD_NS) {
+        return builtin(local)
+            .map(SimpleRef::Builtin)
+            .ok_or_else(|| anyhow!("Unsupported built-in type '{qname}'"));
+    }
+    Ok(SimpleRef::Named(local.to_string()))
+}
+
+fn unsupported(element: &Element) -> anyhow::Error {
+    anyhow!("Unsupported XSD construct xs:{}", element.name)
+}
+
+impl Schema {
+    pub fn load(path: &Path, limits: &XmlLimits) -> Result<Schema> {
+        let root = xml_safe::parse_file(path, limits)?;
+        Schema::from_element(&root)
+            .with_context(|| format!("Loading schema {}", path.display()))
+    }
+
+    fn from_element(root: &Element) -> Result<Schema> {
+        if !is_xsd(root, "schema") {
+            bail!("Root element is not xs:schema");
+        }
+        let mut schema = Schema::default();
+        for child in xsd_children(root) {
+            match child.name.as_str() {
+                "element" => {
+                    let decl = parse_element(child)?;
+                    schema.elements.insert(decl.name.clone(), decl);
+                }
+                "complexType" => {
+                    let name = required_attr(child, "name")?.to_string();
+                    schema.complex_types.insert(name, parse_complex(child)?);
+                }
+                "simpleType" => {
+                    let name = required_attr(child, "name")?.to_string();
+                    schema.simple_types.insert(name, parse_simple(child)?);
+                }
+                _ => return Err(unsupported(child)),
+            }
+        }
+        Ok(schema)
+    }
+}
+
+fn parse_element(element: &Element) -> Result<ElementDecl> {
+    if let Some(target) = element.attributes.get("ref") {
+        let name = target.rsplit(':').next().unwrap_or(target).to_string();
+        return Ok(ElementDecl {
+            ty: TypeRef::OfElement(name.clone()),
+            name,
+        });
+    }
+    let name = required_attr(element, "name")?.to_string();
+    let ty = match element.attributes.get("type") {
+        Some(qname) => match type_name(element, qname)? {
+            SimpleRef::Named(name) => TypeRef::Named(name),
+            simple => TypeRef::Simple(simple),
+        },
+        None => match xsd_children(element).next() {
+            Some(child) if is_xsd(child, "complexType") => {
+                TypeRef::Complex(Box::new(parse_complex(child)?))
+            }
+            Some(child) if is_xsd(child, "simpleType") => TypeRef::Simple(
+                SimpleRef::Inline(Box::new(parse_simple(child)?)),
+            ),
+            Some(child) => return Err(unsupported(child)),
+            None => TypeRef::Any,
+        },
+    };
+    Ok(ElementDecl { name, ty })
+}
+
+fn parse_particle(element: &Element) -> Result<Particle> {
+    let (min, max) = occurs(element)?;
+    let term = match element.name.as_str() {
+        "element" => Term::Element(parse_element(element)?),
+        "sequence" | "choice" => {
+            let items = xsd_children(element)
+                .map(parse_particle)
+                .collect::<Result<Vec<_>>>()?;
+            if element.name == "sequence" {
+                Term::Sequence(items)
+            } else {
+                Term::Choice(items)
+            }
+        }
+        _ => return Err(unsupported(element)),
+    };
+    Ok(Particle { term, min, max })
+}
+
+fn parse_attribute(element: &Element) -> Result<AttributeDecl> {
+    let ty = match element.attributes.get("type") {
+        Some(qname) => type_name(element, qname)?,
+        None => match xsd_children(element).next() {
+            Some(child) if is_xsd(child, "simpleType") => {
+                SimpleRef::Inline(Box::new(parse_simple(child)?))
+            }
+            Some(child) => return Err(unsupported(child)),
+            None => SimpleRef::Builtin(Builtin::AnySimple),
+        },
+    };
+    Ok(AttributeDecl {
+        name: required_attr(element, "name")?.to_string(),
+        ty,
+        required: element.attributes.get("use").map(String::as_str)
+            == Some("required"),
+        fixed: element.attributes.get("fixed").cloned(),
+    })
+}
+
+fn parse_complex(element: &Element) -> Result<ComplexType> {
+    let mut complex = ComplexType {
+        mixed: element.attributes.get("mixed").map(String::as_str)
+            == Some("true"),
+        ..ComplexType::default()
+    };
+    for child in xsd_children(element) {
+        match child.name.as_str() {
+            "sequence" | "choice" => {
+                complex.content = Content::Particle(parse_particle(child)?)
+            }
+            "attribute" => complex.attributes.push(parse_attribute(child)?),
+            "anyAttribute" => complex.any_attribute = true,
+            "simpleContent" => {
+                let extension = xsd_children(child)
+                    .next()
+                    .filter(|e| is_xsd(e, "extension"))
+                    .ok_or_else(|| {
+                        anyhow!("Only xs:extension is supported in xs:simpleContent")
+                    })?;
+                complex.content = Content::Simple(type_name(
+                    extension,
+                    required_attr(extension, "base")?,
+                )?);
+                for attribute in xsd_children(extension) {
+                    match attribute.name.as_str() {
+                        "attribute" => {
+                            complex.attributes.push(parse_attribute(attribute)?)
+                        }
+                        "anyAttribute" => complex.any_attribute = true,
+                        _ => return Err(unsupported(attribute)),
+                    }
+                }
+            }
+            _ => return Err(unsupported(child)),
+        }
+    }
+    Ok(complex)
+}
+
+fn parse_simple(element: &Element) -> Result<SimpleType> {
+    let restriction = xsd_children(element)
+        .next()
+        .filter(|e| is_xsd(e, "restriction"))
+        .ok_or_else(|| {
+            anyhow!("Only xs:restriction is supported in xs:simpleType")
+        })?;
+    let base = match restriction.attributes.get("base") {
+        Some(qname) => type_name(restriction, qname)?,
+        None => match xsd_children(restriction)
+            .find(|e| is_xsd(e, "simpleType"))
+        {
+            Some(inline) => SimpleRef::Inline(Box::new(parse_simple(inline)?)),
+            None => bail!("xs:restriction needs a base type"),
+        },
+    };
+    let mut facets = Facets::default();
+    for facet in xsd_children(restriction) {
+        if is_xsd(facet, "simpleType") {
+            continue;
+        }
+        let value = required_attr(facet, "value")?;
+        let number = || {
+            value
+                .parse::<usize>()
+                .with_context(|| format!("Invalid {} '{value}'", facet.name))
+        };
+        match facet.name.as_str() {
+            "pattern" => facets.patterns.push(compile_pattern(value)?),
+            "enumeration" => facets.enumeration.push(value.to_string()),
+            "length" => facets.length = Some(number()?),
+            "minLength" => facets.min_length = Some(number()?),
+            "maxLength" => facets.max_length = Some(number()?),
+            "minInclusive" => facets.min_inclusive = Some(value.to_string()),
+            "maxInclusive" => facets.max_inclusive = Some(value.to_string()),
+            "minExclusive" => facets.min_exclusive = Some(value.to_string()),
+            "maxExclusive" => facets.max_exclusive = Some(value.to_string()),
+            "whiteSpace" => {}
+            _ => return Err(unsupported(facet)),
+        }
+    }
+    Ok(SimpleType { base, facets })
+}
+
+/// Orders two values numerically when both are numbers, and as strings
+/// otherwise (which is right for ISO 8601 dates in the same zone).
+fn compare_values(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+impl Facets {
+    fn check(&self, value: &str) -> Result<(), String> {
+        use std::cmp::Ordering::{Greater, Less};
+        if !self.enumeration.is_empty()
+            && !self.enumeration.iter().any(|allowed| allowed == value)
+        {
+            return Err(format!(
+                "'{value}' is not one of: {}",
+                self.enumeration.join(", ")
+            ));
+        }
+        if !self.patterns.is_empty()
+            && !self.patterns.iter().any(|pattern| pattern.is_match(value))
+        {
+            return Err(format!(
+                "'{value}' does not match the required pattern"
+            ));
+        }
+        let length = value.chars().count();
+        if self.length.is_some_and(|n| length != n)
+            || self.min_length.is_some_and(|n| length < n)
+            || self.max_length.is_some_and(|n| length > n)
+        {
+            return Err(format!("'{value}' has the wrong length ({length})"));
+        }
+        let out_of_range =
+            |bound: &Option<String>, bad: &[std::cmp::Ordering]| {
+                bound.as_deref().is_some_and(|bound| {
+                    bad.contains(&compare_values(value, bound))
+                })
+            };
+        if out_of_range(&self.min_inclusive, &[Less])
+            || out_of_range(&self.max_inclusive, &[Greater])
+            || out_of_range(
+                &self.min_exclusive,
+                &[Less, std::cmp::Ordering::Equal],
+            )
+            || out_of_range(
+                &self.max_exclusive,
+                &[Greater, std::cmp::Ordering::Equal],
+            )
+        {
+            return Err(format!("'{value}' is out of range"));
+        }
+        Ok(())
+    }
+}
+
+/// Remembers how far the content model got through the children and which
+/// element names it would have accepted there, for error messages.
+#[derive(Default)]
+struct Tracker<'a> {
+    furthest: usize,
+    expected: BTreeSet<&'a str>,
+}
+
+impl<'a> Tracker<'a> {
+    fn reach(&mut self, position: usize) {
+        if position > self.furthest {
+            self.furthest = position;
+            self.expected.clear();
+        }
+    }
+
+    fn expect(&mut self, position: usize, name: &'a str) {
+        self.reach(position);
+        if position == self.furthest {
+            self.expected.insert(name);
+        }
+    }
+}
+
+/// Returns every child position at which a match of `particle` that starts
+/// at one of `starts` can end, like stepping an NFA over the children.
+fn match_particle<'a>(
+    particle: &'a Particle,
+    children: &[&Element],
+    starts: &BTreeSet<usize>,
+    tracker: &mut Tracker<'a>,
+) -> BTreeSet<usize> {
+    let mut reached = if particle.min == 0 {
+        starts.clone()
+    } else {
+        BTreeSet::new()
+    };
+    let mut current = starts.clone();
+    let mut count = 0;
+    while !current.is_empty() && particle.max.is_none_or(|max| count < max) {
+        count += 1;
+        let mut next = BTreeSet::new();
+        for &start in &current {
+            next.extend(match_term(&particle.term, children, start, tracker));
+        }
+        if count >= particle.min {
+            // Positions seen before were already expanded, so repeating
+            // further cannot reach anything new.
+            if next.is_subset(&reached) {
+                break;
+            }
+            reached.extend(&next);
+        }
+        current = next;
+    }
+    reached
+}
+
+fn match_term<'a>(
+    term: &'a Term,
+    children: &[&Element],
+    start: usize,
+    tracker: &mut Tracker<'a>,
+) -> BTreeSet<usize> {
+    match term {
+        Term::Element(decl) => {
+            tracker.expect(start, &decl.name);
+            if children.get(start).is_some_and(|c| c.name == decl.name) {
+                tracker.reach(start + 1);
+                BTreeSet::from([start + 1])
+            } else {
+                BTreeSet::new()
+            }
+        }
+        Term::Sequence(items) => {
+            let mut positions = BTreeSet::from([start]);
+            for item in items {
+                positions = match_particle(item, children, &positions, tracker);
+                if positions.is_empty() {
+                    break;
+                }
+            }
+            positions
+        }
+        Term::Choice(items) => {
+            let starts = BTreeSet::from([start]);
+            items
+                .iter()
+                .flat_map(|item| {
+                    match_particle(item, children, &starts, tracker)
+                })
+                .collect()
+        }
+    }
+}
+
+fn collect_decls<'a>(
+    particle: &'a Particle,
+    out: &mut HashMap<&'a str, &'a ElementDecl>,
+) {
+    match &particle.term {
+        Term::Element(decl) => {
+            out.entry(&decl.name).or_insert(decl);
+        }
+        Term::Sequence(items) | Term::Choice(items) => {
+            for item in items {
+                collect_decls(item, out);
+            }
+        }
+    }
+}
+
+fn text_of(element: &Element) -> String {
+    element
+        .children
+        .iter()
+        .filter_map(|child| match child {
+            XMLNode::Text(text) | XMLNode::CData(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+enum Resolved<'a> {
+    Any,
+    Complex(&'a ComplexType),
+    Simple(&'a SimpleRef),
+    SimpleType(&'a SimpleType),
+}
+
+/// Guards against simple types that restrict themselves in a cycle.
+const MAX_DERIVATION_DEPTH: usize = 32;
+
+struct Validator<'a> {
+    schema: &'a Schema,
+    errors: Vec<String>,
+    total: usize,
+}
+
+impl<'a> Validator<'a> {
+    fn report(&mut self, path: &str, message: impl Into<String>) {
+        self.total += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(format!("{path}: {}", message.into()));
+        }
+    }
+
+    fn resolve(&self, ty: &'a TypeRef) -> Result<Resolved<'a>, String> {
+        match ty {
+            TypeRef::Any => Ok(Resolved::Any),
+            TypeRef::Complex(complex) => Ok(Resolved::Complex(complex)),
+            TypeRef::Simple(simple) => Ok(Resolved::Simple(simple)),
+            TypeRef::Named(name) => {
+                if let Some(complex) = self.schema.complex_types.get(name) {
+                    Ok(Resolved::Complex(complex))
+                } else if let Some(simple) = self.schema.simple_types.get(name)
+                {
+                    Ok(Resolved::SimpleType(simple))
+                } else {
+                    Err(format!("schema has no type named '{name}'"))
+                }
+            }
+            TypeRef::OfElement(name) => match self.schema.elements.get(name) {
+                Some(decl) => self.resolve(&decl.ty),
+                None => Err(format!("schema has no global element '{name}'")),
+            },
+        }
+    }
+
+    /// Checks a value against a simple type and returns it with the type's
+    /// whitespace handling applied.
+    fn check_simple(
+        &self,
+        simple: &SimpleRef,
+        raw: &str,
+        depth: usize,
+    ) -> Result<String, String> {
+        if depth > MAX_DERIVATION_DEPTH {
+            return Err("simple type derivation is too deep".to_string());
+        }
+        match simple {
+            SimpleRef::Builtin(builtin) => {
+                let value = builtin.normalize(raw);
+                if builtin.check(&value) {
+                    Ok(value)
+                } else {
+                    Err(format!("'{value}' is not {}", builtin.describe()))
+                }
+            }
+            SimpleRef::Named(name) => {
+                match self.schema.simple_types.get(name) {
+                    Some(simple) => self.check_type(simple, raw, depth),
+                    None => {
+                        Err(format!("schema has no simple type named '{name}'"))
+                    }
+                }
+            }
+            SimpleRef::Inline(simple) => self.check_type(simple, raw, depth),
+        }
+    }
+
+    fn check_type(
+        &self,
+        simple: &SimpleType,
+        raw: &str,
+        depth: usize,
+    ) -> Result<String, String> {
+        let value = self.check_simple(&simple.base, raw, depth + 1)?;
+        simple.facets.check(&value)?;
+        Ok(value)
+    }
+
+    fn check_attributes(
+        &mut self,
+        element: &Element,
+        complex: Option<&ComplexType>,
+        path: &str,
+    ) {
+        let declared = complex.map_or(&[][..], |c| &c.attributes[..]);
+        for decl in declared {
+            match element.attributes.get(&decl.name) {
+                None if decl.required => self.report(
+                    path,
+                    format!("missing required attribute '{}'", decl.name),
+                ),
+                None => {}
+                Some(value) => {
+                    if let Err(message) = self.check_simple(&decl.ty, value, 0)
+                    {
+                        self.report(
+                            path,
+                            format!("attribute '{}': {message}", decl.name),
+                        );
+                    } else if let Some(fixed) =
+                        decl.fixed.as_ref().filter(|fixed| *fixed != value)
+                    {
+                        self.report(
+                            path,
+                            format!(
+                                "attribute '{}' must be '{fixed}'",
+                                decl.name
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        if complex.is_some_and(|c| c.any_attribute) {
+            return;
+        }
+        let mut names: Vec<&String> = element.attributes.keys().collect();
+        names.sort();
+        for name in names {
+            let is_instance =
+                name.split_once(':').is_some_and(|(prefix, _)| {
+                    element
+                        .namespaces
+                        .as_ref()
+                        .and_then(|namespaces| namespaces.get(prefix))
+                        == Some(XSI_NS)
+                });
+            if !declared.iter().any(|d| &d.name == name) && !is_instance {
+                self.report(path, format!("attribute '{name}' is not allowed"));
+            }
+        }
+    }
+
+    fn check_no_elements(&mut self, element: &Element, path: &str) {
+        if let Some(child) =
+            element.children.iter().find_map(XMLNode::as_element)
+        {
+            self.report(
+                path,
+                format!("child element <{}> is not allowed here", child.name),
+            );
+        }
+    }
+
+    fn validate(
+        &mut self,
+        element: &Element,
+        decl: &'a ElementDecl,
+        path: &str,
+    ) {
+        let resolved = match self.resolve(&decl.ty) {
+            Ok(resolved) => resolved,
+            Err(message) => return self.report(path, message),
+        };
+        let complex = match resolved {
+            Resolved::Any => return,
+            Resolved::Simple(_) | Resolved::SimpleType(_) => {
+                self.check_attributes(element, None, path);
+                self.check_no_elements(element, path);
+                let text = text_of(element);
+                let result = match resolved {
+                    Resolved::SimpleType(simple) => {
+                        self.check_type(simple, &text, 0)
+                    }
+                    Resolved::Simple(simple) => {
+                        self.check_simple(simple, &text, 0)
+                    }
+                    _ => unreachable!(),
+                };
+                if let Err(message) = result {
+                    self.report(path, message);
+                }
+                return;
+            }
+            Resolved::Complex(complex) => complex,
+        };
+
+        self.check_attributes(element, Some(complex), path);
+        let text = text_of(element);
+        let particle = match &complex.content {
+            Content::Simple(simple) => {
+                self.check_no_elements(element, path);
+                if let Err(message) = self.check_simple(simple, &text, 0) {
+                    self.report(path, message);
+                }
+                return;
+            }
+            Content::Empty => None,
+            Content::Particle(particle) => Some(particle),
+        };
+        if !complex.mixed && !text.trim().is_empty() {
+            self.report(path, "text content is not allowed here");
+        }
+
+        let children: Vec<&Element> = element
+            .children
+            .iter()
+            .filter_map(XMLNode::as_element)
+            .collect();
+        let mut decls = HashMap::new();
+        let mut tracker = Tracker::default();
+        let ends = match particle {
+            Some(particle) => {
+                collect_decls(particle, &mut decls);
+                match_particle(
+                    particle,
+                    &children,
+                    &BTreeSet::from([0]),
+                    &mut tracker,
+                )
+            }
+            None => BTreeSet::from([0]),
+        };
+        if !ends.contains(&children.len()) {
+            let expected = if tracker.expected.is_empty() {
+                "no more elements".to_string()
+            } else {
+                tracker
+                    .expected
+                    .iter()
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let message = match children.get(tracker.furthest) {
+                Some(child) => format!(
+                    "unexpected <{}> at child element {}, expected {expected}",
+                    child.name,
+                    tracker.furthest + 1
+                ),
+                None => format!("content ends too early, expected {expected}"),
+            };
+            self.report(path, message);
+        }
+
+        for (i, child) in children.iter().enumerate() {
+            let same_name = children.iter().filter(|c| c.name == child.name);
+            let child_path = if same_name.clone().count() > 1 {
+                let index = children[..i]
+                    .iter()
+                    .filter(|c| c.name == child.name)
+                    .count();
+                format!("{path}/{}[{}]", child.name, index + 1)
+            } else {
+                format!("{path}/{}", child.name)
+            };
+            // Undeclared children were already reported by the content model.
+            if let Some(decl) = decls.get(child.name.as_str()) {
+                self.validate(child, decl, &child_path);
+            }
+        }
+    }
+}
+
+/// Outcome of validating a document; `total` counts every error while
+/// `errors` keeps the first [`MAX_REPORTED_ERRORS`].
+pub struct Report {
+    pub errors: Vec<String>,
+    pub total: usize,
+}
+
+impl Schema {
+    pub fn validate(&self, root: &Element) -> Report {
+        let mut validator = Validator {
+            schema: self,
+            errors: Vec::new(),
+            total: 0,
+        };
+        let path = format!("/{}", root.name);
+        match self.elements.get(&root.name) {
+            Some(decl) => validator.validate(root, decl, &path),
+            None => validator
+                .report(&path, "root element is not declared in the schema"),
+        }
+        Report {
+            errors: validator.errors,
+            total: validator.total,
+        }
+    }
+}